@@ -0,0 +1,250 @@
+use anyhow::{anyhow, Context, Result};
+use rand::random;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::scene::SCENE_SLOTS;
+
+/// File that the arrangement is loaded from, relative to the working directory.
+pub const ARRANGEMENT_FILE: &str = "arrangement.toml";
+
+/// Decides whether a jump is taken when a section finishes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JumpCondition {
+    Always,
+    /// Jump with the given chance between 0 and 1.
+    Probability {
+        chance: f32,
+    },
+    /// Jump only the first `count` times the section finishes, e.g. to repeat a verse/chorus block.
+    Times {
+        count: usize,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Jump {
+    /// Index of the section to jump to.
+    pub to: usize,
+    pub condition: JumpCondition,
+}
+
+/// A Section plays a stored scene for a number of bars, optionally repeated.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Section {
+    pub scene: usize,
+    pub bars: usize,
+    #[serde(default = "default_repeats")]
+    pub repeats: usize,
+    #[serde(default)]
+    pub jump: Option<Jump>,
+}
+
+fn default_repeats() -> usize {
+    1
+}
+
+/// An Arrangement chains scenes into a song.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Arrangement {
+    #[serde(default)]
+    pub looping: bool,
+    #[serde(default)]
+    pub sections: Vec<Section>,
+}
+
+/// Where playback currently is in the arrangement.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ArrangementPosition {
+    pub section: usize,
+    pub repeat: usize,
+    pub bar: usize,
+    step: usize,
+    jumps_taken: Vec<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArrangementEvent {
+    /// A new section started, playing the given scene.
+    Section(usize),
+    Finished,
+}
+
+impl Arrangement {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read arrangement from {}", path.display()))?;
+        let arrangement: Arrangement = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse arrangement from {}", path.display()))?;
+        arrangement.validate()?;
+        Ok(arrangement)
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (i, section) in self.sections.iter().enumerate() {
+            if section.scene >= SCENE_SLOTS {
+                return Err(anyhow!(
+                    "Section {i} refers to scene {} which does not exist",
+                    section.scene
+                ));
+            }
+            if section.bars == 0 || section.repeats == 0 {
+                return Err(anyhow!(
+                    "Section {i} must have at least one bar and one repeat"
+                ));
+            }
+            if let Some(jump) = &section.jump {
+                if jump.to >= self.sections.len() {
+                    return Err(anyhow!(
+                        "Section {i} jumps to section {} which does not exist",
+                        jump.to
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Start at the first section, returning the position and the scene to play.
+    pub fn start(&self) -> Option<(ArrangementPosition, usize)> {
+        let first = self.sections.first()?;
+        let position = ArrangementPosition {
+            jumps_taken: vec![0; self.sections.len()],
+            ..ArrangementPosition::default()
+        };
+        Some((position, first.scene))
+    }
+
+    /// Advance the position by one step, returning an event when a section starts or the
+    /// arrangement finishes.
+    pub fn advance(
+        &self,
+        position: &mut ArrangementPosition,
+        steps_per_bar: usize,
+    ) -> Option<ArrangementEvent> {
+        let section = self.sections.get(position.section)?;
+
+        position.step += 1;
+        if position.step < steps_per_bar {
+            return None;
+        }
+        position.step = 0;
+        position.bar += 1;
+        if position.bar < section.bars {
+            return None;
+        }
+        position.bar = 0;
+        position.repeat += 1;
+        if position.repeat < section.repeats {
+            return None;
+        }
+        position.repeat = 0;
+
+        let jump_to = section.jump.as_ref().and_then(|jump| {
+            let taken = &mut position.jumps_taken[position.section];
+            let should_jump = match jump.condition {
+                JumpCondition::Always => true,
+                JumpCondition::Probability { chance } => random::<f32>() < chance,
+                JumpCondition::Times { count } => *taken < count,
+            };
+            if should_jump {
+                *taken += 1;
+            }
+            should_jump.then_some(jump.to)
+        });
+
+        let mut next = jump_to.unwrap_or(position.section + 1);
+        if next >= self.sections.len() {
+            if !self.looping {
+                return Some(ArrangementEvent::Finished);
+            }
+            next = 0;
+            position.jumps_taken.fill(0);
+        }
+        position.section = next;
+
+        Some(ArrangementEvent::Section(self.sections[next].scene))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::STEPS_PER_BAR;
+
+    fn advance_bars(
+        arrangement: &Arrangement,
+        position: &mut ArrangementPosition,
+        bars: usize,
+    ) -> Vec<ArrangementEvent> {
+        (0..bars * STEPS_PER_BAR)
+            .filter_map(|_| arrangement.advance(position, STEPS_PER_BAR))
+            .collect()
+    }
+
+    #[test]
+    fn test_arrangement() {
+        let arrangement: Arrangement = toml::from_str(
+            r#"
+            [[sections]]
+            scene = 0
+            bars = 2
+            repeats = 2
+
+            [[sections]]
+            scene = 1
+            bars = 1
+            jump = { to = 0, condition = { type = "times", count = 1 } }
+
+            [[sections]]
+            scene = 2
+            bars = 1
+            "#,
+        )
+        .unwrap();
+        arrangement.validate().unwrap();
+
+        let (mut position, scene) = arrangement.start().unwrap();
+        assert_eq!(scene, 0);
+
+        // Section 0 plays for 2 bars, twice
+        assert!(advance_bars(&arrangement, &mut position, 3).is_empty());
+        assert_eq!(
+            advance_bars(&arrangement, &mut position, 1),
+            vec![ArrangementEvent::Section(1)]
+        );
+
+        // The jump back to section 0 is taken only once
+        assert_eq!(
+            advance_bars(&arrangement, &mut position, 1),
+            vec![ArrangementEvent::Section(0)]
+        );
+        assert_eq!(
+            advance_bars(&arrangement, &mut position, 5),
+            vec![ArrangementEvent::Section(1), ArrangementEvent::Section(2)]
+        );
+        assert_eq!(
+            advance_bars(&arrangement, &mut position, 1),
+            vec![ArrangementEvent::Finished]
+        );
+    }
+
+    #[test]
+    fn test_invalid_jump() {
+        let arrangement = Arrangement {
+            looping: false,
+            sections: vec![Section {
+                scene: 0,
+                bars: 1,
+                repeats: 1,
+                jump: Some(Jump {
+                    to: 1,
+                    condition: JumpCondition::Always,
+                }),
+            }],
+        };
+        assert!(arrangement.validate().is_err());
+    }
+}
@@ -1,6 +1,39 @@
 use crate::{
-    midi::state::MidiCommand,
-    sequencers::euclidean::gui::{Gui as EuclideanGui, Message as EuclideanGuiMessage},
+    history::Edit,
+    metronome::{ClickOutput, MetronomeConfig, METRONOME_FILE},
+    midi::{
+        learn::{MappingMode, Parameter, MIDI_MAP_FILE},
+        output::OutputPolicy,
+        ports::MidiPortStatus,
+        recording::{RecordMode, RecordTarget},
+        routing::{Route, Source},
+        state::MidiCommand,
+    },
+    note::Sequence,
+    scene::{SCENES_FILE, SCENE_SLOTS},
+    sequencers::{
+        arpeggiator::state::{
+            ArpOrder, ArpRate, ArpeggiatorState, ChordInput, ChordQuality, MAX_CHORDS,
+        },
+        common::SequencerType,
+        euclidean::gui::{Gui as EuclideanGui, Message as EuclideanGuiMessage},
+        step::{
+            gui::{Gui as StepGui, Message as StepGuiMessage},
+            state::{MAX_STEPS, MIN_GATE},
+        },
+    },
+    smf::{
+        export,
+        import::{self, ImportMode, ImportedTrack},
+        LIVE_EXPORT_FILE, SEQUENCE_EXPORT_FILE,
+    },
+    state::SequencerSlot,
+    synth::{
+        render::{self, RenderOptions, RENDER_FILE},
+        timbre::{SynthConfig, Waveform},
+    },
+    tempo::{Nudge, COARSE_BPM_STEP, FINE_BPM_STEP, MAX_BPM, MIN_BPM},
+    transport::{BarAction, Position, TimeSignature},
     SharedState,
 };
 use iced::{
@@ -11,7 +44,7 @@ use iced::{
     widget::{
         button,
         button::{Status as ButtonStatus, Style as ButtonStyle},
-        column, container, pick_list, row, text,
+        checkbox, column, container, mouse_area, pick_list, row, text, text_input,
     },
     widget::{
         slider::{self, Handle, Rail, Status as SliderStatus, Style as SliderStyle},
@@ -22,22 +55,172 @@ use iced::{
 };
 use iced_futures::core::font;
 use log::{error, info, warn};
+use std::fmt;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
-use tokio::sync::{mpsc::Sender, oneshot};
+use tokio::sync::{mpsc::Sender, oneshot, RwLock};
 
 #[derive(Debug, Clone)]
 pub enum Message {
     ReceivedEvent(Event),
     LeftSequencer(EuclideanGuiMessage),
     RightSequencer(EuclideanGuiMessage),
+    StepSequencer(StepGuiMessage),
+    SequencerTypeSelected(SequencerSlot, SequencerType),
+    Freeze(SequencerSlot),
+    Unfreeze(SequencerSlot),
+    StepPitchSelected(u8),
+    StepVelocityChanged(u8),
+    StepGateChanged(f32),
+    StepLengthSelected(usize),
+    ArpInputSelected(ChordInput),
+    ArpOrderSelected(ArpOrder),
+    ArpOctavesSelected(usize),
+    ArpRateSelected(ArpRate),
+    ArpGateChanged(f32),
+    ToggleArpLatch(bool),
+    ToggleArpRhythm(bool),
+    ChordRootSelected(usize, u8),
+    ChordQualitySelected(usize, ChordQuality),
+    AddChord,
+    RemoveChord(usize),
     MixerRatioChanged(f32),
+    Undo,
+    Redo,
+    SceneSelected(usize),
+    SceneModeSelected(SceneMode),
+    SceneNameChanged(String),
+    MorphBarsSelected(usize),
+    ToggleArrangement,
+    ExportChannelSelected(SequencerSlot, u8),
+    ExportBarsSelected(usize),
+    ExportSequence,
+    ExportLive,
+    ExportFinished(Result<String, String>),
+    RenderWav,
+    RenderFinished(Result<String, String>),
+    ImportPathChanged(String),
+    LoadImportFile,
+    ImportTracksLoaded(Result<Vec<ImportedTrack>, String>),
+    ImportTrackSelected(ImportedTrack),
+    ImportModeSelected(ImportMode),
+    ImportTrack,
+    ClearImport,
+    ToggleRecording,
+    RecordModeSelected(RecordMode),
+    RecordTargetSelected(RecordTarget),
+    LearnParameterSelected(Parameter),
+    LearnModeSelected(MappingMode),
+    ToggleLearn,
+    RemoveMapping(usize),
     RefreshMidiPorts,
     MidiPortsLoaded(Result<Vec<String>, String>),
     MidiPortSelected(String),
     MidiPortSet(String),
+    MidiInputPortSelected(String),
+    OutputPolicySelected(OutputPolicy),
+    MidiCommandSent(&'static str),
+    Panic,
+    RouteSourceSelected(Source),
+    RoutePortSelected(String),
+    RouteChannelSelected(RouteChannel),
+    AddRoute,
+    RemoveRoute(usize),
+    ToggleSynth(bool),
+    ToggleLink(bool),
+    ChangeBpm(f32),
+    TapTempo,
+    NudgeChanged(Nudge),
+    RampTargetChanged(f32),
+    RampBarsSelected(usize),
+    StartTempoRamp,
+    TimeSignatureSelected(TimeSignature),
+    BarActionSelected(BarAction),
+    ToggleMetronome(bool),
+    ClickOutputSelected(ClickOutput),
+    MetronomeChannelSelected(u8),
+    /// The note of the downbeat if true, else of the other beats.
+    MetronomeNoteSelected(bool, u8),
+    CountInSelected(usize),
+    SynthWaveformSelected(SequencerSlot, Waveform),
+    SynthEnvelopeChanged(SequencerSlot, EnvelopeStage, f32),
+    SynthVolumeChanged(SequencerSlot, f32),
+    MuteToggled(SequencerSlot),
+    SoloToggled(SequencerSlot),
+    MuteOnBarToggled(SequencerSlot),
+    SlotVolumeChanged(SequencerSlot, f32),
     ErrorOccurred(String),
 }
 
+/// What happens when a scene button is pressed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SceneMode {
+    #[default]
+    Recall,
+    Store,
+    Morph,
+}
+
+impl SceneMode {
+    const ALL: [SceneMode; 3] = [SceneMode::Recall, SceneMode::Store, SceneMode::Morph];
+}
+
+impl fmt::Display for SceneMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneMode::Recall => write!(f, "Recall"),
+            SceneMode::Store => write!(f, "Store"),
+            SceneMode::Morph => write!(f, "Morph"),
+        }
+    }
+}
+
+/// Envelope parameter of a synth timbre edited with a slider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvelopeStage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+impl fmt::Display for EnvelopeStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvelopeStage::Attack => write!(f, "A"),
+            EnvelopeStage::Decay => write!(f, "D"),
+            EnvelopeStage::Sustain => write!(f, "S"),
+            EnvelopeStage::Release => write!(f, "R"),
+        }
+    }
+}
+
+/// MIDI channel of a route, or the channel selected with `C`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RouteChannel(Option<u8>);
+
+impl RouteChannel {
+    fn all() -> Vec<RouteChannel> {
+        std::iter::once(RouteChannel(None))
+            .chain((0..16).map(|channel| RouteChannel(Some(channel))))
+            .collect()
+    }
+}
+
+impl fmt::Display for RouteChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(channel) => write!(f, "Ch. {}", channel + 1),
+            None => write!(f, "Selected ch."),
+        }
+    }
+}
+
+const MORPH_BARS_OPTIONS: [usize; 5] = [1, 2, 4, 8, 16];
+const EXPORT_BARS_OPTIONS: [usize; 6] = [1, 2, 4, 8, 16, 32];
+const COUNT_IN_OPTIONS: [usize; 4] = [0, 1, 2, 4];
+const ARP_OCTAVE_OPTIONS: [usize; 4] = [1, 2, 3, 4];
+
 pub struct CustomTheme {
     pub primary_color: Color,
     pub primary_color_muted: Color,
@@ -90,12 +273,28 @@ impl Default for CustomTheme {
 pub struct Gui {
     tx_gui: Arc<Mutex<Option<mpsc::Sender<Message>>>>,
     tx_midi: Sender<MidiCommand>,
+    shared_state: Arc<RwLock<SharedState>>,
     cached_state: Option<SharedState>,
     sequencer_left: EuclideanGui,
     sequencer_right: EuclideanGui,
+    step_left: StepGui,
+    step_right: StepGui,
     mixer_ratio: f32,
-    midi_out_ports: Vec<String>,
-    selected_midi_port: Option<String>,
+    midi_ports: MidiPortStatus,
+    scene_mode: SceneMode,
+    scene_name: String,
+    export_channels: (u8, u8),
+    export_bars: usize,
+    import_path: String,
+    import_tracks: Vec<ImportedTrack>,
+    selected_import_track: Option<ImportedTrack>,
+    import_mode: ImportMode,
+    learn_parameter: Parameter,
+    learn_mode: MappingMode,
+    route_source: Source,
+    route_port: Option<String>,
+    route_channel: RouteChannel,
+    ramp_target: f32,
     theme: CustomTheme,
 }
 
@@ -103,18 +302,37 @@ impl Gui {
     fn new(
         tx_gui: Arc<Mutex<Option<mpsc::Sender<Message>>>>,
         tx_midi: Sender<MidiCommand>,
+        shared_state: Arc<RwLock<SharedState>>,
         sequencer_left: EuclideanGui,
         sequencer_right: EuclideanGui,
+        step_left: StepGui,
+        step_right: StepGui,
     ) -> Self {
         Self {
             tx_gui,
             tx_midi,
+            shared_state,
             cached_state: None,
             sequencer_left,
             sequencer_right,
+            step_left,
+            step_right,
             mixer_ratio: 0.5,
-            midi_out_ports: vec!["".to_string()],
-            selected_midi_port: None,
+            midi_ports: MidiPortStatus::default(),
+            scene_mode: SceneMode::default(),
+            scene_name: String::new(),
+            export_channels: (0, 0),
+            export_bars: 4,
+            import_path: String::new(),
+            import_tracks: Vec::new(),
+            selected_import_track: None,
+            import_mode: ImportMode::default(),
+            learn_parameter: Parameter::default(),
+            learn_mode: MappingMode::default(),
+            route_source: Source::default(),
+            route_port: None,
+            route_channel: RouteChannel::default(),
+            ramp_target: 120.0,
             theme: CustomTheme::default(),
         }
     }
@@ -122,6 +340,137 @@ impl Gui {
         Subscription::run(poll).map(Message::ReceivedEvent)
     }
 
+    /// Write to the SharedState and send the resulting state back to the GUI.
+    fn edit_state(&self, f: impl FnOnce(&mut SharedState) + Send + 'static) -> Task<Message> {
+        let shared_state = self.shared_state.clone();
+
+        Task::perform(
+            async move {
+                let mut w_state = shared_state.write().await;
+                f(&mut w_state);
+                w_state.clone()
+            },
+            |state| Message::ReceivedEvent(Event::StateChanged(Box::new(state))),
+        )
+    }
+
+    /// Change the state like edit_state, and save what `f` returns once the state is unlocked,
+    /// so that writing the file does not hold up playback.
+    fn edit_and_save<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut SharedState) -> T + Send + 'static,
+        save: impl FnOnce(T) -> anyhow::Result<()> + Send + 'static,
+    ) -> Task<Message> {
+        let shared_state = self.shared_state.clone();
+
+        Task::perform(
+            async move {
+                let mut w_state = shared_state.write().await;
+                let saved = f(&mut w_state);
+                let state = w_state.clone();
+                drop(w_state);
+                if let Err(e) = save(saved) {
+                    error!("{e:#}");
+                }
+                state
+            },
+            |state| Message::ReceivedEvent(Event::StateChanged(Box::new(state))),
+        )
+    }
+
+    fn edit_metronome(
+        &self,
+        f: impl FnOnce(&mut MetronomeConfig) + Send + 'static,
+    ) -> Task<Message> {
+        self.edit_and_save(
+            move |s| s.edit_metronome(f),
+            |metronome| metronome.save(Path::new(METRONOME_FILE)),
+        )
+    }
+
+    /// Send a command to the MIDI handler.
+    fn send_midi(&self, command: MidiCommand, name: &'static str) -> Task<Message> {
+        let tx_midi = self.tx_midi.clone();
+
+        Task::perform(
+            async move { tx_midi.send(command).await.map_err(|e| e.to_string()) },
+            move |result| match result {
+                Ok(()) => Message::MidiCommandSent(name),
+                Err(e) => Message::ErrorOccurred(format!("Could not send {name} message: {e}")),
+            },
+        )
+    }
+
+    /// Change the arpeggiator of the active slot.
+    fn edit_arpeggiator(
+        &self,
+        f: impl FnOnce(&mut ArpeggiatorState) + Send + 'static,
+    ) -> Task<Message> {
+        self.edit_state(move |s| {
+            let slot = s.active_sequencer.clone();
+            f(s.arpeggiator_mut(&slot))
+        })
+    }
+
+    /// Change the synth settings shown in the GUI and send them to the MIDI handler.
+    fn edit_synth(&mut self, f: impl FnOnce(&mut SynthConfig)) -> Task<Message> {
+        f(&mut self.midi_ports.synth);
+        let config = self.midi_ports.synth.clone();
+        self.send_midi(MidiCommand::SetSynth { config }, "SetSynth")
+    }
+
+    /// Export the current sequence, or the last bars of live output, to a MIDI file.
+    fn export(&self, live_bars: Option<usize>) -> Task<Message> {
+        let shared_state = self.shared_state.clone();
+        let channels = self.export_channels;
+
+        Task::perform(
+            async move {
+                let r_state = shared_state.read().await;
+                let (steps, file) = match live_bars {
+                    Some(bars) => (r_state.last_played_steps(bars), LIVE_EXPORT_FILE),
+                    None => (r_state.mixed_sequence.notes.clone(), SEQUENCE_EXPORT_FILE),
+                };
+                let options = export::ExportOptions {
+                    channels,
+                    ..r_state.export_options()
+                };
+                drop(r_state);
+
+                export::write_file(&steps, &options, Path::new(file))
+                    .map(|_| file.to_string())
+                    .map_err(|e| format!("{e:#}"))
+            },
+            Message::ExportFinished,
+        )
+    }
+
+    /// Render the current sequence on the built-in synth to a WAV file, looped for the
+    /// selected number of bars.
+    fn render_wav(&self) -> Task<Message> {
+        let shared_state = self.shared_state.clone();
+        let bars = self.export_bars;
+        let config = self.midi_ports.synth.clone();
+
+        Task::perform(
+            async move {
+                let r_state = shared_state.read().await;
+                let steps = r_state.mixed_sequence.notes.clone();
+                let options = RenderOptions::new(r_state.bpm, bars, r_state.steps_per_bar());
+                drop(r_state);
+
+                tokio::task::spawn_blocking(move || {
+                    render::write_file(&steps, &options, &config, Path::new(RENDER_FILE))
+                        .map(|_| RENDER_FILE.to_string())
+                        .map_err(|e| format!("{e:#}"))
+                })
+                .await
+                .unwrap_or_else(|e| Err(e.to_string()))
+            },
+            Message::RenderFinished,
+        )
+    }
+
     pub fn update(&mut self, message: Message) -> Task<Message> {
         match message {
             Message::ReceivedEvent(event) => match event {
@@ -133,14 +482,19 @@ impl Gui {
                 }
                 Event::Disconnected => info!("Sender Disconnected"),
                 Event::StateChanged(state) => {
-                    self.cached_state = Some(state.clone());
-
                     self.sequencer_left
-                        .update(EuclideanGuiMessage::FromApp(state.clone()));
+                        .update(EuclideanGuiMessage::FromApp((*state).clone()));
                     self.sequencer_right
-                        .update(EuclideanGuiMessage::FromApp(state.clone()));
+                        .update(EuclideanGuiMessage::FromApp((*state).clone()));
+                    self.step_left
+                        .update(StepGuiMessage::FromApp(state.clone()));
+                    self.step_right
+                        .update(StepGuiMessage::FromApp(state.clone()));
                     self.mixer_ratio = state.mixer_state.ratio;
+
+                    self.cached_state = Some(*state);
                 }
+                Event::MidiPortsChanged(status) => self.midi_ports = *status,
             },
             Message::LeftSequencer(state) => {
                 info!("Left sequencer message in Main GUI update: {:?}", state)
@@ -148,8 +502,184 @@ impl Gui {
             Message::RightSequencer(state) => {
                 info!("Right sequencer message in Main GUI update: {:?}", state)
             }
+            Message::StepSequencer(message) => match message {
+                StepGuiMessage::ToggleStep(slot, index) => {
+                    return self.edit_state(move |s| {
+                        s.select_step(&slot, index);
+                        s.toggle_step();
+                    })
+                }
+                StepGuiMessage::SelectStep(slot, index) => {
+                    return self.edit_state(move |s| s.select_step(&slot, index))
+                }
+                StepGuiMessage::FromApp(_) => {}
+            },
+            Message::SequencerTypeSelected(slot, sequencer_type) => {
+                return self.edit_state(move |s| {
+                    s.apply_edit(Edit::SequencerType, |s| {
+                        s.set_sequencer_type(&slot, sequencer_type)
+                    })
+                })
+            }
+            Message::Freeze(slot) => return self.edit_state(move |s| s.freeze(&slot)),
+            Message::Unfreeze(slot) => return self.edit_state(move |s| s.unfreeze(&slot)),
+            Message::ArpInputSelected(input) => {
+                return self.edit_arpeggiator(move |a| a.input = input)
+            }
+            Message::ArpOrderSelected(order) => {
+                return self.edit_arpeggiator(move |a| a.order = order)
+            }
+            Message::ArpOctavesSelected(octaves) => {
+                return self.edit_arpeggiator(move |a| a.octaves = octaves)
+            }
+            Message::ArpRateSelected(rate) => return self.edit_arpeggiator(move |a| a.rate = rate),
+            Message::ArpGateChanged(gate) => return self.edit_arpeggiator(move |a| a.gate = gate),
+            Message::ToggleArpLatch(latch) => {
+                return self.edit_arpeggiator(move |a| a.latch = latch)
+            }
+            Message::ToggleArpRhythm(rhythm_gate) => {
+                return self.edit_arpeggiator(move |a| a.rhythm_gate = rhythm_gate)
+            }
+            Message::ChordRootSelected(index, root) => {
+                return self.edit_arpeggiator(move |a| a.chord_lane[index].root = root)
+            }
+            Message::ChordQualitySelected(index, quality) => {
+                return self.edit_arpeggiator(move |a| a.chord_lane[index].quality = quality)
+            }
+            Message::AddChord => return self.edit_arpeggiator(|a| a.add_chord()),
+            Message::RemoveChord(index) => {
+                return self.edit_arpeggiator(move |a| a.remove_chord(index))
+            }
+            Message::StepPitchSelected(pitch) => {
+                return self.edit_state(move |s| s.edit_step(|step| step.pitch = pitch))
+            }
+            Message::StepVelocityChanged(velocity) => {
+                return self.edit_state(move |s| s.edit_step(|step| step.velocity = velocity))
+            }
+            Message::StepGateChanged(gate) => {
+                return self.edit_state(move |s| s.edit_step(|step| step.gate = gate))
+            }
+            Message::StepLengthSelected(length) => {
+                return self.edit_state(move |s| {
+                    let slot = s.active_sequencer.clone();
+                    s.apply_edit(Edit::Step, |s| s.step_state_mut(&slot).set_length(length))
+                })
+            }
             Message::MixerRatioChanged(ratio) => {
                 self.mixer_ratio = ratio;
+                return self.edit_state(move |s| {
+                    s.apply_edit(Edit::MixerRatio, |s| s.mixer_state.set_ratio(ratio))
+                });
+            }
+            Message::Undo => return self.edit_state(|s| s.undo()),
+            Message::Redo => return self.edit_state(|s| s.redo()),
+            Message::SceneSelected(slot) => {
+                return match self.scene_mode {
+                    SceneMode::Recall => self.edit_state(move |s| s.recall_scene(slot)),
+                    SceneMode::Morph => self.edit_state(move |s| s.morph_to_scene(slot)),
+                    SceneMode::Store => {
+                        let name = (!self.scene_name.is_empty()).then(|| self.scene_name.clone());
+                        self.scene_mode = SceneMode::Recall;
+                        self.scene_name.clear();
+
+                        self.edit_state(move |s| {
+                            s.store_scene(slot, name);
+                            if let Err(e) = s.scenes.save(Path::new(SCENES_FILE)) {
+                                error!("{e:#}");
+                            }
+                        })
+                    }
+                };
+            }
+            Message::SceneModeSelected(mode) => {
+                self.scene_mode = mode;
+            }
+            Message::SceneNameChanged(name) => {
+                self.scene_name = name;
+            }
+            Message::MorphBarsSelected(bars) => {
+                return self.edit_state(move |s| s.morph_bars = bars);
+            }
+            Message::ToggleArrangement => return self.edit_state(|s| s.toggle_arrangement()),
+            Message::ExportChannelSelected(slot, channel) => match slot {
+                SequencerSlot::Left => self.export_channels.0 = channel,
+                SequencerSlot::Right => self.export_channels.1 = channel,
+            },
+            Message::ExportBarsSelected(bars) => {
+                self.export_bars = bars;
+            }
+            Message::ExportSequence => return self.export(None),
+            Message::ExportLive => return self.export(Some(self.export_bars)),
+            Message::ExportFinished(result) => match result {
+                Ok(path) => info!("Exported MIDI file to {path}"),
+                Err(e) => error!("Failed to export MIDI file: {e}"),
+            },
+            Message::RenderWav => return self.render_wav(),
+            Message::RenderFinished(result) => match result {
+                Ok(path) => info!("Rendered WAV file to {path}"),
+                Err(e) => error!("Failed to render WAV file: {e}"),
+            },
+            Message::ImportPathChanged(path) => {
+                self.import_path = path;
+            }
+            Message::LoadImportFile => {
+                let path = self.import_path.clone();
+                let shared_state = self.shared_state.clone();
+                return Task::perform(
+                    async move {
+                        let steps_per_bar = shared_state.read().await.steps_per_bar();
+                        import::read_tracks(Path::new(&path), steps_per_bar)
+                            .map_err(|e| format!("{e:#}"))
+                    },
+                    Message::ImportTracksLoaded,
+                );
+            }
+            Message::ImportTracksLoaded(result) => match result {
+                Ok(tracks) => {
+                    info!("Loaded {} tracks with notes", tracks.len());
+                    self.selected_import_track = tracks.first().cloned();
+                    self.import_tracks = tracks;
+                }
+                Err(e) => error!("Failed to load MIDI file: {e}"),
+            },
+            Message::ImportTrackSelected(track) => {
+                self.selected_import_track = Some(track);
+            }
+            Message::ImportModeSelected(mode) => {
+                self.import_mode = mode;
+            }
+            Message::ImportTrack => {
+                if let Some(track) = self.selected_import_track.clone() {
+                    let mode = self.import_mode;
+                    return self.edit_state(move |s| s.import_track(&track, mode));
+                }
+            }
+            Message::ClearImport => return self.edit_state(|s| s.clear_import()),
+            Message::ToggleRecording => return self.edit_state(|s| s.toggle_recording()),
+            Message::RecordModeSelected(mode) => {
+                return self.edit_state(move |s| s.recording.mode = mode)
+            }
+            Message::RecordTargetSelected(target) => {
+                return self.edit_state(move |s| s.recording.target = target)
+            }
+            Message::LearnParameterSelected(parameter) => self.learn_parameter = parameter,
+            Message::LearnModeSelected(mode) => self.learn_mode = mode,
+            Message::ToggleLearn => {
+                let (parameter, mode) = (self.learn_parameter, self.learn_mode);
+                return self.edit_state(move |s| {
+                    if s.midi_map.learning.take().is_none() {
+                        s.midi_map.learn(parameter, mode);
+                    }
+                });
+            }
+            Message::RemoveMapping(index) => {
+                return self.edit_and_save(
+                    move |s| {
+                        s.midi_map.remove(index);
+                        s.midi_map.clone()
+                    },
+                    |midi_map| midi_map.save(Path::new(MIDI_MAP_FILE)),
+                )
             }
             Message::RefreshMidiPorts => {
                 info!("Sending GetPorts");
@@ -179,8 +709,8 @@ impl Gui {
             }
             Message::MidiPortsLoaded(result) => match result {
                 Ok(ports) => {
-                    self.midi_out_ports = ports;
-                    info!("Successfully received new ports: {:?}", self.midi_out_ports);
+                    info!("Successfully received new ports: {:?}", ports);
+                    self.midi_ports.outputs = ports;
                 }
                 Err(e) => {
                     warn!("Failed to load ports: {}", e);
@@ -211,27 +741,156 @@ impl Gui {
             Message::ErrorOccurred(err) => {
                 error!("Received error: {}", err);
             }
-            Message::MidiPortSet(port) => {
-                self.selected_midi_port = Some(port);
+            Message::MidiPortSet(port) => info!("Selected MIDI output {port}"),
+            Message::OutputPolicySelected(policy) => {
+                return self.send_midi(MidiCommand::SetOutputPolicy { policy }, "SetOutputPolicy")
+            }
+            Message::RouteSourceSelected(source) => self.route_source = source,
+            Message::RoutePortSelected(port) => self.route_port = Some(port),
+            Message::RouteChannelSelected(channel) => self.route_channel = channel,
+            Message::AddRoute => {
+                if let Some(port) = self.route_port.clone() {
+                    let route = Route {
+                        source: self.route_source,
+                        port,
+                        channel: self.route_channel.0,
+                    };
+                    return self.send_midi(MidiCommand::AddRoute { route }, "AddRoute");
+                }
+            }
+            Message::RemoveRoute(index) => {
+                return self.send_midi(MidiCommand::RemoveRoute { index }, "RemoveRoute")
+            }
+            Message::Panic => return self.send_midi(MidiCommand::Panic { done: None }, "Panic"),
+            Message::ChangeBpm(delta) => return self.edit_state(move |s| s.change_bpm(delta)),
+            Message::TapTempo => return self.edit_state(|s| s.tap_tempo()),
+            Message::NudgeChanged(nudge) => return self.edit_state(move |s| s.nudge = nudge),
+            Message::RampTargetChanged(bpm) => self.ramp_target = bpm,
+            Message::RampBarsSelected(bars) => return self.edit_state(move |s| s.ramp_bars = bars),
+            Message::StartTempoRamp => {
+                let bpm = self.ramp_target;
+                return self.edit_state(move |s| s.start_tempo_ramp(bpm));
+            }
+            Message::TimeSignatureSelected(time_signature) => {
+                return self.edit_state(move |s| s.time_signature = time_signature)
+            }
+            Message::BarActionSelected(action) => {
+                return self.edit_state(move |s| s.bar_action = action)
+            }
+            Message::ToggleMetronome(enabled) => {
+                return self.edit_metronome(move |m| m.enabled = enabled)
+            }
+            Message::ClickOutputSelected(output) => {
+                return self.edit_metronome(move |m| m.output = output)
+            }
+            Message::MetronomeChannelSelected(channel) => {
+                return self.edit_metronome(move |m| m.channel = channel)
+            }
+            Message::MetronomeNoteSelected(accent, note) => {
+                return self.edit_metronome(move |m| match accent {
+                    true => m.accent_note = note,
+                    false => m.note = note,
+                })
+            }
+            Message::CountInSelected(bars) => {
+                return self.edit_metronome(move |m| m.count_in_bars = bars)
+            }
+            Message::MuteToggled(slot) => return self.edit_state(move |s| s.toggle_mute(&slot)),
+            Message::SoloToggled(slot) => return self.edit_state(move |s| s.toggle_solo(&slot)),
+            Message::MuteOnBarToggled(slot) => {
+                return self.edit_state(move |s| s.toggle_mute_on_bar(&slot))
+            }
+            Message::SlotVolumeChanged(slot, volume) => {
+                return self.edit_state(move |s| s.slot_mix_mut(&slot).volume = volume)
+            }
+            Message::ToggleLink(enabled) => {
+                return self.edit_state(move |s| s.link.enabled = enabled)
+            }
+            Message::ToggleSynth(enabled) => {
+                return self.edit_synth(|config| config.enabled = enabled)
+            }
+            Message::SynthWaveformSelected(slot, waveform) => {
+                return self.edit_synth(|config| config.timbre_mut(&slot).waveform = waveform)
+            }
+            Message::SynthEnvelopeChanged(slot, stage, value) => {
+                return self.edit_synth(|config| {
+                    let envelope = &mut config.timbre_mut(&slot).envelope;
+                    match stage {
+                        EnvelopeStage::Attack => envelope.attack = value,
+                        EnvelopeStage::Decay => envelope.decay = value,
+                        EnvelopeStage::Sustain => envelope.sustain = value,
+                        EnvelopeStage::Release => envelope.release = value,
+                    }
+                })
+            }
+            Message::SynthVolumeChanged(slot, volume) => {
+                return self.edit_synth(|config| config.timbre_mut(&slot).volume = volume)
+            }
+            Message::MidiCommandSent(command) => info!("Sent {command}"),
+            Message::MidiInputPortSelected(port) => {
+                let tx_midi = self.tx_midi.clone();
+
+                return Task::perform(
+                    async move {
+                        match tx_midi
+                            .send(MidiCommand::SetInputPort {
+                                in_port: port.clone(),
+                            })
+                            .await
+                        {
+                            Ok(_) => Message::MidiPortSet(port),
+                            Err(e) => Message::ErrorOccurred(format!(
+                                "Could not send SetInputPort message: {e}"
+                            )),
+                        }
+                    },
+                    |msg| msg,
+                );
             }
         }
 
         Task::none()
     }
 
-    pub fn view(&self) -> Element<Message> {
-        let sequencer_left_view =
-            Container::new(self.sequencer_left.view().map(Message::LeftSequencer))
-                .width(Length::FillPortion(1))
-                .height(Length::Fill);
+    pub fn view(&self) -> Element<'_, Message> {
+        let sequencer_type = |slot| {
+            self.cached_state
+                .as_ref()
+                .map(|state| state.sequencer_type(&slot))
+                .unwrap_or_default()
+        };
+        let sequencer_left_view = match sequencer_type(SequencerSlot::Left) {
+            SequencerType::Euclidean => {
+                Container::new(self.sequencer_left.view().map(Message::LeftSequencer))
+            }
+            SequencerType::Step => {
+                Container::new(self.step_left.view().map(Message::StepSequencer))
+            }
+            SequencerType::Arpeggiator => {
+                Container::new(self.view_arpeggiator(SequencerSlot::Left))
+            }
+        }
+        .width(Length::FillPortion(1))
+        .height(Length::Fill);
 
-        let sequencer_right_view =
-            Container::new(self.sequencer_right.view().map(Message::RightSequencer))
-                .width(Length::FillPortion(1))
-                .height(Length::Fill);
+        let sequencer_right_view = match sequencer_type(SequencerSlot::Right) {
+            SequencerType::Euclidean => {
+                Container::new(self.sequencer_right.view().map(Message::RightSequencer))
+            }
+            SequencerType::Step => {
+                Container::new(self.step_right.view().map(Message::StepSequencer))
+            }
+            SequencerType::Arpeggiator => {
+                Container::new(self.view_arpeggiator(SequencerSlot::Right))
+            }
+        }
+        .width(Length::FillPortion(1))
+        .height(Length::Fill);
 
         let sequencer_content = row![sequencer_left_view, sequencer_right_view].spacing(20);
 
+        let sequencer_type_content = self.view_sequencer_types();
+
         let mixer_content = Container::new(self.view_mixer())
             .width(Length::Fill)
             .height(Length::Fill);
@@ -248,7 +907,7 @@ impl Gui {
             vertical_space().height(10),
             text("General").color(self.theme.secondary_text_color).font(self.theme.bold_font),
             text(
-                "Spacebar: resume / pause playback\nTab: change active sequencer\nCtrl+C: exit program"
+                "Spacebar: resume / pause playback\nTab: change active sequencer\nZ / Y: undo / redo\nE: export sequence to MIDI file\nQ: arm / stop MIDI recording\nP: MIDI panic, all notes off\nCtrl+C: exit program"
             )
             .color(self.theme.text_color),
             vertical_space().height(20),
@@ -260,14 +919,57 @@ impl Gui {
             text("Euclidean sequencer").color(self.theme.secondary_text_color).font(self.theme.bold_font),
             text("Up / Down: increase / decrease steps\nRight / Left: increase / decrease pulses").color(self.theme.text_color),
             vertical_space().height(20),
+            text("Step sequencer").color(self.theme.secondary_text_color).font(self.theme.bold_font),
+            text("Arrows: move the selected step\nEnter: switch selected step on / off\nW / S, D / A: change pitch of selected step\nV / Shift + V: increase / decrease velocity\nG / Shift + G: lengthen / shorten gate\nClick: switch a step on / off, right click: select it\nU: freeze generated pattern into steps / unfreeze").color(self.theme.text_color),
+            vertical_space().height(20),
             text("Mixer").color(self.theme.secondary_text_color).font(self.theme.bold_font),
-            text("R / F: increase / decrease mixer ratio").color(self.theme.text_color),
+            text("R / F: increase / decrease mixer ratio\nX: mute / unmute active sequencer\nShift + X: mute / unmute active sequencer on the next bar\nO: solo active sequencer\n[ / ]: decrease / increase volume of active sequencer").color(self.theme.text_color),
+            vertical_space().height(20),
+            text("Tempo").color(self.theme.secondary_text_color).font(self.theme.bold_font),
+            text("= / -: increase / decrease BPM by 1\nShift + = / -: increase / decrease BPM by 0.1\nT: tap tempo\nHold , / .: nudge slower / faster\nK: metronome on / off").color(self.theme.text_color),
+            vertical_space().height(20),
+            text("Scenes").color(self.theme.secondary_text_color).font(self.theme.bold_font),
+            text("1-8: recall scene\nShift + 1-8: store scene\nCtrl + 1-8: morph to scene\nM: start / stop arrangement").color(self.theme.text_color),
             vertical_space().height(80)
         ];
 
+        let tempo_content = self.view_tempo();
+
+        let metronome_content = self.view_metronome();
+
+        let history_content = self.view_history();
+
+        let scenes_content = self.view_scenes();
+
+        let export_content = self.view_export();
+
+        let import_content = self.view_import();
+
+        let recording_content = self.view_recording();
+
+        let midi_learn_content = self.view_midi_learn();
+
+        let routing_content = self.view_routing();
+
+        let synth_content = self.view_synth();
+
+        let link_content = self.view_link();
+
         let content = column![
             sequencer_content,
+            sequencer_type_content,
             mixer_content,
+            tempo_content,
+            metronome_content,
+            history_content,
+            scenes_content,
+            export_content,
+            import_content,
+            recording_content,
+            midi_learn_content,
+            routing_content,
+            synth_content,
+            link_content,
             midi_content,
             help_text_content
         ]
@@ -280,7 +982,207 @@ impl Gui {
             .into()
     }
 
-    pub fn view_mixer(&self) -> Element<Message> {
+    /// The type of sequencer of each slot, and the selected step while a step pattern is active.
+    pub fn view_sequencer_types(&self) -> Element<'_, Message> {
+        let Some(state) = self.cached_state.as_ref() else {
+            return row![].into();
+        };
+        let types = [SequencerSlot::Left, SequencerSlot::Right].map(|slot| {
+            let sequencer_type = state.sequencer_type(&slot);
+            let type_slot = slot.clone();
+            // A generated pattern is frozen into a step pattern, and a step pattern unfrozen
+            let freeze = match sequencer_type {
+                SequencerType::Step => button("Unfreeze").on_press(Message::Unfreeze(slot.clone())),
+                _ => button("Freeze").on_press(Message::Freeze(slot.clone())),
+            };
+            row![
+                text(format!("{slot:?}")).color(self.theme.text_color),
+                pick_list(SequencerType::ALL, Some(sequencer_type), move |t| {
+                    Message::SequencerTypeSelected(type_slot.clone(), t)
+                }),
+                freeze.style(self.button_style()),
+            ]
+            .spacing(10)
+            .align_y(Center)
+            .into()
+        });
+        let mut content = column![row(types).spacing(40)].spacing(10).align_x(Center);
+
+        if state.is_step_active() {
+            let step_state = state.step_state(&state.active_sequencer);
+            let step = step_state.steps[state.step_cursor];
+            let pitches = (20..=108).collect::<Vec<u8>>();
+            let lengths = (1..=MAX_STEPS).collect::<Vec<usize>>();
+            content = content.push(
+                row![
+                    text(format!("Step {}", state.step_cursor + 1))
+                        .color(self.theme.primary_text_color)
+                        .font(self.theme.bold_font),
+                    text("Pitch").color(self.theme.text_color),
+                    pick_list(pitches, Some(step.pitch), Message::StepPitchSelected),
+                    text(format!("Vel {}", step.velocity)).color(self.theme.text_color),
+                    iced::widget::slider(1.0..=127.0, step.velocity as f32, |velocity| {
+                        Message::StepVelocityChanged(velocity as u8)
+                    })
+                    .width(100),
+                    text(format!("Gate {:.1}", step.gate)).color(self.theme.text_color),
+                    iced::widget::slider(MIN_GATE..=1.0, step.gate, Message::StepGateChanged)
+                        .step(0.05)
+                        .width(100),
+                    text("Length").color(self.theme.text_color),
+                    pick_list(
+                        lengths,
+                        Some(step_state.length),
+                        Message::StepLengthSelected
+                    ),
+                ]
+                .spacing(10)
+                .align_y(Center),
+            );
+        }
+
+        if state.sequencer_type(&state.active_sequencer) == SequencerType::Arpeggiator {
+            content = content.push(self.view_arpeggiator_settings(state));
+        }
+
+        container(content)
+            .width(Length::Fill)
+            .align_x(Center)
+            .into()
+    }
+
+    /// The chords an arpeggiator plays, in place of the grid of its slot.
+    pub fn view_arpeggiator(&self, slot: SequencerSlot) -> Element<'_, Message> {
+        let chords = self
+            .cached_state
+            .as_ref()
+            .map(|state| {
+                let arpeggiator = state.arpeggiator(&slot);
+                state
+                    .arpeggiator_chords(&slot)
+                    .iter()
+                    .map(|chord| {
+                        arpeggiator
+                            .pattern(chord)
+                            .into_iter()
+                            .map(Sequence::midi_to_note_name)
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let chords = chords.into_iter().enumerate().map(|(bar, notes)| {
+            let notes = if notes.is_empty() {
+                "Hold a chord on the MIDI input".to_string()
+            } else {
+                notes
+            };
+            text(format!("{}: {notes}", bar + 1))
+                .color(self.theme.text_color)
+                .into()
+        });
+
+        let content = column![
+            text(format!("{slot:?} arpeggiator"))
+                .color(self.theme.primary_text_color)
+                .font(self.theme.header_font),
+            column(chords).spacing(5),
+        ]
+        .align_x(Center)
+        .spacing(10);
+
+        container(content)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .align_x(Center)
+            .align_y(Center)
+            .into()
+    }
+
+    /// Settings of the arpeggiator of the active slot, and its chord lane.
+    fn view_arpeggiator_settings(&self, state: &SharedState) -> Element<'_, Message> {
+        let arpeggiator = state.arpeggiator(&state.active_sequencer);
+        let settings = row![
+            text("Arpeggiator")
+                .color(self.theme.primary_text_color)
+                .font(self.theme.bold_font),
+            pick_list(
+                ChordInput::ALL,
+                Some(arpeggiator.input),
+                Message::ArpInputSelected
+            ),
+            pick_list(
+                ArpOrder::ALL,
+                Some(arpeggiator.order),
+                Message::ArpOrderSelected
+            ),
+            text("Octaves").color(self.theme.text_color),
+            pick_list(
+                ARP_OCTAVE_OPTIONS,
+                Some(arpeggiator.octaves),
+                Message::ArpOctavesSelected
+            ),
+            text("Rate").color(self.theme.text_color),
+            pick_list(
+                ArpRate::ALL,
+                Some(arpeggiator.rate),
+                Message::ArpRateSelected
+            ),
+            text(format!("Gate {:.1}", arpeggiator.gate)).color(self.theme.text_color),
+            iced::widget::slider(MIN_GATE..=1.0, arpeggiator.gate, Message::ArpGateChanged)
+                .step(0.05)
+                .width(100),
+            checkbox("Latch", arpeggiator.latch).on_toggle(Message::ToggleArpLatch),
+            checkbox("Rhythm of other slot", arpeggiator.rhythm_gate)
+                .on_toggle(Message::ToggleArpRhythm),
+        ]
+        .spacing(10)
+        .align_y(Center);
+
+        if arpeggiator.input != ChordInput::Lane {
+            return settings.into();
+        }
+        let roots = (24..=96).collect::<Vec<u8>>();
+        let chords = arpeggiator
+            .chord_lane
+            .iter()
+            .enumerate()
+            .map(|(index, chord)| {
+                row![
+                    pick_list(roots.clone(), Some(chord.root), move |root| {
+                        Message::ChordRootSelected(index, root)
+                    }),
+                    pick_list(ChordQuality::ALL, Some(chord.quality), move |quality| {
+                        Message::ChordQualitySelected(index, quality)
+                    }),
+                    button("x")
+                        .on_press_maybe(
+                            (arpeggiator.chord_lane.len() > 1)
+                                .then_some(Message::RemoveChord(index))
+                        )
+                        .style(self.button_style()),
+                ]
+                .spacing(5)
+                .align_y(Center)
+                .into()
+            });
+        let lane = row![
+            text("Chords").color(self.theme.text_color),
+            row(chords).spacing(15),
+            button("Add chord")
+                .on_press_maybe(
+                    (arpeggiator.chord_lane.len() < MAX_CHORDS).then_some(Message::AddChord)
+                )
+                .style(self.button_style()),
+        ]
+        .spacing(10)
+        .align_y(Center);
+
+        column![settings, lane].spacing(10).align_x(Center).into()
+    }
+
+    pub fn view_mixer(&self) -> Element<'_, Message> {
         let theme = &self.theme;
         let slider = iced::widget::slider(0.0..=1.0, self.mixer_ratio, Message::MixerRatioChanged)
             .style(move |_: &iced::Theme, status: SliderStatus| {
@@ -322,11 +1224,39 @@ impl Gui {
                     },
                 }
             });
+        let slots = [SequencerSlot::Left, SequencerSlot::Right].map(|slot| {
+            let mix = self
+                .cached_state
+                .as_ref()
+                .map(|state| *state.slot_mix(&slot))
+                .unwrap_or_default();
+            let (mute_slot, solo_slot, bar_slot, volume_slot) =
+                (slot.clone(), slot.clone(), slot.clone(), slot.clone());
+            row![
+                text(format!("{slot:?}")).color(self.theme.text_color),
+                checkbox("Mute", mix.muted)
+                    .on_toggle(move |_| Message::MuteToggled(mute_slot.clone())),
+                checkbox("Solo", mix.solo)
+                    .on_toggle(move |_| Message::SoloToggled(solo_slot.clone())),
+                checkbox("Mute next bar", mix.mute_on_bar)
+                    .on_toggle(move |_| Message::MuteOnBarToggled(bar_slot.clone())),
+                text("Vol").color(self.theme.text_color),
+                iced::widget::slider(0.0..=1.0, mix.volume, move |volume| {
+                    Message::SlotVolumeChanged(volume_slot.clone(), volume)
+                })
+                .step(0.01)
+                .width(100),
+            ]
+            .spacing(10)
+            .align_y(Center)
+            .into()
+        });
         let content = column![
             text("Mixer")
                 .color(self.theme.primary_text_color)
                 .font(self.theme.bold_font),
             slider,
+            row(slots).spacing(40),
         ]
         .align_x(Center)
         .spacing(20);
@@ -339,52 +1269,668 @@ impl Gui {
             .into()
     }
 
-    pub fn view_midi(&self) -> Element<Message> {
-        let dropdown = pick_list(
-            self.midi_out_ports.clone(),
-            self.selected_midi_port.clone(),
+    pub fn view_tempo(&self) -> Element<'_, Message> {
+        let (bpm, ramp, ramp_bars) = self
+            .cached_state
+            .as_ref()
+            .map(|state| (state.bpm, state.tempo_ramp, Some(state.ramp_bars)))
+            .unwrap_or((120.0, None, None));
+        let status = match ramp {
+            Some(ramp) => format!("{bpm:.1} BPM, ramping to {:.1}", ramp.to),
+            None => format!("{bpm:.1} BPM"),
+        };
+
+        let adjust = [
+            (-COARSE_BPM_STEP, "-1"),
+            (-FINE_BPM_STEP, "-0.1"),
+            (FINE_BPM_STEP, "+0.1"),
+            (COARSE_BPM_STEP, "+1"),
+        ]
+        .map(|(delta, label)| {
+            button(label)
+                .on_press(Message::ChangeBpm(delta))
+                .style(self.button_style())
+                .into()
+        });
+        // Nudging lasts while the button is held
+        let nudge =
+            [(Nudge::Slower, "Nudge -"), (Nudge::Faster, "Nudge +")].map(|(nudge, label)| {
+                mouse_area(button(label).style(self.button_style()))
+                    .on_press(Message::NudgeChanged(nudge))
+                    .on_release(Message::NudgeChanged(Nudge::None))
+                    .into()
+            });
+
+        let (position, counting_in, time_signature, bar_action) = self
+            .cached_state
+            .as_ref()
+            .map(|state| {
+                (
+                    state.position(),
+                    state.count_in > 0,
+                    Some(state.time_signature),
+                    Some(state.bar_action),
+                )
+            })
+            .unwrap_or((
+                Position::at(0, &TimeSignature::default()),
+                false,
+                None,
+                None,
+            ));
+        let counter = if counting_in {
+            "Count-in".to_string()
+        } else {
+            format!(
+                "Bar {} Beat {} Step {}",
+                position.bar, position.beat, position.step
+            )
+        };
+        let transport = row![
+            // Bar counter
+            text(counter)
+                .color(self.theme.primary_text_color)
+                .width(170),
+            pick_list(
+                TimeSignature::ALL,
+                time_signature,
+                Message::TimeSignatureSelected
+            ),
+            text("At every bar").color(self.theme.text_color),
+            pick_list(BarAction::ALL, bar_action, Message::BarActionSelected),
+        ]
+        .spacing(10)
+        .align_y(Center);
+
+        let content = column![
+            transport,
+            row![
+                text("Tempo")
+                    .color(self.theme.primary_text_color)
+                    .font(self.theme.bold_font),
+                text(status).color(self.theme.text_color),
+                row(adjust).spacing(5),
+                button("Tap")
+                    .on_press(Message::TapTempo)
+                    .style(self.button_style()),
+                row(nudge).spacing(5),
+            ]
+            .spacing(10)
+            .align_y(Center),
+            row![
+                text("Ramp to").color(self.theme.text_color),
+                iced::widget::slider(
+                    MIN_BPM..=MAX_BPM,
+                    self.ramp_target,
+                    Message::RampTargetChanged
+                )
+                .step(1.0)
+                .width(150),
+                text(format!("{:.0} BPM over", self.ramp_target)).color(self.theme.text_color),
+                pick_list(MORPH_BARS_OPTIONS, ramp_bars, Message::RampBarsSelected),
+                text("bars").color(self.theme.text_color),
+                button("Ramp")
+                    .on_press(Message::StartTempoRamp)
+                    .style(self.button_style()),
+            ]
+            .spacing(10)
+            .align_y(Center),
+        ]
+        .align_x(Center)
+        .spacing(10);
+
+        container(content)
+            .width(Length::Fill)
+            .align_x(Center)
+            .into()
+    }
+
+    pub fn view_metronome(&self) -> Element<'_, Message> {
+        let metronome = self
+            .cached_state
+            .as_ref()
+            .map(|state| state.metronome)
+            .unwrap_or_default();
+        let MetronomeConfig {
+            enabled,
+            output,
+            channel,
+            note,
+            accent_note,
+            count_in_bars,
+            ..
+        } = metronome;
+        let channels = (1..=16).collect::<Vec<u8>>();
+        let notes = (0..=127).collect::<Vec<u8>>();
+
+        let content = row![
+            text("Metronome")
+                .color(self.theme.primary_text_color)
+                .font(self.theme.bold_font),
+            checkbox("Click", enabled).on_toggle(Message::ToggleMetronome),
+            pick_list(ClickOutput::ALL, Some(output), Message::ClickOutputSelected),
+            text("Ch.").color(self.theme.text_color),
+            pick_list(channels, Some(channel + 1), |c| {
+                Message::MetronomeChannelSelected(c - 1)
+            }),
+            text("Accent").color(self.theme.text_color),
+            pick_list(notes.clone(), Some(accent_note), |n| {
+                Message::MetronomeNoteSelected(true, n)
+            }),
+            text("Beat").color(self.theme.text_color),
+            pick_list(notes, Some(note), |n| Message::MetronomeNoteSelected(
+                false, n
+            )),
+            text("Count-in bars").color(self.theme.text_color),
+            pick_list(
+                COUNT_IN_OPTIONS,
+                Some(count_in_bars),
+                Message::CountInSelected
+            ),
+        ]
+        .spacing(10)
+        .align_y(Center);
+
+        container(content)
+            .width(Length::Fill)
+            .align_x(Center)
+            .into()
+    }
+
+    pub fn view_history(&self) -> Element<'_, Message> {
+        let (can_undo, can_redo) = self
+            .cached_state
+            .as_ref()
+            .map(|state| (state.history.can_undo(), state.history.can_redo()))
+            .unwrap_or_default();
+
+        let undo_button = button("Undo")
+            .on_press_maybe(can_undo.then_some(Message::Undo))
+            .style(self.button_style());
+        let redo_button = button("Redo")
+            .on_press_maybe(can_redo.then_some(Message::Redo))
+            .style(self.button_style());
+
+        container(row![undo_button, redo_button].spacing(10))
+            .width(Length::Fill)
+            .align_x(Center)
+            .into()
+    }
+
+    pub fn view_scenes(&self) -> Element<'_, Message> {
+        let scene_buttons = (0..SCENE_SLOTS).map(|slot| {
+            let label = self
+                .cached_state
+                .as_ref()
+                .and_then(|state| state.scenes.get(slot))
+                .map(|scene| scene.name.clone())
+                .unwrap_or_else(|| format!("{}", slot + 1));
+
+            button(text(label))
+                .on_press(Message::SceneSelected(slot))
+                .style(self.button_style())
+                .into()
+        });
+
+        let morph_bars = self.cached_state.as_ref().map(|state| state.morph_bars);
+
+        let controls = row![
+            pick_list(
+                SceneMode::ALL,
+                Some(self.scene_mode),
+                Message::SceneModeSelected
+            ),
+            text_input("Scene name", &self.scene_name)
+                .on_input(Message::SceneNameChanged)
+                .width(150),
+            text("Morph bars").color(self.theme.text_color),
+            pick_list(MORPH_BARS_OPTIONS, morph_bars, Message::MorphBarsSelected),
+        ]
+        .spacing(10)
+        .align_y(Center);
+
+        let content = column![
+            text("Scenes")
+                .color(self.theme.primary_text_color)
+                .font(self.theme.bold_font),
+            row(scene_buttons).spacing(10),
+            controls,
+            self.view_arrangement(),
+        ]
+        .align_x(Center)
+        .spacing(10);
+
+        container(content)
+            .width(Length::Fill)
+            .align_x(Center)
+            .into()
+    }
+
+    pub fn view_arrangement(&self) -> Element<'_, Message> {
+        let position = self
+            .cached_state
+            .as_ref()
+            .and_then(|state| Some((state, state.arrangement_position.as_ref()?)));
+
+        let (label, status) = match position {
+            Some((state, position)) => {
+                let section = &state.arrangement.sections[position.section];
+                (
+                    "Stop arrangement",
+                    format!(
+                        "Section {}/{} - scene {} - bar {}/{} - repeat {}/{}",
+                        position.section + 1,
+                        state.arrangement.sections.len(),
+                        section.scene + 1,
+                        position.bar + 1,
+                        section.bars,
+                        position.repeat + 1,
+                        section.repeats
+                    ),
+                )
+            }
+            None => ("Start arrangement", String::new()),
+        };
+
+        row![
+            button(label)
+                .on_press(Message::ToggleArrangement)
+                .style(self.button_style()),
+            text(status).color(self.theme.text_color),
+        ]
+        .spacing(10)
+        .align_y(Center)
+        .into()
+    }
+
+    pub fn view_export(&self) -> Element<'_, Message> {
+        let channels = (1..=16).collect::<Vec<u8>>();
+
+        let content = row![
+            text("Export")
+                .color(self.theme.primary_text_color)
+                .font(self.theme.bold_font),
+            text("Left ch.").color(self.theme.text_color),
+            pick_list(channels.clone(), Some(self.export_channels.0 + 1), |c| {
+                Message::ExportChannelSelected(SequencerSlot::Left, c - 1)
+            }),
+            text("Right ch.").color(self.theme.text_color),
+            pick_list(channels, Some(self.export_channels.1 + 1), |c| {
+                Message::ExportChannelSelected(SequencerSlot::Right, c - 1)
+            }),
+            button("Export sequence")
+                .on_press(Message::ExportSequence)
+                .style(self.button_style()),
+            pick_list(
+                EXPORT_BARS_OPTIONS,
+                Some(self.export_bars),
+                Message::ExportBarsSelected
+            ),
+            button("Export last bars")
+                .on_press(Message::ExportLive)
+                .style(self.button_style()),
+            button("Render WAV")
+                .on_press(Message::RenderWav)
+                .style(self.button_style()),
+        ]
+        .spacing(10)
+        .align_y(Center);
+
+        container(content)
+            .width(Length::Fill)
+            .align_x(Center)
+            .into()
+    }
+
+    pub fn view_import(&self) -> Element<'_, Message> {
+        let content = row![
+            text("Import")
+                .color(self.theme.primary_text_color)
+                .font(self.theme.bold_font),
+            text_input("Path to .mid file", &self.import_path)
+                .on_input(Message::ImportPathChanged)
+                .on_submit(Message::LoadImportFile)
+                .width(200),
+            button("Load")
+                .on_press(Message::LoadImportFile)
+                .style(self.button_style()),
+            pick_list(
+                self.import_tracks.clone(),
+                self.selected_import_track.clone(),
+                Message::ImportTrackSelected
+            )
+            .placeholder("Track"),
+            pick_list(
+                ImportMode::ALL,
+                Some(self.import_mode),
+                Message::ImportModeSelected
+            ),
+            button("Import")
+                .on_press_maybe(
+                    self.selected_import_track
+                        .is_some()
+                        .then_some(Message::ImportTrack)
+                )
+                .style(self.button_style()),
+            button("Clear")
+                .on_press(Message::ClearImport)
+                .style(self.button_style()),
+        ]
+        .spacing(10)
+        .align_y(Center);
+
+        container(content)
+            .width(Length::Fill)
+            .align_x(Center)
+            .into()
+    }
+
+    pub fn view_recording(&self) -> Element<'_, Message> {
+        let recording = self
+            .cached_state
+            .as_ref()
+            .map(|state| state.recording.clone())
+            .unwrap_or_default();
+
+        let content = row![
+            text("Record")
+                .color(self.theme.primary_text_color)
+                .font(self.theme.bold_font),
+            button(if recording.armed { "Stop" } else { "Arm" })
+                .on_press(Message::ToggleRecording)
+                .style(self.button_style()),
+            pick_list(
+                RecordMode::ALL,
+                Some(recording.mode),
+                Message::RecordModeSelected
+            ),
+            pick_list(
+                RecordTarget::ALL,
+                Some(recording.target),
+                Message::RecordTargetSelected
+            ),
+        ]
+        .spacing(10)
+        .align_y(Center);
+
+        container(content)
+            .width(Length::Fill)
+            .align_x(Center)
+            .into()
+    }
+
+    pub fn view_midi_learn(&self) -> Element<'_, Message> {
+        let midi_map = self
+            .cached_state
+            .as_ref()
+            .map(|state| state.midi_map.clone())
+            .unwrap_or_default();
+
+        let status = match midi_map.learning {
+            Some((parameter, _)) => format!("Move a control for {parameter}"),
+            None => String::new(),
+        };
+        let controls = row![
+            text("MIDI learn")
+                .color(self.theme.primary_text_color)
+                .font(self.theme.bold_font),
+            pick_list(
+                Parameter::ALL,
+                Some(self.learn_parameter),
+                Message::LearnParameterSelected
+            ),
+            pick_list(
+                MappingMode::ALL,
+                Some(self.learn_mode),
+                Message::LearnModeSelected
+            ),
+            button(if midi_map.learning.is_some() {
+                "Cancel"
+            } else {
+                "Learn"
+            })
+            .on_press(Message::ToggleLearn)
+            .style(self.button_style()),
+            text(status).color(self.theme.text_color),
+        ]
+        .spacing(10)
+        .align_y(Center);
+
+        let mappings = midi_map
+            .mappings()
+            .iter()
+            .enumerate()
+            .map(|(i, mapping)| {
+                row![
+                    text(mapping.to_string()).color(self.theme.text_color),
+                    button("Remove")
+                        .on_press(Message::RemoveMapping(i))
+                        .style(self.button_style()),
+                ]
+                .spacing(10)
+                .align_y(Center)
+                .into()
+            })
+            .collect::<Vec<_>>();
+
+        let content = column![controls, column(mappings).spacing(5).align_x(Center)]
+            .align_x(Center)
+            .spacing(10);
+
+        container(content)
+            .width(Length::Fill)
+            .align_x(Center)
+            .into()
+    }
+
+    pub fn view_routing(&self) -> Element<'_, Message> {
+        let controls = row![
+            text("Routing")
+                .color(self.theme.primary_text_color)
+                .font(self.theme.bold_font),
+            pick_list(
+                Source::ALL,
+                Some(self.route_source),
+                Message::RouteSourceSelected
+            ),
+            pick_list(
+                self.midi_ports.outputs.clone(),
+                self.route_port.clone(),
+                Message::RoutePortSelected
+            )
+            .placeholder("Output"),
+            pick_list(
+                RouteChannel::all(),
+                Some(self.route_channel),
+                Message::RouteChannelSelected
+            ),
+            button("Add")
+                .on_press_maybe(self.route_port.is_some().then_some(Message::AddRoute))
+                .style(self.button_style()),
+        ]
+        .spacing(10)
+        .align_y(Center);
+
+        let ports = self.midi_ports.routing.ports();
+        let routes = self
+            .midi_ports
+            .routing
+            .routes()
+            .iter()
+            .enumerate()
+            .map(|(i, route)| {
+                let connected = ports
+                    .iter()
+                    .position(|port| *port == route.port)
+                    .and_then(|index| self.midi_ports.routed.get(index))
+                    .is_some_and(|connection| connection.is_connected());
+                row![
+                    text(route.to_string()).color(self.theme.text_color),
+                    text(if connected { "" } else { "(not connected)" })
+                        .color(self.theme.secondary_color),
+                    button("Remove")
+                        .on_press(Message::RemoveRoute(i))
+                        .style(self.button_style()),
+                ]
+                .spacing(10)
+                .align_y(Center)
+                .into()
+            })
+            .collect::<Vec<_>>();
+
+        let content = column![controls, column(routes).spacing(5).align_x(Center)]
+            .align_x(Center)
+            .spacing(10);
+
+        container(content)
+            .width(Length::Fill)
+            .align_x(Center)
+            .into()
+    }
+
+    pub fn view_synth(&self) -> Element<'_, Message> {
+        let config = &self.midi_ports.synth;
+        let timbres = [SequencerSlot::Left, SequencerSlot::Right].map(|slot| {
+            let timbre = config.timbre(&slot);
+            let envelope = [
+                (EnvelopeStage::Attack, timbre.envelope.attack, 0.0..=2.0),
+                (EnvelopeStage::Decay, timbre.envelope.decay, 0.0..=2.0),
+                (EnvelopeStage::Sustain, timbre.envelope.sustain, 0.0..=1.0),
+                (EnvelopeStage::Release, timbre.envelope.release, 0.0..=4.0),
+            ];
+            let sliders = envelope.into_iter().map(|(stage, value, range)| {
+                let slot = slot.clone();
+                row![
+                    text(stage.to_string()).color(self.theme.text_color),
+                    iced::widget::slider(range, value, move |value| {
+                        Message::SynthEnvelopeChanged(slot.clone(), stage, value)
+                    })
+                    .step(0.01)
+                    .width(80),
+                ]
+                .spacing(5)
+                .align_y(Center)
+                .into()
+            });
+            let volume_slot = slot.clone();
+            let waveform_slot = slot.clone();
+            row![
+                text(format!("{slot:?}")).color(self.theme.text_color),
+                pick_list(Waveform::ALL, Some(timbre.waveform), move |waveform| {
+                    Message::SynthWaveformSelected(waveform_slot.clone(), waveform)
+                }),
+                row(sliders).spacing(10),
+                text("Vol").color(self.theme.text_color),
+                iced::widget::slider(0.0..=1.0, timbre.volume, move |volume| {
+                    Message::SynthVolumeChanged(volume_slot.clone(), volume)
+                })
+                .step(0.01)
+                .width(80),
+            ]
+            .spacing(10)
+            .align_y(Center)
+            .into()
+        });
+
+        let content = column![
+            row![
+                text("Synth")
+                    .color(self.theme.primary_text_color)
+                    .font(self.theme.bold_font),
+                checkbox("Play on built-in synth", config.enabled).on_toggle(Message::ToggleSynth),
+            ]
+            .spacing(10)
+            .align_y(Center),
+            column(timbres).spacing(5).align_x(Center),
+        ]
+        .align_x(Center)
+        .spacing(10);
+
+        container(content)
+            .width(Length::Fill)
+            .align_x(Center)
+            .into()
+    }
+
+    pub fn view_link(&self) -> Element<'_, Message> {
+        let link = self
+            .cached_state
+            .as_ref()
+            .map(|state| state.link.clone())
+            .unwrap_or_default();
+        let status = match link.timeline {
+            Some(timeline) => format!("{} peer(s) at {:.1} BPM", link.peers, timeline.bpm()),
+            None => String::new(),
+        };
+
+        let content = row![
+            text("Link")
+                .color(self.theme.primary_text_color)
+                .font(self.theme.bold_font),
+            checkbox("Sync with Link peers", link.enabled).on_toggle(Message::ToggleLink),
+            text(status).color(self.theme.text_color),
+        ]
+        .spacing(10)
+        .align_y(Center);
+
+        container(content)
+            .width(Length::Fill)
+            .align_x(Center)
+            .into()
+    }
+
+    pub fn view_midi(&self) -> Element<'_, Message> {
+        let output_dropdown = pick_list(
+            self.midi_ports.outputs.clone(),
+            self.midi_ports.output.name().map(str::to_string),
             Message::MidiPortSelected,
         )
         .placeholder("Select MIDI output interface");
 
-        let theme = &self.theme;
-        let button = button("⟳")
+        let input_dropdown = pick_list(
+            self.midi_ports.inputs.clone(),
+            self.midi_ports.input.name().map(str::to_string),
+            Message::MidiInputPortSelected,
+        )
+        .placeholder("Select MIDI input interface");
+
+        let refresh_button = button("⟳")
             .on_press(Message::RefreshMidiPorts)
             .height(25)
             .width(25)
-            .style(move |_: &iced::Theme, status: ButtonStatus| {
-                let button_color = match status {
-                    ButtonStatus::Hovered => theme.accent_color,
-                    ButtonStatus::Pressed => theme.primary_color,
-                    ButtonStatus::Active => theme.primary_color_muted,
-                    ButtonStatus::Disabled => theme.text_color,
-                };
-
-                ButtonStyle {
-                    background: Some(Background::Color(button_color)),
-                    text_color: self.theme.primary_text_color,
-                    border: Border {
-                        color: button_color,
-                        width: 2.,
-                        radius: Radius {
-                            top_left: 4.,
-                            top_right: 4.,
-                            bottom_left: 4.,
-                            bottom_right: 4.,
-                        },
-                    },
-                    shadow: Shadow::default(),
-                }
-            });
+            .style(self.button_style());
 
         let content = column![
             text("Mixer")
                 .color(self.theme.primary_text_color)
                 .font(self.theme.bold_font),
-            row![dropdown, button].spacing(10)
+            row![
+                output_dropdown,
+                refresh_button,
+                button("Panic")
+                    .on_press(Message::Panic)
+                    .style(self.button_style())
+            ]
+            .spacing(10),
+            text(format!("Output: {}", self.midi_ports.output)).color(self.theme.text_color),
+            pick_list(
+                OutputPolicy::ALL,
+                Some(self.midi_ports.policy),
+                Message::OutputPolicySelected
+            ),
+            input_dropdown,
+            text(format!("Input: {}", self.midi_ports.input)).color(self.theme.text_color),
+            text(
+                self.midi_ports
+                    .error
+                    .as_ref()
+                    .map(|e| e.to_string())
+                    .unwrap_or_default()
+            )
+            .color(self.theme.secondary_color),
         ]
         .align_x(Center)
-        .spacing(20);
+        .spacing(10);
 
         container(content)
             .width(Length::Fill)
@@ -394,11 +1940,43 @@ impl Gui {
             .into()
     }
 
+    fn button_style(&self) -> impl Fn(&Theme, ButtonStatus) -> ButtonStyle + '_ {
+        let theme = &self.theme;
+
+        move |_: &Theme, status: ButtonStatus| {
+            let button_color = match status {
+                ButtonStatus::Hovered => theme.accent_color,
+                ButtonStatus::Pressed => theme.primary_color,
+                ButtonStatus::Active => theme.primary_color_muted,
+                ButtonStatus::Disabled => theme.overlay_color,
+            };
+
+            ButtonStyle {
+                background: Some(Background::Color(button_color)),
+                text_color: theme.primary_text_color,
+                border: Border {
+                    color: button_color,
+                    width: 2.,
+                    radius: Radius {
+                        top_left: 4.,
+                        top_right: 4.,
+                        bottom_left: 4.,
+                        bottom_right: 4.,
+                    },
+                },
+                shadow: Shadow::default(),
+            }
+        }
+    }
+
     pub fn run(
         tx_gui: Arc<Mutex<Option<mpsc::Sender<Message>>>>,
         tx_midi: Sender<MidiCommand>,
+        shared_state: Arc<RwLock<SharedState>>,
         sequencer_left: EuclideanGui,
         sequencer_right: EuclideanGui,
+        step_left: StepGui,
+        step_right: StepGui,
     ) -> iced::Result {
         iced::application("Sequencer", Gui::update, Gui::view)
             .subscription(|gui| gui.subscription())
@@ -407,7 +1985,15 @@ impl Gui {
             .centered()
             .run_with(|| {
                 (
-                    Self::new(tx_gui, tx_midi, sequencer_left, sequencer_right),
+                    Self::new(
+                        tx_gui,
+                        tx_midi,
+                        shared_state,
+                        sequencer_left,
+                        sequencer_right,
+                        step_left,
+                        step_right,
+                    ),
                     Task::none(),
                 )
             })
@@ -418,7 +2004,8 @@ impl Gui {
 pub enum Event {
     Connected(mpsc::Sender<Message>),
     Disconnected,
-    StateChanged(SharedState),
+    StateChanged(Box<SharedState>),
+    MidiPortsChanged(Box<MidiPortStatus>),
 }
 
 fn poll() -> impl Stream<Item = Event> {
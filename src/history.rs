@@ -0,0 +1,254 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::mixer::state::MixerState;
+use crate::sequencers::common::SequencerType;
+use crate::sequencers::euclidean::state::EuclideanSequencerState;
+use crate::sequencers::step::state::StepSequencerState;
+use crate::state::SequencerSlot;
+
+/// Maximum number of undo entries kept before the oldest ones are dropped.
+pub const HISTORY_CAPACITY: usize = 100;
+
+/// Repeated edits of the same kind on the same slot within this window are grouped into one
+/// undo entry, so holding down an arrow key or dragging a slider can be undone in one go.
+const GROUPING_WINDOW: Duration = Duration::from_millis(750);
+
+/// The kind of performance edit that was made. Used to group rapid repeated edits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edit {
+    Steps,
+    Pulses,
+    Phase,
+    Pitch,
+    MixerRatio,
+    Bpm,
+    Scene,
+    Import,
+    Step,
+    SequencerType,
+    /// Several parameters set at once, e.g. a state sent to the web API.
+    Group,
+}
+
+impl Edit {
+    /// Edits of the whole performance rather than of one sequencer slot, which are grouped
+    /// whichever slot is active.
+    fn is_global(&self) -> bool {
+        matches!(
+            self,
+            Edit::MixerRatio | Edit::Bpm | Edit::Scene | Edit::Group
+        )
+    }
+}
+
+/// The part of the SharedState that can be undone and redone.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PerformanceState {
+    pub left_state: EuclideanSequencerState,
+    pub right_state: EuclideanSequencerState,
+    pub mixer_state: MixerState,
+    pub bpm: f32,
+    #[serde(default)]
+    pub left_step_state: StepSequencerState,
+    #[serde(default)]
+    pub right_step_state: StepSequencerState,
+    #[serde(default)]
+    pub left_sequencer_type: SequencerType,
+    #[serde(default)]
+    pub right_sequencer_type: SequencerType,
+}
+
+impl PerformanceState {
+    /// Linearly interpolate towards another state, with t between 0 and 1.
+    pub fn interpolate(&self, other: &Self, t: f32) -> Self {
+        PerformanceState {
+            left_state: self.left_state.interpolate(&other.left_state, t),
+            right_state: self.right_state.interpolate(&other.right_state, t),
+            mixer_state: MixerState {
+                ratio: self.mixer_state.ratio
+                    + (other.mixer_state.ratio - self.mixer_state.ratio) * t,
+            },
+            bpm: self.bpm + (other.bpm - self.bpm) * t,
+            left_step_state: self.left_step_state.interpolate(&other.left_step_state, t),
+            right_step_state: self
+                .right_step_state
+                .interpolate(&other.right_step_state, t),
+            // Like steps, sequencer types switch halfway
+            left_sequencer_type: if t < 0.5 {
+                self.left_sequencer_type
+            } else {
+                other.left_sequencer_type
+            },
+            right_sequencer_type: if t < 0.5 {
+                self.right_sequencer_type
+            } else {
+                other.right_sequencer_type
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    state: PerformanceState,
+    /// The kind of edit and its slot, without a slot for global edits.
+    edit: Option<(Edit, Option<SequencerSlot>)>,
+    time: Instant,
+}
+
+/// A bounded undo/redo stack of PerformanceStates.
+#[derive(Clone, Debug)]
+pub struct History {
+    undo_stack: VecDeque<Entry>,
+    redo_stack: Vec<PerformanceState>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        History {
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            capacity,
+        }
+    }
+
+    /// Record the state from before an edit was applied.
+    pub fn record(&mut self, before: PerformanceState, edit: Edit, slot: SequencerSlot) {
+        self.record_at(before, edit, slot, Instant::now());
+    }
+
+    fn record_at(
+        &mut self,
+        before: PerformanceState,
+        edit: Edit,
+        slot: SequencerSlot,
+        now: Instant,
+    ) {
+        self.redo_stack.clear();
+
+        let key = Some((edit, (!edit.is_global()).then_some(slot)));
+        if let Some(last) = self.undo_stack.back_mut() {
+            if last.edit == key && now.duration_since(last.time) < GROUPING_WINDOW {
+                last.time = now;
+                return;
+            }
+        }
+
+        self.push_undo(Entry {
+            state: before,
+            edit: key,
+            time: now,
+        });
+    }
+
+    /// Add an undo entry, dropping the oldest entries beyond the capacity.
+    fn push_undo(&mut self, entry: Entry) {
+        self.undo_stack.push_back(entry);
+        while self.undo_stack.len() > self.capacity {
+            self.undo_stack.pop_front();
+        }
+    }
+
+    /// Returns the state to restore, given the current state which becomes redoable.
+    pub fn undo(&mut self, current: PerformanceState) -> Option<PerformanceState> {
+        let entry = self.undo_stack.pop_back()?;
+        self.redo_stack.push(current);
+        Some(entry.state)
+    }
+
+    /// Returns the state to restore, given the current state which becomes undoable again.
+    pub fn redo(&mut self, current: PerformanceState) -> Option<PerformanceState> {
+        let state = self.redo_stack.pop()?;
+        self.push_undo(Entry {
+            state: current,
+            edit: None,
+            time: Instant::now(),
+        });
+        Some(state)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(HISTORY_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_with_bpm(bpm: f32) -> PerformanceState {
+        PerformanceState {
+            left_state: EuclideanSequencerState::new(),
+            right_state: EuclideanSequencerState::new(),
+            mixer_state: MixerState::new(),
+            bpm,
+            left_step_state: StepSequencerState::new(),
+            right_step_state: StepSequencerState::new(),
+            left_sequencer_type: SequencerType::Euclidean,
+            right_sequencer_type: SequencerType::Euclidean,
+        }
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut history = History::default();
+        history.record(state_with_bpm(120.), Edit::Bpm, SequencerSlot::Left);
+
+        assert_eq!(
+            history.undo(state_with_bpm(121.)),
+            Some(state_with_bpm(120.))
+        );
+        assert!(!history.can_undo());
+        assert_eq!(
+            history.redo(state_with_bpm(120.)),
+            Some(state_with_bpm(121.))
+        );
+        assert!(!history.can_redo());
+        assert_eq!(
+            history.undo(state_with_bpm(121.)),
+            Some(state_with_bpm(120.))
+        );
+    }
+
+    #[test]
+    fn test_grouping_and_capacity() {
+        let mut history = History::new(2);
+        let start = Instant::now();
+
+        // Rapid edits of the same kind are grouped
+        history.record_at(state_with_bpm(1.), Edit::Bpm, SequencerSlot::Left, start);
+        history.record_at(state_with_bpm(2.), Edit::Bpm, SequencerSlot::Left, start);
+        assert_eq!(history.undo_stack.len(), 1);
+
+        // Global edits are grouped whichever slot is active
+        history.record_at(state_with_bpm(2.), Edit::Bpm, SequencerSlot::Right, start);
+        assert_eq!(history.undo_stack.len(), 1);
+
+        // A different kind, slot or a pause starts a new entry
+        history.record_at(state_with_bpm(3.), Edit::Pitch, SequencerSlot::Left, start);
+        history.record_at(state_with_bpm(4.), Edit::Pitch, SequencerSlot::Right, start);
+        history.record_at(
+            state_with_bpm(5.),
+            Edit::Pitch,
+            SequencerSlot::Right,
+            start + GROUPING_WINDOW * 2,
+        );
+        assert_eq!(history.undo_stack.len(), 2);
+        assert_eq!(history.undo(state_with_bpm(6.)), Some(state_with_bpm(5.)));
+        assert_eq!(history.undo(state_with_bpm(5.)), Some(state_with_bpm(4.)));
+        assert_eq!(history.undo(state_with_bpm(4.)), None);
+    }
+}
@@ -2,15 +2,22 @@ use anyhow::Result;
 use device_query::{DeviceQuery, DeviceState, Keycode};
 use log::{error, info};
 use std::collections::HashSet;
+use std::path::Path;
 use std::sync::{Arc, Mutex as SyncMutex};
 use std::thread;
 use std::time::Duration;
 use tokio::sync::{mpsc, RwLock};
 
 use crate::gui::{Event, Message};
+use crate::history::Edit;
+use crate::metronome::METRONOME_FILE;
+use crate::midi::state::MidiCommand;
 use crate::mixer::state::MixerInput;
+use crate::scene::{SCENES_FILE, SCENE_SLOTS};
 use crate::sequencers::euclidean::state::EuclideanSequencerInput;
+use crate::smf::{export, SEQUENCE_EXPORT_FILE};
 use crate::state::SharedState;
+use crate::tempo::{Nudge, COARSE_BPM_STEP, FINE_BPM_STEP};
 
 pub enum Input {
     Bpm(f32),
@@ -40,10 +47,47 @@ pub fn start_polling(tx: mpsc::Sender<HashSet<Keycode>>) {
     });
 }
 
+/// While the active slot plays a step pattern, the arrows move the step cursor on the grid and
+/// the pitch keys edit the selected step. Returns false for keys that are not step keys.
+fn handle_step_key(state: &mut SharedState, key: &Keycode, shift: bool) -> bool {
+    match key {
+        Keycode::Up => state.move_step_cursor(-4),
+        Keycode::Down => state.move_step_cursor(4),
+        Keycode::Left => state.move_step_cursor(-1),
+        Keycode::Right => state.move_step_cursor(1),
+        Keycode::Enter => state.toggle_step(),
+        Keycode::W => state.edit_step(|step| step.change_pitch(1)),
+        Keycode::S => state.edit_step(|step| step.change_pitch(-1)),
+        Keycode::D => state.edit_step(|step| step.change_pitch(12)),
+        Keycode::A => state.edit_step(|step| step.change_pitch(-12)),
+        Keycode::V => state.edit_step(|step| step.change_velocity(if shift { -10 } else { 10 })),
+        Keycode::G => state.edit_step(|step| step.change_gate(if shift { -0.1 } else { 0.1 })),
+        _ => return false,
+    }
+    true
+}
+
+/// Number keys 1 to 8 select a scene slot.
+fn scene_slot(key: &Keycode) -> Option<usize> {
+    let slot = match key {
+        Keycode::Key1 => 0,
+        Keycode::Key2 => 1,
+        Keycode::Key3 => 2,
+        Keycode::Key4 => 3,
+        Keycode::Key5 => 4,
+        Keycode::Key6 => 5,
+        Keycode::Key7 => 6,
+        Keycode::Key8 => 7,
+        _ => return None,
+    };
+    (slot < SCENE_SLOTS).then_some(slot)
+}
+
 // TODO merge this with playback so that all state writing is done in one location
 pub async fn run_input_handler(
     mut rx: mpsc::Receiver<HashSet<Keycode>>,
     tx_gui: Arc<SyncMutex<Option<iced::futures::channel::mpsc::Sender<Message>>>>,
+    tx_midi: mpsc::Sender<MidiCommand>,
     state: Arc<RwLock<SharedState>>,
 ) -> Result<()> {
     let mut last_keys = HashSet::new();
@@ -51,9 +95,46 @@ pub async fn run_input_handler(
     while let Some(keys) = rx.recv().await {
         let diff: Vec<_> = keys.difference(&last_keys).cloned().collect();
 
+        // The transport is nudged only while a nudge key is held
+        let nudge = match (keys.contains(&Keycode::Comma), keys.contains(&Keycode::Dot)) {
+            (true, false) => Nudge::Slower,
+            (false, true) => Nudge::Faster,
+            _ => Nudge::None,
+        };
+        if state.read().await.nudge != nudge {
+            state.write().await.nudge = nudge;
+        }
+
         if !diff.is_empty() {
+            let shift = keys.contains(&Keycode::LShift) || keys.contains(&Keycode::RShift);
+            let bpm_step = if shift {
+                FINE_BPM_STEP
+            } else {
+                COARSE_BPM_STEP
+            };
+            let control = keys.contains(&Keycode::LControl) || keys.contains(&Keycode::RControl);
+
+            let mut panic = false;
+            let mut save_metronome = None;
             let mut w_state = state.write().await;
             for key in diff {
+                if let Some(slot) = scene_slot(&key) {
+                    if shift {
+                        w_state.store_scene(slot, None);
+                        if let Err(e) = w_state.scenes.save(Path::new(SCENES_FILE)) {
+                            error!("{e:#}");
+                        }
+                    } else if control {
+                        w_state.morph_to_scene(slot);
+                    } else {
+                        w_state.recall_scene(slot);
+                    }
+                    continue;
+                }
+                if w_state.is_step_active() && handle_step_key(&mut w_state, &key, shift) {
+                    continue;
+                }
+
                 match key {
                     Keycode::Space => {
                         w_state.playing = !w_state.playing;
@@ -67,27 +148,87 @@ pub async fn run_input_handler(
                         w_state.change_midi_channel();
                         info!("Changing MIDI channel to {}", w_state.midi_channel + 1)
                     }
-                    Keycode::R => w_state.mixer_state.increase_ratio(),
-                    Keycode::F => w_state.mixer_state.decrease_ratio(),
-                    Keycode::Up => w_state.increase_steps(),
-                    Keycode::Down => w_state.decrease_steps(),
-                    Keycode::Right => w_state.increase_pulses(),
-                    Keycode::Left => w_state.decrease_pulses(),
-                    Keycode::W => w_state.change_pitch(1),
-                    Keycode::S => w_state.change_pitch(-1),
-                    Keycode::D => w_state.change_pitch(12),
-                    Keycode::A => w_state.change_pitch(-12),
+                    Keycode::R => {
+                        w_state.apply_edit(Edit::MixerRatio, |s| s.mixer_state.increase_ratio())
+                    }
+                    Keycode::F => {
+                        w_state.apply_edit(Edit::MixerRatio, |s| s.mixer_state.decrease_ratio())
+                    }
+                    Keycode::Up => w_state.apply_edit(Edit::Steps, |s| s.increase_steps()),
+                    Keycode::Down => w_state.apply_edit(Edit::Steps, |s| s.decrease_steps()),
+                    Keycode::Right => w_state.apply_edit(Edit::Pulses, |s| s.increase_pulses()),
+                    Keycode::Left => w_state.apply_edit(Edit::Pulses, |s| s.decrease_pulses()),
+                    Keycode::W => w_state.apply_edit(Edit::Pitch, |s| s.change_pitch(1)),
+                    Keycode::S => w_state.apply_edit(Edit::Pitch, |s| s.change_pitch(-1)),
+                    Keycode::D => w_state.apply_edit(Edit::Pitch, |s| s.change_pitch(12)),
+                    Keycode::A => w_state.apply_edit(Edit::Pitch, |s| s.change_pitch(-12)),
+                    Keycode::T => w_state.tap_tempo(),
+                    Keycode::K => save_metronome = Some(w_state.toggle_metronome()),
+                    Keycode::X => {
+                        let slot = w_state.active_sequencer.clone();
+                        if shift {
+                            w_state.toggle_mute_on_bar(&slot);
+                        } else {
+                            w_state.toggle_mute(&slot);
+                        }
+                    }
+                    Keycode::U => {
+                        let slot = w_state.active_sequencer.clone();
+                        if w_state.is_step_active() {
+                            w_state.unfreeze(&slot);
+                        } else {
+                            w_state.freeze(&slot);
+                        }
+                    }
+                    Keycode::O => {
+                        let slot = w_state.active_sequencer.clone();
+                        w_state.toggle_solo(&slot);
+                    }
+                    Keycode::LeftBracket => {
+                        let slot = w_state.active_sequencer.clone();
+                        w_state.slot_mix_mut(&slot).change_volume(-0.1);
+                    }
+                    Keycode::RightBracket => {
+                        let slot = w_state.active_sequencer.clone();
+                        w_state.slot_mix_mut(&slot).change_volume(0.1);
+                    }
+                    Keycode::Equal => w_state.change_bpm(bpm_step),
+                    Keycode::Minus => w_state.change_bpm(-bpm_step),
+                    Keycode::Z => w_state.undo(),
+                    Keycode::Y => w_state.redo(),
                     Keycode::Tab => w_state.switch_active_sequencer(),
+                    Keycode::M => w_state.toggle_arrangement(),
+                    Keycode::Q => w_state.toggle_recording(),
+                    Keycode::P => panic = true,
+                    Keycode::E => {
+                        let path = Path::new(SEQUENCE_EXPORT_FILE);
+                        match export::write_file(
+                            &w_state.mixed_sequence.notes,
+                            &w_state.export_options(),
+                            path,
+                        ) {
+                            Ok(()) => info!("Exported sequence to {}", path.display()),
+                            Err(e) => error!("{e:#}"),
+                        }
+                    }
                     _ => {}
                 };
             }
 
             drop(w_state);
+            if let Some(metronome) = save_metronome {
+                if let Err(e) = metronome.save(Path::new(METRONOME_FILE)) {
+                    error!("{e:#}");
+                }
+            }
+            if panic {
+                tx_midi.send(MidiCommand::Panic { done: None }).await?;
+            }
             let r_state = state.read().await;
             if let Some(mut tx) = tx_gui.lock().unwrap().clone() {
-                if let Err(e) =
-                    tx.try_send(Message::ReceivedEvent(Event::StateChanged(r_state.clone())))
-                {
+                if let Err(e) = tx.try_send(Message::ReceivedEvent(Event::StateChanged(Box::new(
+                    r_state.clone(),
+                )))) {
                     error!("Error sending Message::ReceivedEvent to GUI: {:?}", e);
                 }
             }
@@ -1,11 +1,22 @@
+pub mod arrangement;
 pub mod gui;
+pub mod history;
 pub mod input;
+pub mod link;
+pub mod metronome;
 pub mod midi;
 pub mod mixer;
 pub mod note;
+pub mod osc;
 pub mod playback;
+pub mod scene;
 pub mod sequencers;
+pub mod smf;
 pub mod state;
+pub mod synth;
+pub mod tempo;
+pub mod transport;
+pub mod web;
 
 pub use gui::Gui;
 pub use input::{run_input_handler, start_polling};
@@ -14,7 +25,9 @@ pub use mixer::{state::MixerState, Mixer};
 pub use note::Sequence;
 pub use playback::PlaybackHandler;
 pub use sequencers::{
+    arpeggiator::ArpeggiatorSequencer,
     common::Sequencer,
     euclidean::{state::EuclideanSequencerState, EuclideanSequencer},
+    step::StepSequencer,
 };
 pub use state::SharedState;
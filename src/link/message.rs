@@ -0,0 +1,186 @@
+use anyhow::{bail, Context, Result};
+
+use crate::link::session::{StartStop, Timeline};
+use crate::tempo::{MAX_BPM, MIN_BPM};
+
+/// Every discovery message starts with the protocol header of Link.
+const PROTOCOL_HEADER: &[u8; 8] = b"_asdp_v\x01";
+
+const TIMELINE_KEY: u32 = u32::from_be_bytes(*b"tmln");
+const SESSION_KEY: u32 = u32::from_be_bytes(*b"sess");
+const START_STOP_KEY: u32 = u32::from_be_bytes(*b"stst");
+
+/// Seconds that a peer is remembered after its last message.
+pub const TTL: u8 = 5;
+
+pub type NodeId = [u8; 8];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageType {
+    /// Sent to the multicast group regularly, and answered with a response.
+    Alive = 1,
+    Response = 2,
+    /// Sent when leaving the session.
+    ByeBye = 3,
+}
+
+/// A discovery message, announcing the timeline and start/stop state of a peer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    pub message_type: MessageType,
+    pub ttl: u8,
+    pub node_id: NodeId,
+    pub session_id: Option<NodeId>,
+    pub timeline: Option<Timeline>,
+    pub start_stop: Option<StartStop>,
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut packet = PROTOCOL_HEADER.to_vec();
+        packet.push(self.message_type as u8);
+        packet.push(self.ttl);
+        packet.extend_from_slice(&0u16.to_be_bytes()); // group
+        packet.extend_from_slice(&self.node_id);
+
+        if let Some(timeline) = &self.timeline {
+            let mut value = Vec::new();
+            value.extend_from_slice(&timeline.micros_per_beat.to_be_bytes());
+            value.extend_from_slice(&timeline.beat_origin.to_be_bytes());
+            value.extend_from_slice(&timeline.time_origin.to_be_bytes());
+            write_entry(&mut packet, TIMELINE_KEY, &value);
+        }
+        if let Some(session_id) = &self.session_id {
+            write_entry(&mut packet, SESSION_KEY, session_id);
+        }
+        if let Some(start_stop) = &self.start_stop {
+            let mut value = vec![start_stop.playing as u8];
+            value.extend_from_slice(&start_stop.beats.to_be_bytes());
+            value.extend_from_slice(&start_stop.timestamp.to_be_bytes());
+            write_entry(&mut packet, START_STOP_KEY, &value);
+        }
+        packet
+    }
+}
+
+fn write_entry(packet: &mut Vec<u8>, key: u32, value: &[u8]) {
+    packet.extend_from_slice(&key.to_be_bytes());
+    packet.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    packet.extend_from_slice(value);
+}
+
+fn read_i64(bytes: &[u8], offset: usize) -> Result<i64> {
+    let bytes = bytes
+        .get(offset..offset + 8)
+        .context("Link entry is too short")?;
+    Ok(i64::from_be_bytes(bytes.try_into()?))
+}
+
+/// Decode a discovery message, skipping entries that are not used, e.g. measurement endpoints.
+pub fn decode(packet: &[u8]) -> Result<Message> {
+    if !packet.starts_with(PROTOCOL_HEADER) || packet.len() < 20 {
+        bail!("Not a Link discovery message");
+    }
+    let message_type = match packet[8] {
+        1 => MessageType::Alive,
+        2 => MessageType::Response,
+        3 => MessageType::ByeBye,
+        other => bail!("Unknown Link message type {other}"),
+    };
+    let mut message = Message {
+        message_type,
+        ttl: packet[9],
+        node_id: packet[12..20].try_into()?,
+        session_id: None,
+        timeline: None,
+        start_stop: None,
+    };
+
+    let mut position = 20;
+    while position + 8 <= packet.len() {
+        let key = u32::from_be_bytes(packet[position..position + 4].try_into()?);
+        let size = u32::from_be_bytes(packet[position + 4..position + 8].try_into()?) as usize;
+        let end = (position + 8)
+            .checked_add(size)
+            .context("Link entry is too long")?;
+        let value = packet
+            .get(position + 8..end)
+            .context("Link entry is too short")?;
+        position = end;
+
+        match key {
+            TIMELINE_KEY => {
+                let timeline = Timeline {
+                    micros_per_beat: read_i64(value, 0)?,
+                    beat_origin: read_i64(value, 8)?,
+                    time_origin: read_i64(value, 16)?,
+                };
+                // Playback is scheduled on the timeline, so tempos that can not be set are
+                // refused
+                if timeline.micros_per_beat <= 0 || !(MIN_BPM..=MAX_BPM).contains(&timeline.bpm()) {
+                    bail!(
+                        "Invalid Link beat length of {} microseconds",
+                        timeline.micros_per_beat
+                    );
+                }
+                message.timeline = Some(timeline)
+            }
+            SESSION_KEY => message.session_id = Some(value.try_into()?),
+            START_STOP_KEY => {
+                message.start_stop = Some(StartStop {
+                    playing: *value.first().context("Link entry is too short")? != 0,
+                    beats: read_i64(value, 1)?,
+                    timestamp: read_i64(value, 9)?,
+                })
+            }
+            _ => {}
+        }
+    }
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let message = Message {
+            message_type: MessageType::Alive,
+            ttl: TTL,
+            node_id: *b"node0001",
+            session_id: Some(*b"session1"),
+            timeline: Some(Timeline {
+                micros_per_beat: 500_000,
+                beat_origin: 4_000_000,
+                time_origin: -12,
+            }),
+            start_stop: Some(StartStop {
+                playing: true,
+                beats: 0,
+                timestamp: 1_000,
+            }),
+        };
+        let mut packet = message.encode();
+        assert_eq!(&packet[20..24], b"tmln");
+        assert_eq!(decode(&packet).unwrap(), message);
+
+        // Unknown entries, like the measurement endpoint, are skipped
+        write_entry(
+            &mut packet,
+            u32::from_be_bytes(*b"mep4"),
+            &[127, 0, 0, 1, 0, 80],
+        );
+        assert_eq!(decode(&packet).unwrap(), message);
+
+        assert!(decode(b"_asdp_v\x01").is_err());
+        assert!(decode(&packet[..packet.len() - 1]).is_err());
+
+        // Timelines with a tempo out of range are refused
+        for micros_per_beat in [0, -500_000, 1, 100_000_000] {
+            let mut message = message.clone();
+            message.timeline.as_mut().unwrap().micros_per_beat = micros_per_beat;
+            assert!(decode(&message.encode()).is_err());
+        }
+    }
+}
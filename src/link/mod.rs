@@ -0,0 +1,231 @@
+pub mod message;
+pub mod session;
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use rand::random;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+use tokio::sync::RwLock;
+use tokio::time::{interval, sleep, Duration, Instant, MissedTickBehavior};
+
+use crate::state::SharedState;
+use crate::tempo::clamp_bpm;
+use message::{decode, MessageType, NodeId};
+use session::{now_micros, Session, StartStop, Timeline};
+
+/// Multicast group and port that Link peers discover each other on.
+const MULTICAST_GROUP: Ipv4Addr = Ipv4Addr::new(224, 76, 78, 75);
+const MULTICAST_PORT: u16 = 20808;
+
+/// How often the shared state is compared with the session.
+const TICK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How often the session is announced to the peers.
+const ALIVE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The Link session as seen by the transport and the GUI.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LinkState {
+    pub enabled: bool,
+    /// The timeline of the session while connected, which bars are aligned to.
+    pub timeline: Option<Timeline>,
+    pub peers: usize,
+}
+
+/// Takes part in a Link session while enabled, sharing the tempo, the beat phase and
+/// start/stop with the peers.
+pub struct LinkHandler {
+    shared_state: Arc<RwLock<SharedState>>,
+    node_id: NodeId,
+}
+
+struct Connection {
+    multicast: UdpSocket,
+    unicast: UdpSocket,
+    session: Session,
+    /// The timeline and start/stop state last synced with the shared state, to tell
+    /// changes of the peers from local ones.
+    timeline: Timeline,
+    start_stop: Option<StartStop>,
+    bpm: f32,
+    playing: bool,
+}
+
+fn multicast_socket() -> Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    // Several peers on the same host listen on the same port
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, MULTICAST_PORT).into())?;
+    socket.join_multicast_v4(&MULTICAST_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_nonblocking(true)?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+impl LinkHandler {
+    pub fn new(shared_state: Arc<RwLock<SharedState>>) -> Self {
+        LinkHandler {
+            shared_state,
+            node_id: random(),
+        }
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        loop {
+            if !self.shared_state.read().await.link.enabled {
+                sleep(TICK_INTERVAL).await;
+                continue;
+            }
+            match self.connect().await {
+                Ok(mut connection) => {
+                    info!("Joined the Link session");
+                    if let Err(e) = self.run_connection(&mut connection).await {
+                        warn!("Link stopped: {e:#}");
+                    }
+                    let bye = connection.session.message(MessageType::ByeBye).encode();
+                    let _ = connection
+                        .unicast
+                        .send_to(&bye, (MULTICAST_GROUP, MULTICAST_PORT))
+                        .await;
+                    info!("Left the Link session");
+                }
+                Err(e) => warn!("{e:#}"),
+            }
+
+            let mut w_state = self.shared_state.write().await;
+            w_state.link = LinkState::default();
+        }
+    }
+
+    async fn connect(&self) -> Result<Connection> {
+        let multicast = multicast_socket().context("Failed to join the Link multicast group")?;
+        let unicast = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+            .await
+            .context("Failed to open a socket for Link")?;
+        let r_state = self.shared_state.read().await;
+        let session = Session::new(self.node_id, r_state.bpm, now_micros());
+        Ok(Connection {
+            multicast,
+            unicast,
+            timeline: session.timeline,
+            start_stop: session.start_stop,
+            session,
+            bpm: r_state.bpm,
+            playing: r_state.playing,
+        })
+    }
+
+    /// Exchange messages with the peers until Link is disabled.
+    async fn run_connection(&self, connection: &mut Connection) -> Result<()> {
+        let mut tick = interval(TICK_INTERVAL);
+        tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut last_alive = None;
+        let (mut multicast_buffer, mut unicast_buffer) = ([0; 512], [0; 512]);
+
+        loop {
+            let (size, address) = tokio::select! {
+                received = connection.multicast.recv_from(&mut multicast_buffer) => {
+                    let (size, address) = received?;
+                    (size, Some((address, true)))
+                }
+                received = connection.unicast.recv_from(&mut unicast_buffer) => {
+                    let (size, address) = received?;
+                    (size, Some((address, false)))
+                }
+                _ = tick.tick() => (0, None),
+            };
+
+            match address {
+                Some((address, multicast)) => {
+                    let packet = if multicast {
+                        &multicast_buffer[..size]
+                    } else {
+                        &unicast_buffer[..size]
+                    };
+                    self.receive(connection, packet, address).await;
+                }
+                None => {
+                    if !self.sync(connection).await {
+                        return Ok(());
+                    }
+                    let local_change = connection.session.timeline != connection.timeline
+                        || connection.session.start_stop != connection.start_stop;
+                    if local_change
+                        || last_alive.is_none_or(|last: Instant| last.elapsed() >= ALIVE_INTERVAL)
+                    {
+                        let alive = connection.session.message(MessageType::Alive).encode();
+                        connection
+                            .unicast
+                            .send_to(&alive, (MULTICAST_GROUP, MULTICAST_PORT))
+                            .await?;
+                        last_alive = Some(Instant::now());
+                    }
+                }
+            }
+            self.publish(connection).await;
+        }
+    }
+
+    async fn receive(&self, connection: &mut Connection, packet: &[u8], address: SocketAddr) {
+        let message = match decode(packet) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Invalid Link message from {address}: {e:#}");
+                return;
+            }
+        };
+        if let Some(response) = connection.session.receive(&message, now_micros()) {
+            if let Err(e) = connection
+                .unicast
+                .send_to(&response.encode(), address)
+                .await
+            {
+                warn!("Failed to answer Link peer {address}: {e}");
+            }
+        }
+    }
+
+    /// Apply local changes of the tempo and the transport to the session, returning whether
+    /// Link is still enabled.
+    async fn sync(&self, connection: &mut Connection) -> bool {
+        let now = now_micros();
+        connection.session.expire_peers(now);
+        let r_state = self.shared_state.read().await;
+        if r_state.bpm != connection.bpm {
+            connection.session.set_tempo(r_state.bpm, now);
+            connection.bpm = r_state.bpm;
+        }
+        if r_state.playing != connection.playing {
+            connection.session.set_playing(r_state.playing, now);
+            connection.playing = r_state.playing;
+        }
+        r_state.link.enabled
+    }
+
+    /// Follow changes of the session made by peers in the shared state.
+    async fn publish(&self, connection: &mut Connection) {
+        let session = &connection.session;
+        let mut w_state = self.shared_state.write().await;
+        if session.timeline != connection.timeline {
+            let bpm = clamp_bpm(session.timeline.bpm());
+            if (bpm - w_state.bpm).abs() > 0.001 {
+                info!("Link tempo {bpm:.2} BPM");
+                w_state.bpm = bpm;
+            }
+            connection.timeline = session.timeline;
+            connection.bpm = w_state.bpm;
+        }
+        if session.start_stop != connection.start_stop {
+            if let Some(start_stop) = session.start_stop {
+                w_state.playing = start_stop.playing;
+            }
+            connection.start_stop = session.start_stop;
+            connection.playing = w_state.playing;
+        }
+        w_state.link.timeline = Some(session.timeline);
+        w_state.link.peers = session.peers();
+    }
+}
@@ -0,0 +1,254 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::link::message::{Message, MessageType, NodeId, TTL};
+
+/// Microseconds on the clock that the timelines of all peers refer to.
+pub fn now_micros() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_micros() as i64)
+        .unwrap_or_default()
+}
+
+/// Maps time to beats: at `time_origin` (microseconds) the beat is `beat_origin`
+/// (microbeats), and beats advance at the tempo.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timeline {
+    pub micros_per_beat: i64,
+    pub beat_origin: i64,
+    pub time_origin: i64,
+}
+
+impl Timeline {
+    pub fn new(bpm: f32, now: i64) -> Self {
+        Timeline {
+            micros_per_beat: (60_000_000.0 / bpm.max(1.0) as f64).round() as i64,
+            beat_origin: 0,
+            time_origin: now,
+        }
+    }
+
+    /// The tempo, rounded to hundredths as the beat length in microseconds is not exact.
+    pub fn bpm(&self) -> f32 {
+        ((6_000_000_000.0 / self.micros_per_beat as f64).round() / 100.0) as f32
+    }
+
+    // Origins come from peers, so the arithmetic saturates instead of overflowing
+    pub fn beat_at(&self, time: i64) -> f64 {
+        self.beat_origin as f64 / 1e6
+            + time.saturating_sub(self.time_origin) as f64 / self.micros_per_beat as f64
+    }
+
+    pub fn time_at(&self, beat: f64) -> i64 {
+        self.time_origin.saturating_add(
+            ((beat - self.beat_origin as f64 / 1e6) * self.micros_per_beat as f64) as i64,
+        )
+    }
+
+    /// The first bar boundary at or after a time, for bars of `quantum` beats.
+    pub fn next_bar(&self, time: i64, quantum: f64) -> f64 {
+        (self.beat_at(time) / quantum).ceil() * quantum
+    }
+
+    /// A timeline with a new tempo that continues from the current beat, so the phase is kept.
+    /// Its beat origin is later than that of this timeline, so peers adopt it.
+    pub fn with_tempo(&self, bpm: f32, now: i64) -> Self {
+        let beat = ((self.beat_at(now) * 1e6) as i64).max(self.beat_origin.saturating_add(1));
+        Timeline {
+            micros_per_beat: Timeline::new(bpm, now).micros_per_beat,
+            beat_origin: beat,
+            time_origin: self.time_at(beat as f64 / 1e6),
+        }
+    }
+}
+
+/// Whether the session is playing, changed at `timestamp` (microseconds) on `beats`
+/// (microbeats).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StartStop {
+    pub playing: bool,
+    pub beats: i64,
+    pub timestamp: i64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Peer {
+    node_id: NodeId,
+    session_id: NodeId,
+    timeline: Timeline,
+    start_stop: Option<StartStop>,
+    /// Time after which the peer is forgotten, in microseconds.
+    expires: i64,
+}
+
+/// The view of one participant on the Link session: its own timeline and start/stop state,
+/// and the peers it has seen. Peers join the session with the lowest id, and adopt the
+/// timeline with the latest beat origin and the latest start/stop state of that session.
+#[derive(Clone, Debug)]
+pub struct Session {
+    node_id: NodeId,
+    session_id: NodeId,
+    pub timeline: Timeline,
+    pub start_stop: Option<StartStop>,
+    peers: Vec<Peer>,
+}
+
+impl Session {
+    /// Start a session of our own, which is given up when joining a session with a lower id.
+    pub fn new(node_id: NodeId, bpm: f32, now: i64) -> Self {
+        Session {
+            node_id,
+            session_id: node_id,
+            timeline: Timeline::new(bpm, now),
+            start_stop: None,
+            peers: Vec::new(),
+        }
+    }
+
+    pub fn peers(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn message(&self, message_type: MessageType) -> Message {
+        Message {
+            message_type,
+            ttl: TTL,
+            node_id: self.node_id,
+            session_id: Some(self.session_id),
+            timeline: Some(self.timeline),
+            start_stop: self.start_stop,
+        }
+    }
+
+    pub fn set_tempo(&mut self, bpm: f32, now: i64) {
+        self.timeline = self.timeline.with_tempo(bpm, now);
+    }
+
+    pub fn set_playing(&mut self, playing: bool, now: i64) {
+        self.start_stop = Some(StartStop {
+            playing,
+            beats: (self.timeline.beat_at(now) * 1e6) as i64,
+            timestamp: now,
+        });
+    }
+
+    /// Forget peers that have not been heard of for longer than their TTL.
+    pub fn expire_peers(&mut self, now: i64) {
+        self.peers.retain(|peer| peer.expires > now);
+    }
+
+    /// Handle a message of another peer, returning the response to an alive message.
+    pub fn receive(&mut self, message: &Message, now: i64) -> Option<Message> {
+        if message.node_id == self.node_id {
+            return None;
+        }
+        self.peers.retain(|peer| peer.node_id != message.node_id);
+        if message.message_type == MessageType::ByeBye {
+            return None;
+        }
+        let (Some(session_id), Some(timeline)) = (message.session_id, message.timeline) else {
+            return None;
+        };
+        self.peers.push(Peer {
+            node_id: message.node_id,
+            session_id,
+            timeline,
+            start_stop: message.start_stop,
+            expires: now + message.ttl as i64 * 1_000_000,
+        });
+        self.follow_peers();
+
+        (message.message_type == MessageType::Alive).then(|| self.message(MessageType::Response))
+    }
+
+    fn follow_peers(&mut self) {
+        if let Some(peer) = self
+            .peers
+            .iter()
+            .filter(|peer| peer.session_id < self.session_id)
+            .min_by_key(|peer| peer.session_id)
+        {
+            self.session_id = peer.session_id;
+            self.timeline = peer.timeline;
+            self.start_stop = peer.start_stop;
+        }
+
+        for peer in self
+            .peers
+            .iter()
+            .filter(|p| p.session_id == self.session_id)
+        {
+            if peer.timeline.beat_origin > self.timeline.beat_origin {
+                self.timeline = peer.timeline;
+            }
+            if let Some(start_stop) = peer.start_stop {
+                if self
+                    .start_stop
+                    .is_none_or(|own| start_stop.timestamp > own.timestamp)
+                {
+                    self.start_stop = Some(start_stop);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeline() {
+        let timeline = Timeline::new(120.0, 1_000_000);
+        assert_eq!(timeline.beat_at(1_500_000), 1.0);
+        assert_eq!(timeline.time_at(2.0), 2_000_000);
+        assert_eq!(timeline.next_bar(1_500_000, 4.0), 4.0);
+        assert_eq!(timeline.next_bar(1_500_000, 3.5), 3.5);
+
+        // Changing the tempo keeps the beat
+        let faster = timeline.with_tempo(240.0, 2_000_000);
+        assert_eq!(faster.beat_at(2_000_000), 2.0);
+        assert_eq!(faster.beat_at(2_250_000), 3.0);
+        assert!(faster.beat_origin > timeline.beat_origin);
+
+        // Origins of peers far from the clock saturate instead of overflowing
+        let far = Timeline {
+            micros_per_beat: 500_000,
+            beat_origin: i64::MAX,
+            time_origin: i64::MIN,
+        };
+        assert!(far.beat_at(i64::MAX).is_finite());
+        assert_eq!(far.time_at(-1e30), i64::MIN);
+        far.with_tempo(120.0, 0);
+    }
+
+    #[test]
+    fn test_sessions_converge() {
+        let mut a = Session::new(*b"aaaaaaaa", 120.0, 0);
+        let mut b = Session::new(*b"bbbbbbbb", 90.0, 300_000);
+
+        // b joins the session of a, which has the lower id, and adopts its timeline
+        let response = b
+            .receive(&a.message(MessageType::Alive), 1_000_000)
+            .unwrap();
+        assert_eq!(b.timeline, a.timeline);
+        a.receive(&response, 1_000_000);
+        assert_eq!((a.peers(), b.peers()), (1, 1));
+
+        // Tempo and start/stop changes of any peer are followed by the others
+        b.set_tempo(100.0, 2_000_000);
+        b.set_playing(true, 2_000_000);
+        a.receive(&b.message(MessageType::Alive), 2_000_000);
+        assert_eq!(a.timeline.bpm(), 100.0);
+        assert_eq!(a.start_stop.map(|s| s.playing), Some(true));
+        assert_eq!(a.timeline.beat_at(3_000_000), b.timeline.beat_at(3_000_000));
+
+        // Own messages, e.g. looped back by multicast, are ignored
+        assert_eq!(a.receive(&a.message(MessageType::Alive), 0), None);
+
+        a.receive(&b.message(MessageType::ByeBye), 3_000_000);
+        assert_eq!(a.peers(), 0);
+        b.expire_peers(1_000_000 + 6_000_000);
+        assert_eq!(b.peers(), 0);
+    }
+}
@@ -1,17 +1,38 @@
 use anyhow::Result;
 use device_query::Keycode;
 use env_logger::Builder;
+use log::{info, warn};
 use std::{
     collections::HashSet,
+    path::Path,
     sync::{Arc, Mutex as SyncMutex},
 };
 use tokio::signal;
-use tokio::sync::{mpsc, RwLock};
+use tokio::sync::{mpsc, oneshot, RwLock};
+use tokio::time::{timeout, Duration};
 
 use sequencer::{
-    gui::Message, midi::state::MidiCommand, note::MixedSequence, run_input_handler,
-    sequencers::euclidean::gui::Gui as EuclideanGui, start_polling, state::SequencerSlot,
-    EuclideanSequencer, Gui, MidiHandler, Mixer, PlaybackHandler, Sequence, Sequencer, SharedState,
+    arrangement::{Arrangement, ARRANGEMENT_FILE},
+    gui::Message,
+    link::LinkHandler,
+    metronome::{MetronomeConfig, METRONOME_FILE},
+    midi::{
+        learn::{MidiMap, MIDI_MAP_FILE},
+        ports::{MidiPortConfig, MIDI_PORTS_FILE},
+        routing::{Routing, ROUTING_FILE},
+        state::MidiCommand,
+    },
+    note::MixedSequence,
+    osc::{OscConfig, OscServer, OSC_FILE},
+    run_input_handler,
+    scene::{SceneBank, SCENES_FILE},
+    sequencers::{euclidean::gui::Gui as EuclideanGui, step::gui::Gui as StepGui},
+    start_polling,
+    state::SequencerSlot,
+    synth::timbre::{SynthConfig, SYNTH_FILE},
+    web::{WebConfig, WebServer, WEB_FILE},
+    ArpeggiatorSequencer, EuclideanSequencer, Gui, MidiHandler, Mixer, PlaybackHandler, Sequence,
+    Sequencer, SharedState, StepSequencer,
 };
 
 #[tokio::main]
@@ -32,6 +53,36 @@ async fn main() -> Result<()> {
 
     let shared_state: Arc<RwLock<SharedState>> = Arc::new(RwLock::new(SharedState::new(120.)));
 
+    // Scenes stored in a previous session
+    match SceneBank::load(Path::new(SCENES_FILE)) {
+        Ok(scenes) => {
+            info!("Loaded scenes from {SCENES_FILE}");
+            shared_state.write().await.scenes = scenes;
+        }
+        Err(e) => info!("No scenes loaded: {e:#}"),
+    }
+    match Arrangement::load(Path::new(ARRANGEMENT_FILE)) {
+        Ok(arrangement) => {
+            info!("Loaded arrangement from {ARRANGEMENT_FILE}");
+            shared_state.write().await.arrangement = arrangement;
+        }
+        Err(e) => info!("No arrangement loaded: {e:#}"),
+    }
+    match MidiMap::load(Path::new(MIDI_MAP_FILE)) {
+        Ok(midi_map) => {
+            info!("Loaded MIDI mappings from {MIDI_MAP_FILE}");
+            shared_state.write().await.midi_map = midi_map;
+        }
+        Err(e) => info!("No MIDI mappings loaded: {e:#}"),
+    }
+    match MetronomeConfig::load(Path::new(METRONOME_FILE)) {
+        Ok(metronome) => {
+            info!("Loaded metronome settings from {METRONOME_FILE}");
+            shared_state.write().await.metronome = metronome;
+        }
+        Err(e) => info!("No metronome settings loaded: {e:#}"),
+    }
+
     let tx_gui: Arc<SyncMutex<Option<iced::futures::channel::mpsc::Sender<Message>>>> =
         Arc::new(SyncMutex::new(None));
 
@@ -46,7 +97,7 @@ async fn main() -> Result<()> {
         sequencer_a.run().await.unwrap();
     });
 
-    // both Euclidean for now to keep it simple
+    // The Euclidean sequencer of the right slot, playing while the slot is set to that type
     let mut sequencer_b = EuclideanSequencer::new(
         SequencerSlot::Right,
         tx_sequence.clone(),
@@ -55,6 +106,16 @@ async fn main() -> Result<()> {
     sequencer_b.generate_sequence().await;
     tokio::spawn(async move { sequencer_b.run().await });
 
+    // Step sequencers and arpeggiators, playing their slot while it is set to their type
+    for slot in [SequencerSlot::Left, SequencerSlot::Right] {
+        let mut step_sequencer =
+            StepSequencer::new(slot.clone(), tx_sequence.clone(), shared_state.clone());
+        tokio::spawn(async move { step_sequencer.run().await });
+        let mut arpeggiator =
+            ArpeggiatorSequencer::new(slot, tx_sequence.clone(), shared_state.clone());
+        tokio::spawn(async move { arpeggiator.run().await });
+    }
+
     let mut sequence_mixer = Mixer::new(shared_state.clone(), tx_mixed_sequence, rx_sequence);
     sequence_mixer.mix().await;
     tokio::spawn(async move { sequence_mixer.run().await });
@@ -63,10 +124,47 @@ async fn main() -> Result<()> {
     start_polling(tx_keys);
     let shared_state_input = shared_state.clone();
     let tx_gui_input = tx_gui.clone();
-    tokio::spawn(async move { run_input_handler(rx_keys, tx_gui_input, shared_state_input).await });
+    let tx_midi_input = tx_midi.clone();
+    tokio::spawn(async move {
+        run_input_handler(rx_keys, tx_gui_input, tx_midi_input, shared_state_input).await
+    });
 
     // Playback
-    let mut midi_handler = MidiHandler::new(rx_midi)?;
+    let port_config = match MidiPortConfig::load(Path::new(MIDI_PORTS_FILE)) {
+        Ok(port_config) => port_config,
+        Err(e) => {
+            info!("No MIDI ports loaded: {e:#}");
+            MidiPortConfig::default()
+        }
+    };
+    let routing = match Routing::load(Path::new(ROUTING_FILE)) {
+        Ok(routing) => {
+            info!("Loaded MIDI routing from {ROUTING_FILE}");
+            routing
+        }
+        Err(e) => {
+            info!("No MIDI routing loaded: {e:#}");
+            Routing::default()
+        }
+    };
+    let synth_config = match SynthConfig::load(Path::new(SYNTH_FILE)) {
+        Ok(synth_config) => {
+            info!("Loaded synth settings from {SYNTH_FILE}");
+            synth_config
+        }
+        Err(e) => {
+            info!("No synth settings loaded: {e:#}");
+            SynthConfig::default()
+        }
+    };
+    let mut midi_handler = MidiHandler::new(
+        rx_midi,
+        tx_gui.clone(),
+        shared_state.clone(),
+        port_config,
+        routing,
+        synth_config,
+    )?;
     tokio::spawn(async move {
         let _ = midi_handler.run().await;
     });
@@ -79,25 +177,103 @@ async fn main() -> Result<()> {
     );
     tokio::spawn(async move { playback_handler.run().await });
 
+    // Link session, joined while enabled in the GUI
+    let mut link_handler = LinkHandler::new(shared_state.clone());
+    tokio::spawn(async move { link_handler.run().await });
+
+    // OSC remote control
+    let osc_config = match OscConfig::load(Path::new(OSC_FILE)) {
+        Ok(osc_config) => {
+            info!("Loaded OSC settings from {OSC_FILE}");
+            osc_config
+        }
+        Err(e) => {
+            info!("No OSC settings loaded: {e:#}");
+            OscConfig::default()
+        }
+    };
+    if osc_config.enabled {
+        match OscServer::bind(&osc_config, shared_state.clone()).await {
+            Ok(mut osc_server) => {
+                tokio::spawn(async move {
+                    if let Err(e) = osc_server.run().await {
+                        warn!("OSC server stopped: {e:#}");
+                    }
+                });
+            }
+            Err(e) => warn!("{e:#}"),
+        }
+    }
+
+    // Web control page and API
+    let web_config = match WebConfig::load(Path::new(WEB_FILE)) {
+        Ok(web_config) => {
+            info!("Loaded web settings from {WEB_FILE}");
+            web_config
+        }
+        Err(e) => {
+            info!("No web settings loaded: {e:#}");
+            WebConfig::default()
+        }
+    };
+    if web_config.enabled {
+        match WebServer::bind(&web_config, shared_state.clone()).await {
+            Ok(mut web_server) => {
+                tokio::spawn(async move {
+                    if let Err(e) = web_server.run().await {
+                        warn!("Web server stopped: {e:#}");
+                    }
+                });
+            }
+            Err(e) => warn!("{e:#}"),
+        }
+    }
+
     // GUI
     let gui_sequencer_left = EuclideanGui::new(SequencerSlot::Left);
     let gui_sequencer_right = EuclideanGui::new(SequencerSlot::Right);
+    let gui_step_left = StepGui::new(SequencerSlot::Left);
+    let gui_step_right = StepGui::new(SequencerSlot::Right);
 
-    Gui::run(
-        tx_gui.clone(),
-        tx_midi,
-        gui_sequencer_left,
-        gui_sequencer_right,
-    )?;
-
-    // Shutdown
+    // Shutdown, turning off all notes first
+    let tx_midi_ctrl_c = tx_midi.clone();
     let _ctrl_c_handle = tokio::spawn(async move {
         signal::ctrl_c()
             .await
             .expect("Failed to install Ctrl+C handler");
         println!("Ctrl+C received, exiting...");
+        midi_panic(&tx_midi_ctrl_c).await;
         std::process::exit(0);
     });
 
+    Gui::run(
+        tx_gui.clone(),
+        tx_midi.clone(),
+        shared_state.clone(),
+        gui_sequencer_left,
+        gui_sequencer_right,
+        gui_step_left,
+        gui_step_right,
+    )?;
+
+    midi_panic(&tx_midi).await;
+
     Ok(())
 }
+
+/// Turn off all notes, waiting at most a second for the MIDI handler.
+async fn midi_panic(tx_midi: &mpsc::Sender<MidiCommand>) {
+    let (done, rx_done) = oneshot::channel();
+    let panic = async {
+        tx_midi
+            .send(MidiCommand::Panic { done: Some(done) })
+            .await?;
+        rx_done.await?;
+        anyhow::Ok(())
+    };
+    match timeout(Duration::from_secs(1), panic).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => warn!("Failed to turn off all notes: {e:#}"),
+        Err(e) => warn!("Failed to turn off all notes: {e}"),
+    }
+}
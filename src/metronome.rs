@@ -0,0 +1,148 @@
+use anyhow::{Context, Result};
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::transport::{Position, TimeSignature};
+
+/// File that the metronome settings are saved to and loaded from, relative to the working
+/// directory.
+pub const METRONOME_FILE: &str = "metronome.toml";
+
+/// Length of a click sent over MIDI, in milliseconds.
+pub const CLICK_MILLIS: u64 = 50;
+
+/// Where the click is played.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClickOutput {
+    /// A note on the MIDI outputs, or on the outputs the click is routed to.
+    #[default]
+    Midi,
+    /// A short beep on the built-in synth.
+    Synth,
+}
+
+impl ClickOutput {
+    pub const ALL: [ClickOutput; 2] = [ClickOutput::Midi, ClickOutput::Synth];
+}
+
+impl fmt::Display for ClickOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClickOutput::Midi => write!(f, "MIDI"),
+            ClickOutput::Synth => write!(f, "Built-in synth"),
+        }
+    }
+}
+
+/// Settings of the metronome, which clicks on every beat with an accent on the downbeat.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetronomeConfig {
+    pub enabled: bool,
+    pub output: ClickOutput,
+    /// MIDI channel (0-15), by default the General MIDI drum channel.
+    pub channel: u8,
+    pub note: u8,
+    pub velocity: u8,
+    /// Note and velocity of the first beat of every bar.
+    pub accent_note: u8,
+    pub accent_velocity: u8,
+    /// Bars that are counted in before the transport starts while recording is armed.
+    pub count_in_bars: usize,
+}
+
+impl Default for MetronomeConfig {
+    /// Low and high wood block on the General MIDI drum channel.
+    fn default() -> Self {
+        MetronomeConfig {
+            enabled: false,
+            output: ClickOutput::Midi,
+            channel: 9,
+            note: 77,
+            velocity: 90,
+            accent_note: 76,
+            accent_velocity: 120,
+            count_in_bars: 1,
+        }
+    }
+}
+
+/// A click to play, with the MIDI channel and note or the pitch on the built-in synth.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Click {
+    pub output: ClickOutput,
+    pub channel: u8,
+    pub note: u8,
+    pub velocity: u8,
+}
+
+impl MetronomeConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path).with_context(|| {
+            format!("Failed to read metronome settings from {}", path.display())
+        })?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse metronome settings from {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents = toml::to_string(self).context("Failed to serialize metronome settings")?;
+        fs::write(path, contents)
+            .with_context(|| format!("Failed to write metronome settings to {}", path.display()))
+    }
+
+    /// The click at a position of the transport, if a beat starts there.
+    pub fn click(&self, position: &Position) -> Option<Click> {
+        if position.step != 1 {
+            return None;
+        }
+        let (note, velocity) = if position.beat == 1 {
+            (self.accent_note, self.accent_velocity)
+        } else {
+            (self.note, self.velocity)
+        };
+        Some(Click {
+            output: self.output,
+            channel: self.channel & 0x0F,
+            note: note & 0x7F,
+            velocity: velocity & 0x7F,
+        })
+    }
+}
+
+/// The position within the count-in, while `remaining` steps are left. A count-in lasts
+/// whole bars, so it always ends on a bar boundary.
+pub fn count_in_position(remaining: usize, time_signature: &TimeSignature) -> Position {
+    let steps_per_bar = time_signature.steps_per_bar();
+    Position::at(
+        (steps_per_bar - remaining % steps_per_bar) % steps_per_bar,
+        time_signature,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clicks() {
+        let config = MetronomeConfig::default();
+        let time_signature = TimeSignature::new(3, 4);
+        let clicks = (0..12)
+            .map(|step| config.click(&Position::at(step, &time_signature)))
+            .collect::<Vec<_>>();
+        assert_eq!(clicks[0].map(|c| c.note), Some(config.accent_note));
+        assert_eq!(clicks[1], None);
+        assert_eq!(clicks[4].map(|c| c.note), Some(config.note));
+        assert_eq!(clicks[8].map(|c| c.velocity), Some(config.velocity));
+        assert_eq!(clicks.iter().flatten().count(), 3);
+
+        // A count-in of two bars starts with the accent and ends before a bar
+        let remaining = 2 * time_signature.steps_per_bar();
+        assert!(count_in_position(remaining, &time_signature).is_bar_start());
+        assert_eq!(count_in_position(1, &time_signature).to_string(), "1.3.4");
+    }
+}
@@ -0,0 +1,40 @@
+use std::fmt;
+
+/// Errors of the MIDI input and output, and of the built-in synth, shown in the GUI.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MidiError {
+    /// The MIDI client could not be created, e.g. because the ALSA sequencer is not available.
+    Client(String),
+    PortNotFound(String),
+    Connect {
+        port: String,
+        reason: String,
+    },
+    Send {
+        port: String,
+        reason: String,
+    },
+    /// The output port is gone, and notes are dropped or buffered until it returns.
+    Disconnected(String),
+    /// The built-in synth could not play on the audio device.
+    Audio(String),
+}
+
+impl fmt::Display for MidiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MidiError::Client(reason) => write!(f, "Failed to create MIDI client: {reason}"),
+            MidiError::PortNotFound(port) => write!(f, "No MIDI port named {port}"),
+            MidiError::Connect { port, reason } => {
+                write!(f, "Failed to connect to MIDI port {port}: {reason}")
+            }
+            MidiError::Send { port, reason } => {
+                write!(f, "Failed to send to MIDI port {port}: {reason}")
+            }
+            MidiError::Disconnected(port) => write!(f, "MIDI port {port} is disconnected"),
+            MidiError::Audio(reason) => write!(f, "Failed to start the built-in synth: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for MidiError {}
@@ -0,0 +1,325 @@
+use anyhow::{Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// File that MIDI mappings are saved to and loaded from, relative to the working directory.
+pub const MIDI_MAP_FILE: &str = "midi_map.toml";
+
+/// A parameter that can be controlled from a hardware controller.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Parameter {
+    #[default]
+    Steps,
+    Pulses,
+    Phase,
+    Pitch,
+    MixerRatio,
+    Bpm,
+    PlayStop,
+    ActiveSlot,
+    Mute,
+    Solo,
+    Volume,
+    MuteNextBar,
+}
+
+impl Parameter {
+    pub const ALL: [Parameter; 12] = [
+        Parameter::Steps,
+        Parameter::Pulses,
+        Parameter::Phase,
+        Parameter::Pitch,
+        Parameter::MixerRatio,
+        Parameter::Bpm,
+        Parameter::PlayStop,
+        Parameter::ActiveSlot,
+        Parameter::Mute,
+        Parameter::Solo,
+        Parameter::Volume,
+        Parameter::MuteNextBar,
+    ];
+
+    /// Range that an absolute controller is scaled to by default.
+    pub fn default_range(&self) -> (f32, f32) {
+        match self {
+            Parameter::Steps => (1.0, 16.0),
+            Parameter::Pulses => (0.0, 16.0),
+            Parameter::Phase => (0.0, 15.0),
+            Parameter::Pitch => (20.0, 108.0),
+            Parameter::MixerRatio => (0.0, 1.0),
+            Parameter::Bpm => (40.0, 240.0),
+            Parameter::Volume => (0.0, 1.0),
+            Parameter::PlayStop
+            | Parameter::ActiveSlot
+            | Parameter::Mute
+            | Parameter::Solo
+            | Parameter::MuteNextBar => (0.0, 1.0),
+        }
+    }
+
+    /// Change of the parameter for every tick of a relative controller.
+    pub fn increment(&self) -> f32 {
+        match self {
+            Parameter::MixerRatio | Parameter::Volume => 0.01,
+            _ => 1.0,
+        }
+    }
+
+    /// Toggles are triggered by a button instead of set to a value.
+    pub fn is_toggle(&self) -> bool {
+        matches!(
+            self,
+            Parameter::PlayStop
+                | Parameter::ActiveSlot
+                | Parameter::Mute
+                | Parameter::Solo
+                | Parameter::MuteNextBar
+        )
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Parameter::Steps => write!(f, "Steps"),
+            Parameter::Pulses => write!(f, "Pulses"),
+            Parameter::Phase => write!(f, "Phase"),
+            Parameter::Pitch => write!(f, "Pitch"),
+            Parameter::MixerRatio => write!(f, "Mixer ratio"),
+            Parameter::Bpm => write!(f, "BPM"),
+            Parameter::PlayStop => write!(f, "Play/stop"),
+            Parameter::ActiveSlot => write!(f, "Active slot"),
+            Parameter::Mute => write!(f, "Mute"),
+            Parameter::Solo => write!(f, "Solo"),
+            Parameter::Volume => write!(f, "Volume"),
+            Parameter::MuteNextBar => write!(f, "Mute next bar"),
+        }
+    }
+}
+
+/// A control on a hardware controller, identified by its MIDI channel (0-15) and number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Control {
+    ControlChange { channel: u8, number: u8 },
+    Note { channel: u8, number: u8 },
+}
+
+impl fmt::Display for Control {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Control::ControlChange { channel, number } => {
+                write!(f, "CC {} ch. {}", number, channel + 1)
+            }
+            Control::Note { channel, number } => write!(f, "Note {} ch. {}", number, channel + 1),
+        }
+    }
+}
+
+/// How the value of a control is interpreted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MappingMode {
+    /// The value 0-127 is scaled to the range of the mapping, e.g. for knobs and faders.
+    #[default]
+    Absolute,
+    /// The value is a signed change, as sent by endless encoders: 1-63 turn up and 65-127
+    /// (two's complement) turn down.
+    Relative,
+}
+
+impl MappingMode {
+    pub const ALL: [MappingMode; 2] = [MappingMode::Absolute, MappingMode::Relative];
+}
+
+impl fmt::Display for MappingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MappingMode::Absolute => write!(f, "Absolute"),
+            MappingMode::Relative => write!(f, "Relative"),
+        }
+    }
+}
+
+/// Binds a control to a parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Mapping {
+    pub control: Control,
+    pub parameter: Parameter,
+    #[serde(default)]
+    pub mode: MappingMode,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Mapping {
+    pub fn new(control: Control, parameter: Parameter, mode: MappingMode) -> Self {
+        let (min, max) = parameter.default_range();
+        Mapping {
+            control,
+            parameter,
+            mode,
+            min,
+            max,
+        }
+    }
+
+    /// New value of the parameter for a control value, given its current value.
+    pub fn value(&self, value: u8, current: f32) -> f32 {
+        let (low, high) = (self.min.min(self.max), self.min.max(self.max));
+        match self.mode {
+            // min may be larger than max to invert the control
+            MappingMode::Absolute => self.min + (self.max - self.min) * value as f32 / 127.0,
+            MappingMode::Relative => {
+                let delta = match value {
+                    1..=63 => value as f32,
+                    65..=127 => value as f32 - 128.0,
+                    _ => 0.0,
+                };
+                current + delta * self.parameter.increment()
+            }
+        }
+        .clamp(low, high)
+    }
+}
+
+impl fmt::Display for Mapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.parameter.is_toggle() {
+            write!(f, "{} - {}", self.control, self.parameter)
+        } else {
+            write!(
+                f,
+                "{} - {} ({}, {} to {})",
+                self.control, self.parameter, self.mode, self.min, self.max
+            )
+        }
+    }
+}
+
+/// What to do after a control was moved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ControlAction {
+    /// The control was bound to the parameter that was being learned.
+    Learned(Mapping),
+    Set(Parameter, f32),
+    Trigger(Parameter),
+}
+
+/// All mappings of controls to parameters, and the parameter being learned.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MidiMap {
+    #[serde(default)]
+    mappings: Vec<Mapping>,
+    #[serde(skip)]
+    pub learning: Option<(Parameter, MappingMode)>,
+}
+
+impl MidiMap {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read MIDI mappings from {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse MIDI mappings from {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents = toml::to_string(self).context("Failed to serialize MIDI mappings")?;
+        fs::write(path, contents)
+            .with_context(|| format!("Failed to write MIDI mappings to {}", path.display()))
+    }
+
+    pub fn mappings(&self) -> &[Mapping] {
+        &self.mappings
+    }
+
+    /// Bind the next control that is moved to the parameter.
+    pub fn learn(&mut self, parameter: Parameter, mode: MappingMode) {
+        info!("Move a control to map it to {parameter}");
+        self.learning = Some((parameter, mode));
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.mappings.len() {
+            let mapping = self.mappings.remove(index);
+            info!("Removed MIDI mapping {mapping}");
+        }
+    }
+
+    /// Handle a control value, returning None if the control is not mapped.
+    pub fn handle(
+        &mut self,
+        control: Control,
+        value: u8,
+        current: impl Fn(Parameter) -> f32,
+    ) -> Option<ControlAction> {
+        if let Some((parameter, mode)) = self.learning.take() {
+            let mapping = Mapping::new(control, parameter, mode);
+            // A control drives one parameter at a time
+            self.mappings.retain(|other| other.control != control);
+            self.mappings.push(mapping);
+            info!("Mapped {mapping}");
+            return Some(ControlAction::Learned(mapping));
+        }
+
+        let mapping = self
+            .mappings
+            .iter()
+            .find(|mapping| mapping.control == control)?;
+        if mapping.parameter.is_toggle() {
+            // Buttons send 127 when pressed and 0 when released
+            return (value >= 64).then_some(ControlAction::Trigger(mapping.parameter));
+        }
+        let value = mapping.value(value, current(mapping.parameter));
+        Some(ControlAction::Set(mapping.parameter, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KNOB: Control = Control::ControlChange {
+        channel: 0,
+        number: 74,
+    };
+    const ENCODER: Control = Control::ControlChange {
+        channel: 0,
+        number: 16,
+    };
+
+    #[test]
+    fn test_learn_and_scale() {
+        let mut map = MidiMap::default();
+        assert_eq!(map.handle(KNOB, 0, |_| 0.0), None);
+
+        map.learn(Parameter::Bpm, MappingMode::Absolute);
+        assert!(matches!(
+            map.handle(KNOB, 10, |_| 0.0),
+            Some(ControlAction::Learned(_))
+        ));
+        assert_eq!(
+            map.handle(KNOB, 127, |_| 0.0),
+            Some(ControlAction::Set(Parameter::Bpm, 240.0))
+        );
+
+        map.learn(Parameter::Steps, MappingMode::Relative);
+        map.handle(ENCODER, 1, |_| 0.0);
+        assert_eq!(
+            map.handle(ENCODER, 2, |_| 4.0),
+            Some(ControlAction::Set(Parameter::Steps, 6.0))
+        );
+        assert_eq!(
+            map.handle(ENCODER, 127, |_| 1.0),
+            Some(ControlAction::Set(Parameter::Steps, 1.0))
+        );
+
+        // Mappings survive a round trip through the config file format
+        let saved: MidiMap = toml::from_str(&toml::to_string(&map).unwrap()).unwrap();
+        assert_eq!(saved.mappings(), map.mappings());
+    }
+}
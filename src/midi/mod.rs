@@ -1,178 +1,558 @@
+pub mod error;
+pub mod learn;
+pub mod output;
+pub mod ports;
+pub mod recording;
+pub mod routing;
 pub mod state;
 
-use crate::note::Note;
-use crate::state::SharedState;
+use crate::gui::{Event, Message};
+use crate::metronome::{Click, ClickOutput, CLICK_MILLIS};
+use crate::midi::error::MidiError;
+use crate::midi::learn::{Control, ControlAction, MIDI_MAP_FILE};
+use crate::midi::output::OutputConnection;
+use crate::midi::ports::{
+    create_virtual_input, create_virtual_output, default_input, find_port, port_names,
+    MidiPortConfig, MidiPortStatus, PortConnection, INPUT_CLIENT_NAME, MIDI_PORTS_FILE,
+    OUTPUT_CLIENT_NAME, PORT_SCAN_INTERVAL, VIRTUAL_PORT,
+};
+use crate::midi::routing::{Routing, Source, ROUTING_FILE};
+use crate::note::{Note, TICKS_PER_STEP};
+use crate::scene::SCENE_SLOTS;
+use crate::state::{SequencerSlot, SharedState};
+use crate::synth::audio::AudioOutput;
+use crate::synth::timbre::{SynthConfig, SYNTH_FILE};
+use crate::synth::{Synth, SAMPLE_RATE};
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::Result;
 use log::{error, info, warn};
-use midir::{MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
+use midir::{MidiInput, MidiInputConnection, MidiOutput};
 use state::MidiCommand;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex as SyncMutex};
 use tokio::runtime::Handle;
 use tokio::sync::{mpsc, Mutex, RwLock};
-use tokio::task;
-use tokio::time::{sleep, Duration};
+use tokio::task::JoinSet;
+use tokio::time::{interval, sleep, Duration, MissedTickBehavior};
 
 pub struct MidiHandler {
     rx: mpsc::Receiver<MidiCommand>,
-    conn_out: Arc<Mutex<Option<MidiOutputConnection>>>,
+    tx_gui: Arc<SyncMutex<Option<iced::futures::channel::mpsc::Sender<Message>>>>,
+    shared_state: Arc<RwLock<SharedState>>,
+    /// Output ports by name, which stay open while they are selected or routed to.
+    outputs: HashMap<String, Arc<Mutex<OutputConnection>>>,
     conn_in: Option<MidiInputConnection<()>>,
+    /// Ports that were selected, which are reconnected when they come back.
+    port_config: MidiPortConfig,
+    routing: Routing,
+    port_status: MidiPortStatus,
+    /// The built-in synth, which plays the slots alongside the MIDI outputs while `audio` is
+    /// running.
+    synth: Arc<SyncMutex<Synth>>,
+    audio: Option<AudioOutput>,
 }
 
 const NOTE_ON_MSG: u8 = 0x90;
 const NOTE_OFF_MSG: u8 = 0x80;
+const CONTROL_CHANGE_MSG: u8 = 0xB0;
+const PROGRAM_CHANGE_MSG: u8 = 0xC0;
+const CLOCK_MSG: u8 = 0xF8;
+const START_MSG: u8 = 0xFA;
+const STOP_MSG: u8 = 0xFC;
+
+/// MIDI clock runs at 24 ticks per quarter note, so 6 per sixteenth step.
+const CLOCK_TICKS_PER_STEP: u32 = 6;
 
 impl MidiHandler {
-    pub fn new(rx: mpsc::Receiver<MidiCommand>) -> Result<Self> {
+    pub fn new(
+        rx: mpsc::Receiver<MidiCommand>,
+        tx_gui: Arc<SyncMutex<Option<iced::futures::channel::mpsc::Sender<Message>>>>,
+        shared_state: Arc<RwLock<SharedState>>,
+        port_config: MidiPortConfig,
+        routing: Routing,
+        synth_config: SynthConfig,
+    ) -> Result<Self> {
         Ok(Self {
             rx,
-            conn_out: Arc::new(Mutex::new(None)),
+            tx_gui,
+            shared_state,
+            outputs: HashMap::new(),
             conn_in: None,
+            port_status: MidiPortStatus {
+                policy: port_config.policy,
+                routing: routing.clone(),
+                synth: synth_config.clone(),
+                ..MidiPortStatus::default()
+            },
+            port_config,
+            routing,
+            synth: Arc::new(SyncMutex::new(Synth::new(SAMPLE_RATE, &synth_config))),
+            audio: None,
         })
     }
 
-    pub async fn play_multiple_notes(&mut self, notes: (Option<Note>, Option<Note>), channel: u8) {
-        match notes {
-            (None, None) => {}
-            (Some(note_a), None) => {
-                play_note(
-                    Arc::clone(&self.conn_out),
-                    note_a.pitch,
-                    note_a.duration as u64,
-                    note_a.velocity,
-                    channel,
-                )
-                .await
-            }
-            (None, Some(note_b)) => {
-                play_note(
-                    Arc::clone(&self.conn_out),
-                    note_b.pitch,
-                    note_b.duration as u64,
-                    note_b.velocity,
-                    channel,
-                )
-                .await
+    fn output(&mut self, name: &str) -> Arc<Mutex<OutputConnection>> {
+        let policy = self.port_config.policy;
+        self.outputs
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                Arc::new(Mutex::new(OutputConnection::new(name.to_string(), policy)))
+            })
+            .clone()
+    }
+
+    /// The selected output followed by all ports that are routed to.
+    fn wanted_outputs(&self) -> Vec<String> {
+        let mut names = self.port_config.output.iter().cloned().collect::<Vec<_>>();
+        for name in self.routing.ports() {
+            if !names.contains(&name) {
+                names.push(name);
             }
-            (Some(note_a), Some(note_b)) => {
-                let conn_a = Arc::clone(&self.conn_out);
-                let conn_b = Arc::clone(&self.conn_out);
+        }
+        names
+    }
 
-                let task_a = task::spawn(async move {
-                    play_note(
-                        conn_a,
-                        note_a.pitch,
-                        note_a.duration as u64,
-                        note_a.velocity,
-                        channel,
-                    )
-                    .await;
-                });
+    /// Close outputs that are no longer selected or routed to, turning off their notes first.
+    async fn close_unused_outputs(&mut self) {
+        let wanted = self.wanted_outputs();
+        let unused = self
+            .outputs
+            .keys()
+            .filter(|name| !wanted.contains(name))
+            .cloned()
+            .collect::<Vec<_>>();
+        for name in unused {
+            if let Some(output) = self.outputs.remove(&name) {
+                if let Err(e) = output.lock().await.all_notes_off(false) {
+                    warn!("{e}");
+                }
+                info!("Closed MIDI output {name}");
+            }
+        }
+    }
 
-                let task_b = task::spawn(async move {
-                    play_note(
-                        conn_b,
-                        note_b.pitch,
-                        note_b.duration as u64,
-                        note_b.velocity,
-                        channel,
-                    )
-                    .await;
-                });
+    /// Outputs and channels that a source is sent to. Slots without routes play on the
+    /// selected output.
+    fn destinations(
+        &mut self,
+        source: Source,
+        channel: u8,
+    ) -> Vec<(Arc<Mutex<OutputConnection>>, u8)> {
+        let mut routes = self
+            .routing
+            .destinations(source)
+            .map(|route| (route.port.clone(), route.channel.unwrap_or(channel)))
+            .collect::<Vec<_>>();
+        if routes.is_empty() && source != Source::Clock {
+            routes.extend(
+                self.port_config
+                    .output
+                    .iter()
+                    .map(|name| (name.clone(), channel)),
+            );
+        }
+        routes
+            .into_iter()
+            .map(|(name, channel)| (self.output(&name), channel))
+            .collect()
+    }
 
-                let _ = tokio::join!(task_a, task_b);
+    /// Play a click without waiting for it to end, so that it does not hold up the step.
+    fn play_click(&mut self, click: Click) {
+        match click.output {
+            ClickOutput::Synth => {
+                if self.audio.is_some() {
+                    self.synth.lock().unwrap().click(click.note, click.velocity);
+                }
+            }
+            ClickOutput::Midi => {
+                for (conn, channel) in self.destinations(Source::Click, click.channel) {
+                    tokio::spawn(async move {
+                        let result =
+                            play_note(conn, click.note, CLICK_MILLIS, click.velocity, channel)
+                                .await;
+                        if let Err(e) = result {
+                            warn!("Failed to play the metronome: {e}");
+                        }
+                    });
+                }
             }
         }
     }
 
-    pub async fn setup_midi_input(&mut self, shared_state: Arc<RwLock<SharedState>>) -> Result<()> {
-        info!("Setting up MIDI input...");
-        let mut midi_in = MidiInput::new("MIDI Input").context("Failed to create MIDI input")?;
-        midi_in.ignore(midir::Ignore::None);
+    /// Play the notes of both slots and the clock for one step, returning the first error.
+    pub async fn play_multiple_notes(
+        &mut self,
+        notes: (Option<Note>, Option<Note>),
+        channel: u8,
+        step_duration: f32,
+    ) -> Result<(), MidiError> {
+        let mut tasks = JoinSet::new();
+        // Keep the pace of the transport, even without any outputs
+        let step = Duration::from_secs_f32(step_duration / 1000.0);
+        tasks.spawn(async move {
+            sleep(step).await;
+            Ok(())
+        });
 
-        let in_ports = midi_in.ports();
-        info!("Available input ports:");
-        for (i, p) in in_ports.iter().enumerate() {
-            info!(
-                "{}: {}",
-                i,
-                midi_in.port_name(p).context("Failed to get port name")?
-            );
+        for (source, note) in [(Source::Left, notes.0), (Source::Right, notes.1)] {
+            let Some(note) = note else {
+                continue;
+            };
+            if self.audio.is_some() && note.velocity > 0 {
+                let slot = match source {
+                    Source::Right => SequencerSlot::Right,
+                    _ => SequencerSlot::Left,
+                };
+                tasks.spawn(play_synth_note(self.synth.clone(), slot, note, step));
+            }
+            for (conn, channel) in self.destinations(source, channel) {
+                tasks.spawn(play_note(
+                    conn,
+                    note.pitch,
+                    note.millis(step_duration) as u64,
+                    note.velocity,
+                    channel,
+                ));
+            }
+        }
+        for (conn, _) in self.destinations(Source::Clock, channel) {
+            tasks.spawn(send_clock(conn, step));
         }
-        let in_ports_ttymidi = in_ports
-            .iter()
-            .filter(|p| midi_in.port_name(p).unwrap_or_default().contains("ttymidi"))
-            .collect::<Vec<_>>();
-        let in_port = if in_ports_ttymidi.is_empty() {
-            warn!("No ttymidi input ports available.");
-            in_ports
-                .first()
-                .ok_or_else(|| anyhow!("No MIDI input ports available"))?
-        } else {
-            in_ports_ttymidi
-                .first()
-                .ok_or_else(|| anyhow!("No ttymidi input ports available"))?
-        };
 
-        info!(
-            "Connecting to {}",
-            midi_in
-                .port_name(in_port)
-                .context("Failed to get port name")?
-        );
+        let mut result = Ok(());
+        while let Some(joined) = tasks.join_next().await {
+            if let Ok(Err(e)) = joined {
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+
+    async fn send_to_all(&mut self, f: impl Fn(&mut OutputConnection) -> Result<(), MidiError>) {
+        let outputs = self.outputs.values().cloned().collect::<Vec<_>>();
+        for output in outputs {
+            let result = f(&mut *output.lock().await);
+            if let Err(e) = result {
+                self.report_error(e).await;
+            }
+        }
+    }
+
+    /// Connect to an input port by name, or create a virtual input port.
+    fn connect_input(&mut self, name: &str) -> Result<(), MidiError> {
+        let mut midi_in =
+            MidiInput::new(INPUT_CLIENT_NAME).map_err(|e| MidiError::Client(e.to_string()))?;
+        midi_in.ignore(midir::Ignore::None);
 
         let handle = Handle::current();
+        let shared_state = self.shared_state.clone();
+        let callback = move |_stamp, message: &[u8], _: &mut ()| {
+            let shared_state = shared_state.clone();
+            let message = message.to_vec();
+            handle.spawn(async move {
+                handle_midi_message(message, &shared_state).await;
+            });
+        };
 
-        let conn_in = midi_in
-            .connect(
-                in_port,
-                "midir-test",
-                move |_stamp, message, _| {
-                    let shared_state = shared_state.clone();
-                    let message = message.to_vec();
-                    let handle = handle.clone();
-                    handle.spawn(async move {
-                        handle_midi_message(message, &shared_state).await;
-                    });
-                },
-                (),
-            )
-            .map_err(|e| anyhow!("Failed to connect to MIDI input: {}", e))?;
+        let conn_in = if name == VIRTUAL_PORT {
+            create_virtual_input(midi_in, callback)?
+        } else {
+            let port = find_port(&midi_in, name)
+                .ok_or_else(|| MidiError::PortNotFound(name.to_string()))?;
+            midi_in
+                .connect(&port, "gen-seq-in", callback, ())
+                .map_err(|e| MidiError::Connect {
+                    port: name.to_string(),
+                    reason: e.to_string(),
+                })?
+        };
 
         self.conn_in = Some(conn_in);
+        self.port_status.input = PortConnection::Connected(name.to_string());
+        info!("Connected to MIDI input {name}");
         Ok(())
     }
 
+    /// Connect to an output port by name, or create a virtual output port.
+    async fn connect_output(&mut self, name: &str) -> Result<(), MidiError> {
+        let midi_out =
+            MidiOutput::new(OUTPUT_CLIENT_NAME).map_err(|e| MidiError::Client(e.to_string()))?;
+        let conn_out = if name == VIRTUAL_PORT {
+            create_virtual_output(midi_out)?
+        } else {
+            let port = find_port(&midi_out, name)
+                .ok_or_else(|| MidiError::PortNotFound(name.to_string()))?;
+            midi_out
+                .connect(&port, "gen-seq")
+                .map_err(|e| MidiError::Connect {
+                    port: name.to_string(),
+                    reason: e.to_string(),
+                })?
+        };
+
+        self.port_status.error = None;
+        info!("Connected to MIDI output {name}");
+        let result = self.output(name).lock().await.connect(conn_out);
+        self.update_output_status().await;
+        result
+    }
+
+    async fn output_status(&mut self, name: &str) -> PortConnection {
+        match self.outputs.get(name) {
+            Some(output) if output.lock().await.is_connected() => {
+                PortConnection::Connected(name.to_string())
+            }
+            _ => PortConnection::Waiting(name.to_string()),
+        }
+    }
+
+    async fn update_output_status(&mut self) {
+        self.port_status.output = match self.port_config.output.clone() {
+            Some(name) => self.output_status(&name).await,
+            None => PortConnection::None,
+        };
+        let mut routed = Vec::new();
+        for name in self.routing.ports() {
+            routed.push(self.output_status(&name).await);
+        }
+        self.port_status.routed = routed;
+        self.port_status.routing = self.routing.clone();
+    }
+
+    /// Report an error to the GUI once. The transport keeps running, and outputs that failed
+    /// are reconnected when they are available again.
+    async fn report_error(&mut self, e: MidiError) {
+        if self.port_status.error.as_ref() == Some(&e) {
+            return;
+        }
+        error!("{e}");
+        self.port_status.error = Some(e);
+        self.update_output_status().await;
+        self.send_port_status();
+    }
+
+    /// Enumerate the ports, dropping connections to ports that disappeared and reconnecting
+    /// to the selected and routed ports by name when they are available.
+    async fn scan_ports(&mut self) {
+        let before = self.port_status.clone();
+
+        self.port_status.inputs = match MidiInput::new(INPUT_CLIENT_NAME) {
+            Ok(midi_in) => port_names(&midi_in),
+            Err(e) => {
+                warn!("Failed to list MIDI input ports: {e}");
+                Vec::new()
+            }
+        };
+        self.port_status.outputs = match MidiOutput::new(OUTPUT_CLIENT_NAME) {
+            Ok(midi_out) => port_names(&midi_out),
+            Err(e) => {
+                warn!("Failed to list MIDI output ports: {e}");
+                Vec::new()
+            }
+        };
+
+        if let PortConnection::Connected(name) = &self.port_status.input {
+            if !self.port_status.inputs.contains(name) {
+                warn!("MIDI input {name} disappeared");
+                self.conn_in = None;
+                self.port_status.input = PortConnection::Waiting(name.clone());
+            }
+        }
+        if !self.port_status.input.is_connected() {
+            let wanted = self
+                .port_config
+                .input
+                .clone()
+                .or_else(|| default_input(&self.port_status.inputs));
+            if let Some(name) = wanted {
+                if self.port_status.inputs.contains(&name) {
+                    if let Err(e) = self.connect_input(&name) {
+                        self.report_error(e).await;
+                    }
+                }
+                if !self.port_status.input.is_connected() {
+                    self.port_status.input = PortConnection::Waiting(name);
+                }
+            }
+        }
+
+        for name in self.wanted_outputs() {
+            let output = self.output(&name);
+            let connected = output.lock().await.is_connected();
+            let available = self.port_status.outputs.contains(&name);
+            if connected && !available {
+                warn!("MIDI output {name} disappeared");
+                output.lock().await.disconnect();
+            } else if !connected && available {
+                if let Err(e) = self.connect_output(&name).await {
+                    self.report_error(e).await;
+                }
+            }
+        }
+        self.update_output_status().await;
+
+        if self.port_status != before {
+            self.send_port_status();
+        }
+    }
+
+    fn send_port_status(&self) {
+        if let Some(mut tx) = self.tx_gui.lock().unwrap().clone() {
+            if let Err(e) = tx.try_send(Message::ReceivedEvent(Event::MidiPortsChanged(Box::new(
+                self.port_status.clone(),
+            )))) {
+                error!("Error sending Message::ReceivedEvent to GUI: {:?}", e);
+            }
+        }
+    }
+
+    fn save_port_config(&self) {
+        if let Err(e) = self.port_config.save(Path::new(MIDI_PORTS_FILE)) {
+            error!("{e:#}");
+        }
+    }
+
+    /// Start or stop the built-in synth and update its timbres.
+    async fn set_synth(&mut self, mut config: SynthConfig) {
+        self.synth.lock().unwrap().set_timbres(&config);
+        if !config.enabled {
+            if self.audio.take().is_some() {
+                info!("Stopped the built-in synth");
+            }
+        } else if self.audio.is_none() {
+            match AudioOutput::start(self.synth.clone()) {
+                Ok(audio) => self.audio = Some(audio),
+                Err(e) => {
+                    config.enabled = false;
+                    self.report_error(e).await;
+                }
+            }
+        }
+        self.port_status.synth = config;
+    }
+
+    /// Save the changed routing, and open or close outputs to match it.
+    async fn routing_changed(&mut self) {
+        if let Err(e) = self.routing.save(Path::new(ROUTING_FILE)) {
+            error!("{e:#}");
+        }
+        self.close_unused_outputs().await;
+        self.scan_ports().await;
+        self.send_port_status();
+    }
+
     pub async fn run(&mut self) -> Result<()> {
-        while let Some(midi_command) = self.rx.recv().await {
+        if self.port_status.synth.enabled {
+            self.set_synth(self.port_status.synth.clone()).await;
+        }
+        let mut scan = interval(PORT_SCAN_INTERVAL);
+        scan.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            let midi_command = tokio::select! {
+                command = self.rx.recv() => match command {
+                    Some(command) => command,
+                    None => break,
+                },
+                _ = scan.tick() => {
+                    self.scan_ports().await;
+                    continue;
+                }
+            };
+
             match midi_command {
-                MidiCommand::PlayNotes { notes, channel } => {
-                    self.play_multiple_notes(notes, channel).await
+                MidiCommand::PlayNotes {
+                    notes,
+                    channel,
+                    step_duration,
+                } => {
+                    if let Err(e) = self
+                        .play_multiple_notes(notes, channel, step_duration)
+                        .await
+                    {
+                        self.report_error(e).await;
+                    }
                 }
+                MidiCommand::Click { click } => self.play_click(click),
                 MidiCommand::GetPorts { responder } => {
-                    let midi_out = MidiOutput::new("Generative Sequencer MIDI Out")?;
-                    let port_names = midi_out.ports().iter().map(|p| p.id()).collect::<Vec<_>>();
-                    if responder.send(port_names).is_err() {
+                    self.scan_ports().await;
+                    if responder.send(self.port_status.outputs.clone()).is_err() {
                         warn!("Unable to send MIDI output ports.");
                     }
                 }
                 MidiCommand::SetPort { out_port } => {
                     info!("Received SetPort from GUI");
-                    let midi_out = MidiOutput::new("Generative Sequencer MIDI Out")?;
-                    match midi_out.find_port_by_id(out_port.clone()) {
-                        Some(midi_port) => {
-                            let conn_out = midi_out
-                                .connect(&midi_port, "gen-seq")
-                                .map_err(|e| anyhow!("Failed to connect to MIDI output: {}", e))?;
-
-                            *self.conn_out.lock().await = Some(conn_out);
-                            info!("Successfully changed MIDI output port to {out_port}");
+                    self.port_config.output = Some(out_port.clone());
+                    self.save_port_config();
+                    self.close_unused_outputs().await;
+                    let connected = self.output(&out_port).lock().await.is_connected();
+                    if !connected {
+                        if let Err(e) = self.connect_output(&out_port).await {
+                            self.report_error(e).await;
                         }
-                        None => {
-                            error!("Unable to find MIDI output port with name {out_port}")
+                    }
+                    self.update_output_status().await;
+                    self.send_port_status();
+                }
+                MidiCommand::SetInputPort { in_port } => {
+                    info!("Received SetInputPort from GUI");
+                    self.port_config.input = Some(in_port.clone());
+                    self.save_port_config();
+                    self.conn_in = None;
+                    if let Err(e) = self.connect_input(&in_port) {
+                        self.port_status.input = PortConnection::Waiting(in_port);
+                        self.report_error(e).await;
+                    }
+                    self.send_port_status();
+                }
+                MidiCommand::SetOutputPolicy { policy } => {
+                    info!("MIDI output policy: {policy}");
+                    self.port_config.policy = policy;
+                    self.save_port_config();
+                    for output in self.outputs.values() {
+                        output.lock().await.policy = policy;
+                    }
+                    self.port_status.policy = policy;
+                    self.send_port_status();
+                }
+                MidiCommand::AddRoute { route } => {
+                    info!("Routing {route}");
+                    self.routing.add(route);
+                    self.routing_changed().await;
+                }
+                MidiCommand::RemoveRoute { index } => {
+                    self.routing.remove(index);
+                    self.routing_changed().await;
+                }
+                MidiCommand::SetSynth { config } => {
+                    if let Err(e) = config.save(Path::new(SYNTH_FILE)) {
+                        error!("{e:#}");
+                    }
+                    self.set_synth(config).await;
+                    self.send_port_status();
+                }
+                MidiCommand::Transport { playing } => {
+                    let message = if playing { START_MSG } else { STOP_MSG };
+                    for (conn, _) in self.destinations(Source::Clock, 0) {
+                        let result = conn.lock().await.send_raw(&[message]);
+                        if let Err(e) = result {
+                            self.report_error(e).await;
                         }
                     }
                 }
+                MidiCommand::AllNotesOff => {
+                    self.send_to_all(|output| output.all_notes_off(false)).await;
+                    self.synth.lock().unwrap().all_notes_off(false);
+                }
+                MidiCommand::Panic { done } => {
+                    self.send_to_all(|output| output.all_notes_off(true)).await;
+                    self.synth.lock().unwrap().all_notes_off(true);
+                    if let Some(done) = done {
+                        let _ = done.send(());
+                    }
+                }
             };
         }
 
@@ -189,31 +569,106 @@ async fn handle_midi_message(message: Vec<u8>, shared_state: &Arc<RwLock<SharedS
             state.clock_ticks = 0;
             state.quarter_notes += 1;
         }
+    } else if message[0] & 0xF0 == NOTE_ON_MSG && message.len() > 2 && message[2] > 0 {
+        // Notes mapped to a parameter are not recorded
+        let control = Control::Note {
+            channel: message[0] & 0x0F,
+            number: message[1],
+        };
+        if handle_control(shared_state, control, message[2])
+            .await
+            .is_none()
+        {
+            shared_state
+                .write()
+                .await
+                .record_note_on(message[1], message[2]);
+        }
+    } else if message[0] & 0xF0 == CONTROL_CHANGE_MSG && message.len() > 2 {
+        let control = Control::ControlChange {
+            channel: message[0] & 0x0F,
+            number: message[1],
+        };
+        handle_control(shared_state, control, message[2]).await;
+    } else if (message[0] & 0xF0 == NOTE_OFF_MSG || message[0] & 0xF0 == NOTE_ON_MSG)
+        && message.len() > 2
+    {
+        // A note on with velocity 0 is a note off
+        shared_state.write().await.record_note_off(message[1]);
+    } else if message[0] & 0xF0 == PROGRAM_CHANGE_MSG && message.len() > 1 {
+        // Program change recalls the scene with the same number
+        let program = message[1] as usize;
+        if program < SCENE_SLOTS {
+            shared_state.write().await.recall_scene(program);
+        }
     }
 }
 
+/// Apply a mapped control, saving the mappings when a new one was learned. They are saved
+/// once the state is unlocked, so that writing the file does not hold up playback.
+async fn handle_control(
+    shared_state: &RwLock<SharedState>,
+    control: Control,
+    value: u8,
+) -> Option<ControlAction> {
+    let (action, midi_map) = {
+        let mut state = shared_state.write().await;
+        let action = state.handle_control(control, value);
+        let learned = matches!(action, Some(ControlAction::Learned(_)));
+        (action, learned.then(|| state.midi_map.clone()))
+    };
+    if let Some(midi_map) = midi_map {
+        if let Err(e) = midi_map.save(Path::new(MIDI_MAP_FILE)) {
+            error!("{e:#}");
+        }
+    }
+    action
+}
+
+/// Play a note, holding the output only while sending so that notes can overlap.
 pub async fn play_note(
-    conn: Arc<Mutex<Option<MidiOutputConnection>>>,
+    conn: Arc<Mutex<OutputConnection>>,
     note: u8,
     duration: u64,
     velocity: u8,
     channel: u8,
-) {
-    let mut guard = conn.lock().await;
-
-    if let Some(output) = guard.as_mut() {
-        output
-            .send(&[NOTE_ON_MSG, note, velocity, channel])
-            .expect("Failed to send NOTE_ON_MSG");
-    }
+) -> Result<(), MidiError> {
+    let note_on = conn
+        .lock()
+        .await
+        .send(&[NOTE_ON_MSG | (channel & 0x0F), note, velocity]);
 
+    // Keep the timing of the transport, even if the note could not be sent
     sleep(Duration::from_millis(duration)).await;
 
-    if let Some(output) = guard.as_mut() {
-        output
-            .send(&[NOTE_OFF_MSG, note, velocity, channel])
-            .expect("Failed to send NOTE_OFF_MSG");
-    }
+    let note_off = conn
+        .lock()
+        .await
+        .send(&[NOTE_OFF_MSG | (channel & 0x0F), note, 0]);
+    note_on.and(note_off)
+}
+
+/// Play a note on the built-in synth, scheduled like the notes on the MIDI outputs.
+async fn play_synth_note(
+    synth: Arc<SyncMutex<Synth>>,
+    slot: SequencerSlot,
+    note: Note,
+    step: Duration,
+) -> Result<(), MidiError> {
+    synth
+        .lock()
+        .unwrap()
+        .note_on(&slot, note.pitch, note.velocity);
+    sleep(step.mul_f32(note.duration as f32 / TICKS_PER_STEP as f32)).await;
+    synth.lock().unwrap().note_off(&slot, note.pitch);
+    Ok(())
+}
 
-    drop(guard);
+/// Send the clock ticks of one step, spread evenly over the step.
+async fn send_clock(conn: Arc<Mutex<OutputConnection>>, step: Duration) -> Result<(), MidiError> {
+    for _ in 0..CLOCK_TICKS_PER_STEP {
+        conn.lock().await.send_raw(&[CLOCK_MSG])?;
+        sleep(step / CLOCK_TICKS_PER_STEP).await;
+    }
+    Ok(())
 }
@@ -0,0 +1,235 @@
+use log::info;
+use midir::MidiOutputConnection;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::midi::error::MidiError;
+use crate::midi::{CONTROL_CHANGE_MSG, NOTE_OFF_MSG, NOTE_ON_MSG};
+
+/// Maximum number of messages held while the output is disconnected.
+pub const OUTPUT_BUFFER_SIZE: usize = 256;
+
+/// Buffered messages older than this are dropped when the output comes back, so that a long
+/// outage does not end in a burst of stale notes.
+pub const BUFFER_MAX_AGE: Duration = Duration::from_secs(2);
+
+const ALL_SOUND_OFF_CC: u8 = 120;
+const ALL_NOTES_OFF_CC: u8 = 123;
+
+/// What happens to messages while the output is disconnected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputPolicy {
+    #[default]
+    Drop,
+    /// Hold recent messages and send them once the output is reconnected.
+    Buffer,
+}
+
+impl OutputPolicy {
+    pub const ALL: [OutputPolicy; 2] = [OutputPolicy::Drop, OutputPolicy::Buffer];
+}
+
+impl fmt::Display for OutputPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputPolicy::Drop => write!(f, "Drop notes"),
+            OutputPolicy::Buffer => write!(f, "Buffer notes"),
+        }
+    }
+}
+
+/// Notes that were sent a note on but no note off yet, as (channel, pitch). The same note
+/// may be sounding more than once, e.g. when both slots play the same pitch.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SoundingNotes {
+    notes: Vec<(u8, u8)>,
+}
+
+impl SoundingNotes {
+    /// Update the sounding notes for a message that is sent. Returns false for a note off of a
+    /// note that is not sounding, e.g. because all notes were already turned off.
+    pub fn update(&mut self, message: &[u8]) -> bool {
+        let (status, channel) = (message[0] & 0xF0, message[0] & 0x0F);
+        match (status, message.get(1), message.get(2)) {
+            (NOTE_ON_MSG, Some(&pitch), Some(&velocity)) if velocity > 0 => {
+                self.notes.push((channel, pitch));
+                true
+            }
+            (NOTE_ON_MSG | NOTE_OFF_MSG, Some(&pitch), _) => {
+                match self.notes.iter().position(|note| *note == (channel, pitch)) {
+                    Some(index) => {
+                        self.notes.remove(index);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            _ => true,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+
+    /// Messages that stop all sounding notes: a note off for every note, followed by all notes
+    /// off (CC 123) for every channel in case a note off got lost. A panic also sends all sound
+    /// off (CC 120), on all 16 channels.
+    pub fn take_note_offs(&mut self, panic: bool) -> Vec<[u8; 3]> {
+        let notes = std::mem::take(&mut self.notes);
+        let mut channels = if panic {
+            (0..16).collect()
+        } else {
+            notes
+                .iter()
+                .map(|(channel, _)| *channel)
+                .collect::<Vec<_>>()
+        };
+        channels.sort();
+        channels.dedup();
+
+        let note_offs = notes
+            .iter()
+            .map(|(channel, pitch)| [NOTE_OFF_MSG | channel, *pitch, 0]);
+        let controllers = channels.into_iter().flat_map(|channel| {
+            let mut messages = vec![[CONTROL_CHANGE_MSG | channel, ALL_NOTES_OFF_CC, 0]];
+            if panic {
+                messages.push([CONTROL_CHANGE_MSG | channel, ALL_SOUND_OFF_CC, 0]);
+            }
+            messages
+        });
+        note_offs.chain(controllers).collect()
+    }
+}
+
+/// A MIDI output port that survives disappearing: sending fails with an error instead of
+/// panicking, and messages are dropped or buffered until it is connected again.
+pub struct OutputConnection {
+    conn: Option<MidiOutputConnection>,
+    name: String,
+    pub policy: OutputPolicy,
+    buffer: VecDeque<(Instant, Vec<u8>)>,
+    sounding: SoundingNotes,
+}
+
+impl OutputConnection {
+    pub fn new(name: String, policy: OutputPolicy) -> Self {
+        OutputConnection {
+            conn: None,
+            name,
+            policy,
+            buffer: VecDeque::new(),
+            sounding: SoundingNotes::default(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.conn.is_some()
+    }
+
+    /// Use a new connection to the port, sending any buffered messages that are recent enough.
+    pub fn connect(&mut self, conn: MidiOutputConnection) -> Result<(), MidiError> {
+        self.conn = Some(conn);
+
+        let buffered = std::mem::take(&mut self.buffer);
+        let recent = buffered
+            .into_iter()
+            .filter(|(time, _)| time.elapsed() < BUFFER_MAX_AGE)
+            .collect::<Vec<_>>();
+        if !recent.is_empty() {
+            info!("Sending {} buffered MIDI messages", recent.len());
+        }
+        recent
+            .iter()
+            .try_for_each(|(_, message)| self.send_raw(message))
+    }
+
+    /// Drop the connection, keeping the port name so that it is reported as disconnected.
+    pub fn disconnect(&mut self) {
+        self.conn = None;
+    }
+
+    /// Send a message, keeping track of the sounding notes.
+    pub fn send(&mut self, message: &[u8]) -> Result<(), MidiError> {
+        if !self.sounding.update(message) {
+            return Ok(());
+        }
+        self.send_raw(message)
+    }
+
+    /// Turn off all sounding notes, see [SoundingNotes::take_note_offs].
+    pub fn all_notes_off(&mut self, panic: bool) -> Result<(), MidiError> {
+        if self.sounding.is_empty() && !panic {
+            return Ok(());
+        }
+        info!("{}", if panic { "MIDI panic" } else { "All notes off" });
+        self.sounding
+            .take_note_offs(panic)
+            .iter()
+            .try_for_each(|message| self.send_raw(message))
+    }
+
+    /// Send a message that does not change the sounding notes, e.g. a clock tick.
+    pub fn send_raw(&mut self, message: &[u8]) -> Result<(), MidiError> {
+        let Some(conn) = self.conn.as_mut() else {
+            self.hold(message);
+            return Err(MidiError::Disconnected(self.name.clone()));
+        };
+
+        conn.send(message).map_err(|e| {
+            self.conn = None;
+            self.hold(message);
+            MidiError::Send {
+                port: self.name.clone(),
+                reason: e.to_string(),
+            }
+        })
+    }
+
+    fn hold(&mut self, message: &[u8]) {
+        if self.policy != OutputPolicy::Buffer {
+            return;
+        }
+        if self.buffer.len() >= OUTPUT_BUFFER_SIZE {
+            self.buffer.pop_front();
+        }
+        self.buffer.push_back((Instant::now(), message.to_vec()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sounding_notes() {
+        let mut sounding = SoundingNotes::default();
+        assert!(sounding.update(&[NOTE_ON_MSG | 1, 60, 100]));
+        assert!(sounding.update(&[NOTE_ON_MSG | 1, 60, 100]));
+        assert!(sounding.update(&[NOTE_ON_MSG | 2, 64, 100]));
+        // A note on with velocity 0 is a note off
+        assert!(sounding.update(&[NOTE_ON_MSG | 2, 64, 0]));
+        assert!(!sounding.update(&[NOTE_OFF_MSG | 2, 64, 0]));
+
+        assert_eq!(
+            sounding.take_note_offs(false),
+            vec![
+                [NOTE_OFF_MSG | 1, 60, 0],
+                [NOTE_OFF_MSG | 1, 60, 0],
+                [CONTROL_CHANGE_MSG | 1, ALL_NOTES_OFF_CC, 0],
+            ]
+        );
+        assert!(sounding.is_empty());
+        // Late note offs of notes that were already turned off are not sent
+        assert!(!sounding.update(&[NOTE_OFF_MSG | 1, 60, 0]));
+
+        assert_eq!(sounding.take_note_offs(true).len(), 32);
+    }
+}
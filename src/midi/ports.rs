@@ -0,0 +1,207 @@
+use anyhow::{Context, Result};
+use midir::{MidiIO, MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::midi::error::MidiError;
+use crate::midi::output::OutputPolicy;
+use crate::midi::routing::Routing;
+use crate::synth::timbre::SynthConfig;
+
+/// File that the last used MIDI ports are saved to, relative to the working directory.
+pub const MIDI_PORTS_FILE: &str = "midi_ports.toml";
+
+/// How often MIDI ports are enumerated to notice devices being plugged in or out.
+pub const PORT_SCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Client names of our own ports, which are never connected to.
+pub const INPUT_CLIENT_NAME: &str = "Generative Sequencer MIDI In";
+pub const OUTPUT_CLIENT_NAME: &str = "Generative Sequencer MIDI Out";
+
+/// Selecting this port creates a virtual port that other applications, e.g. a DAW or
+/// FluidSynth, can connect to, instead of connecting to an existing port.
+pub const VIRTUAL_PORT: &str = "Virtual port";
+
+/// Name of the virtual ports as other applications see them.
+const VIRTUAL_PORT_NAME: &str = "Generative Sequencer";
+
+/// The MIDI ports that were selected last, by name, so they can be found again after a
+/// restart or after a device was unplugged.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MidiPortConfig {
+    #[serde(default)]
+    pub input: Option<String>,
+    #[serde(default)]
+    pub output: Option<String>,
+    #[serde(default)]
+    pub policy: OutputPolicy,
+}
+
+impl MidiPortConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read MIDI ports from {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse MIDI ports from {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents = toml::to_string(self).context("Failed to serialize MIDI ports")?;
+        fs::write(path, contents)
+            .with_context(|| format!("Failed to write MIDI ports to {}", path.display()))
+    }
+}
+
+/// Connection status of the MIDI input or output.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum PortConnection {
+    #[default]
+    None,
+    Connected(String),
+    /// The port disappeared and is reconnected as soon as it is available again.
+    Waiting(String),
+}
+
+impl PortConnection {
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            PortConnection::None => None,
+            PortConnection::Connected(name) | PortConnection::Waiting(name) => Some(name),
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        matches!(self, PortConnection::Connected(_))
+    }
+}
+
+impl fmt::Display for PortConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortConnection::None => write!(f, "Not connected"),
+            PortConnection::Connected(name) => write!(f, "Connected to {name}"),
+            PortConnection::Waiting(name) => write!(f, "Waiting for {name}"),
+        }
+    }
+}
+
+/// Available MIDI ports and the state of the connections, shown in the GUI.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MidiPortStatus {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub input: PortConnection,
+    pub output: PortConnection,
+    /// Connections of the ports that are routed to, in the order of [Routing::ports].
+    pub routed: Vec<PortConnection>,
+    pub routing: Routing,
+    /// The last error, until the output is connected again.
+    pub error: Option<MidiError>,
+    pub policy: OutputPolicy,
+    pub synth: SynthConfig,
+}
+
+/// Names of all ports, leaving out our own, preceded by the virtual port where supported.
+pub fn port_names<T: MidiIO>(io: &T) -> Vec<String> {
+    let ports = io
+        .ports()
+        .iter()
+        .filter_map(|port| io.port_name(port).ok())
+        .filter(|name| !name.contains(INPUT_CLIENT_NAME) && !name.contains(OUTPUT_CLIENT_NAME))
+        .collect::<Vec<_>>();
+    if cfg!(unix) {
+        std::iter::once(VIRTUAL_PORT.to_string())
+            .chain(ports)
+            .collect()
+    } else {
+        ports
+    }
+}
+
+pub fn find_port<T: MidiIO>(io: &T, name: &str) -> Option<T::Port> {
+    io.ports()
+        .into_iter()
+        .find(|port| io.port_name(port).is_ok_and(|port_name| port_name == name))
+}
+
+/// Input port used when none was selected: the first ttymidi port, else the first port that
+/// is not virtual.
+pub fn default_input(names: &[String]) -> Option<String> {
+    let mut names = names.iter().filter(|name| *name != VIRTUAL_PORT);
+    names
+        .clone()
+        .find(|name| name.contains("ttymidi"))
+        .or_else(|| names.next())
+        .cloned()
+}
+
+#[cfg(unix)]
+pub fn create_virtual_input<F>(
+    midi_in: MidiInput,
+    callback: F,
+) -> Result<MidiInputConnection<()>, MidiError>
+where
+    F: FnMut(u64, &[u8], &mut ()) + Send + 'static,
+{
+    use midir::os::unix::VirtualInput;
+    midi_in
+        .create_virtual(VIRTUAL_PORT_NAME, callback, ())
+        .map_err(|e| MidiError::Connect {
+            port: VIRTUAL_PORT.to_string(),
+            reason: e.to_string(),
+        })
+}
+
+#[cfg(not(unix))]
+pub fn create_virtual_input<F>(
+    _midi_in: MidiInput,
+    _callback: F,
+) -> Result<MidiInputConnection<()>, MidiError>
+where
+    F: FnMut(u64, &[u8], &mut ()) + Send + 'static,
+{
+    Err(MidiError::Client(
+        "Virtual MIDI ports are not supported on this platform".to_string(),
+    ))
+}
+
+#[cfg(unix)]
+pub fn create_virtual_output(midi_out: MidiOutput) -> Result<MidiOutputConnection, MidiError> {
+    use midir::os::unix::VirtualOutput;
+    midi_out
+        .create_virtual(VIRTUAL_PORT_NAME)
+        .map_err(|e| MidiError::Connect {
+            port: VIRTUAL_PORT.to_string(),
+            reason: e.to_string(),
+        })
+}
+
+#[cfg(not(unix))]
+pub fn create_virtual_output(_midi_out: MidiOutput) -> Result<MidiOutputConnection, MidiError> {
+    Err(MidiError::Client(
+        "Virtual MIDI ports are not supported on this platform".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_input() {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(default_input(&[]), None);
+        assert_eq!(default_input(&names(&[VIRTUAL_PORT])), None);
+        assert_eq!(
+            default_input(&names(&["Midi Through:0", "ttymidi:MIDI in 129:0"])).as_deref(),
+            Some("ttymidi:MIDI in 129:0")
+        );
+        assert_eq!(
+            default_input(&names(&["Midi Through:0", "Keystep:0"])).as_deref(),
+            Some("Midi Through:0")
+        );
+    }
+}
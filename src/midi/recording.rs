@@ -0,0 +1,223 @@
+use log::info;
+use std::fmt;
+
+use crate::note::{Note, Sequence, TICKS_PER_STEP};
+use crate::sequencers::euclidean::state::EuclideanSequencerState;
+
+/// How incoming notes are recorded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecordMode {
+    /// Notes are added to the sequence while it plays.
+    #[default]
+    Overdub,
+    /// Steps are cleared as the sequence plays, so only newly played notes remain.
+    Replace,
+    /// Every note is written to the next step, without the transport running.
+    Step,
+}
+
+impl RecordMode {
+    pub const ALL: [RecordMode; 3] = [RecordMode::Overdub, RecordMode::Replace, RecordMode::Step];
+}
+
+impl fmt::Display for RecordMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordMode::Overdub => write!(f, "Overdub"),
+            RecordMode::Replace => write!(f, "Replace"),
+            RecordMode::Step => write!(f, "Step record"),
+        }
+    }
+}
+
+/// Where incoming notes are recorded to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecordTarget {
+    /// The fixed step sequence of the slot, which is created if there is none.
+    #[default]
+    Sequence,
+    /// The pitch lane of the Euclidean sequencer.
+    PitchLane,
+}
+
+impl RecordTarget {
+    pub const ALL: [RecordTarget; 2] = [RecordTarget::Sequence, RecordTarget::PitchLane];
+}
+
+impl fmt::Display for RecordTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordTarget::Sequence => write!(f, "Step sequence"),
+            RecordTarget::PitchLane => write!(f, "Pitch lane"),
+        }
+    }
+}
+
+/// Position of the playhead: the step that is sounding, and how far into it we are (0 to 1).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Playhead {
+    pub step: usize,
+    pub fraction: f32,
+}
+
+/// Records notes from MIDI input into a slot.
+#[derive(Clone, Debug, Default)]
+pub struct Recording {
+    pub armed: bool,
+    pub mode: RecordMode,
+    pub target: RecordTarget,
+    step: usize,
+    held_notes: Vec<u8>,
+    lane_cleared: bool,
+}
+
+impl Recording {
+    pub fn toggle(&mut self) {
+        self.armed = !self.armed;
+        self.step = 0;
+        self.held_notes.clear();
+        self.lane_cleared = false;
+        info!(
+            "Recording {} ({}, {})",
+            if self.armed { "armed" } else { "stopped" },
+            self.mode,
+            self.target
+        );
+    }
+
+    /// Step that a note is recorded to, quantized to the nearest step.
+    pub fn quantize(playhead: Playhead, len: usize) -> usize {
+        let step = if playhead.fraction >= 0.5 {
+            playhead.step + 1
+        } else {
+            playhead.step
+        };
+        step % len
+    }
+
+    /// Record a note on. Recording to a slot without a sequence starts one of a bar of rests.
+    pub fn note_on(
+        &mut self,
+        pitch: u8,
+        velocity: u8,
+        state: &mut EuclideanSequencerState,
+        fixed_sequence: &mut Option<Sequence>,
+        steps_per_bar: usize,
+        playhead: Option<Playhead>,
+    ) {
+        if !self.armed {
+            return;
+        }
+        self.held_notes.push(pitch);
+
+        match self.target {
+            RecordTarget::PitchLane => {
+                if self.mode == RecordMode::Replace && !self.lane_cleared {
+                    state.pitch_lane.clear();
+                    state.pitch = pitch;
+                    self.lane_cleared = true;
+                }
+                let offset = (pitch as i16 - state.pitch as i16).clamp(-128, 127) as i8;
+                if !state.pitch_lane.push(offset) {
+                    info!("Pitch lane is full");
+                }
+            }
+            RecordTarget::Sequence => {
+                let sequence = fixed_sequence.get_or_insert_with(|| Sequence {
+                    notes: vec![Note::rest(TICKS_PER_STEP); steps_per_bar],
+                });
+                let len = sequence.notes.len();
+                let step = match (self.mode, playhead) {
+                    (RecordMode::Step, _) => self.step % len,
+                    (_, Some(playhead)) => Self::quantize(playhead, len),
+                    (_, None) => {
+                        info!("Start playback to record, or use step record");
+                        return;
+                    }
+                };
+                sequence.notes[step] = Note {
+                    pitch,
+                    velocity,
+                    duration: TICKS_PER_STEP,
+                };
+            }
+        }
+    }
+
+    /// In step record, the next step is selected once all notes are released.
+    pub fn note_off(&mut self, pitch: u8) {
+        let was_held = !self.held_notes.is_empty();
+        self.held_notes.retain(|held| *held != pitch);
+        if self.armed && self.mode == RecordMode::Step && was_held && self.held_notes.is_empty() {
+            self.step += 1;
+        }
+    }
+
+    /// Called by playback on every step. In replace mode, the step after the playhead is
+    /// cleared so that notes quantized forward are kept.
+    pub fn advance(&mut self, fixed_sequence: &mut Option<Sequence>, playhead: Playhead) {
+        if !self.armed || self.mode != RecordMode::Replace || self.target != RecordTarget::Sequence
+        {
+            return;
+        }
+        if let Some(sequence) = fixed_sequence.as_mut() {
+            let len = sequence.notes.len();
+            sequence.notes[(playhead.step + 1) % len] = Note::rest(TICKS_PER_STEP);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_modes() {
+        let mut state = EuclideanSequencerState::new();
+        let mut sequence = None;
+        let mut recording = Recording::default();
+        recording.toggle();
+        // A bar of 7/8
+        let steps_per_bar = 14;
+
+        // Overdub quantizes to the nearest step
+        let late = Playhead {
+            step: 3,
+            fraction: 0.75,
+        };
+        recording.note_on(64, 90, &mut state, &mut sequence, steps_per_bar, Some(late));
+        recording.note_off(64);
+        let notes = &sequence.as_ref().unwrap().notes;
+        assert_eq!(notes.len(), steps_per_bar);
+        assert_eq!((notes[4].pitch, notes[4].velocity), (64, 90));
+
+        // Replace clears the step after the playhead
+        recording.mode = RecordMode::Replace;
+        recording.advance(
+            &mut sequence,
+            Playhead {
+                step: 3,
+                fraction: 0.,
+            },
+        );
+        assert_eq!(sequence.as_ref().unwrap().notes[4].pitch, 0);
+
+        // Step record advances when all notes are released
+        recording.mode = RecordMode::Step;
+        recording.note_on(60, 100, &mut state, &mut sequence, steps_per_bar, None);
+        recording.note_on(67, 100, &mut state, &mut sequence, steps_per_bar, None);
+        recording.note_off(60);
+        recording.note_off(67);
+        recording.note_on(62, 100, &mut state, &mut sequence, steps_per_bar, None);
+        let pitches = sequence.as_ref().unwrap().notes[..3]
+            .iter()
+            .map(|note| note.pitch)
+            .collect::<Vec<_>>();
+        assert_eq!(pitches, vec![67, 62, 0]);
+
+        // Pitch lane records offsets from the sequencer pitch
+        recording.target = RecordTarget::PitchLane;
+        recording.note_on(63, 100, &mut state, &mut sequence, steps_per_bar, None);
+        assert_eq!(state.pitch_lane.offsets(), &[3]);
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// File that the routing is saved to and loaded from, relative to the working directory.
+pub const ROUTING_FILE: &str = "routing.toml";
+
+/// What is sent to a destination.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    #[default]
+    Left,
+    Right,
+    /// MIDI clock, with start and stop messages.
+    Clock,
+    /// Clicks of the metronome.
+    Click,
+}
+
+impl Source {
+    pub const ALL: [Source; 4] = [Source::Left, Source::Right, Source::Clock, Source::Click];
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Left => write!(f, "Left slot"),
+            Source::Right => write!(f, "Right slot"),
+            Source::Clock => write!(f, "Clock"),
+            Source::Click => write!(f, "Metronome"),
+        }
+    }
+}
+
+/// Sends a source to an output port, on a MIDI channel (0-15).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Route {
+    pub source: Source,
+    pub port: String,
+    /// Without a channel, notes follow the MIDI channel selected with `C`.
+    #[serde(default)]
+    pub channel: Option<u8>,
+}
+
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.source, self.channel) {
+            (Source::Clock, _) => write!(f, "{} - {}", self.source, self.port),
+            (_, Some(channel)) => write!(f, "{} - {} ch. {}", self.source, self.port, channel + 1),
+            (_, None) => write!(f, "{} - {}", self.source, self.port),
+        }
+    }
+}
+
+/// Routes sources to destinations. A slot without routes plays on the selected MIDI output,
+/// a clock without routes is not sent.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Routing {
+    #[serde(default)]
+    routes: Vec<Route>,
+}
+
+impl Routing {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read routing from {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse routing from {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents = toml::to_string(self).context("Failed to serialize routing")?;
+        fs::write(path, contents)
+            .with_context(|| format!("Failed to write routing to {}", path.display()))
+    }
+
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+
+    pub fn add(&mut self, route: Route) {
+        if !self.routes.contains(&route) {
+            self.routes.push(route);
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.routes.len() {
+            self.routes.remove(index);
+        }
+    }
+
+    /// Routes of a source.
+    pub fn destinations(&self, source: Source) -> impl Iterator<Item = &Route> {
+        self.routes
+            .iter()
+            .filter(move |route| route.source == source)
+    }
+
+    /// Names of all ports that are routed to.
+    pub fn ports(&self) -> Vec<String> {
+        let mut ports = Vec::new();
+        for route in &self.routes {
+            if !ports.contains(&route.port) {
+                ports.push(route.port.clone());
+            }
+        }
+        ports
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routing() {
+        let mut routing: Routing = toml::from_str(
+            r#"
+            [[routes]]
+            source = "left"
+            port = "ttymidi"
+            channel = 0
+
+            [[routes]]
+            source = "right"
+            port = "FLUID Synth"
+
+            [[routes]]
+            source = "clock"
+            port = "ttymidi"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(routing.ports(), vec!["ttymidi", "FLUID Synth"]);
+        assert_eq!(routing.destinations(Source::Left).count(), 1);
+
+        routing.add(routing.routes()[0].clone());
+        assert_eq!(routing.routes().len(), 3);
+        routing.remove(0);
+        assert_eq!(routing.destinations(Source::Left).count(), 0);
+    }
+}
@@ -1,16 +1,56 @@
 use tokio::sync::oneshot;
 
+use crate::metronome::Click;
+use crate::midi::output::OutputPolicy;
+use crate::midi::routing::Route;
 use crate::note::Note;
+use crate::synth::timbre::SynthConfig;
 
 pub enum MidiCommand {
     PlayNotes {
         notes: (Option<Note>, Option<Note>),
         channel: u8,
+        /// Duration of the step in milliseconds, which the clock is spread over.
+        step_duration: f32,
+    },
+    /// Play a metronome click, before the notes of the step.
+    Click {
+        click: Click,
     },
     GetPorts {
         responder: oneshot::Sender<Vec<String>>,
     },
+    /// Select the output port by name, or create a virtual output with
+    /// [VIRTUAL_PORT](crate::midi::ports::VIRTUAL_PORT).
     SetPort {
         out_port: String,
     },
+    /// Select the input port by name, or create a virtual input with
+    /// [VIRTUAL_PORT](crate::midi::ports::VIRTUAL_PORT).
+    SetInputPort {
+        in_port: String,
+    },
+    SetOutputPolicy {
+        policy: OutputPolicy,
+    },
+    AddRoute {
+        route: Route,
+    },
+    RemoveRoute {
+        index: usize,
+    },
+    /// Turn the built-in synth on or off and change its timbres.
+    SetSynth {
+        config: SynthConfig,
+    },
+    /// Start or stop the clock outputs.
+    Transport {
+        playing: bool,
+    },
+    /// Turn off all sounding notes, e.g. when playback stops.
+    AllNotesOff,
+    /// Turn off all notes and sounds on all channels, signalling `done` when sent.
+    Panic {
+        done: Option<oneshot::Sender<()>>,
+    },
 }
@@ -35,7 +35,7 @@ impl Mixer {
         let mut previous_state = MixerState::default();
 
         loop {
-            let state = self.shared_state.read().await.mixer_state.clone();
+            let state = self.shared_state.read().await.mixer_state;
 
             if state != previous_state {
                 debug!("Mixer received update request");
@@ -64,9 +64,9 @@ impl Mixer {
         let len_b = self.sequences.1.notes.len();
 
         let common_factor = if len_a >= len_b {
-            len_a % len_b == 0
+            len_a.is_multiple_of(len_b)
         } else {
-            len_b % len_a == 0
+            len_b.is_multiple_of(len_a)
         };
 
         let sequence_length = if common_factor {
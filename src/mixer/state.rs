@@ -1,11 +1,14 @@
 use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::note::Note;
 
 pub enum MixerInput {
     IncreaseRatio,
     DecreaseRatio,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct MixerState {
     pub ratio: f32,
 }
@@ -20,6 +23,11 @@ impl MixerState {
         debug!("Mixer ratio increased to {}", self.ratio);
     }
 
+    pub fn set_ratio(&mut self, ratio: f32) {
+        self.ratio = ratio.clamp(0.0, 1.0);
+        debug!("Mixer ratio set to {}", self.ratio);
+    }
+
     pub fn decrease_ratio(&mut self) {
         self.ratio = (self.ratio - 0.05).clamp(0.0, 1.0);
         debug!("Mixer ratio decreased to {}", self.ratio);
@@ -31,3 +39,101 @@ impl Default for MixerState {
         Self::new()
     }
 }
+
+/// How a sequencer slot is heard, applied by playback without changing its pattern.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SlotMix {
+    pub muted: bool,
+    /// While any slot is soloed, only soloed slots are heard.
+    pub solo: bool,
+    /// Scale of the velocity of the notes, from 0 to 1.
+    pub volume: f32,
+    /// Toggle the mute at the start of the next bar.
+    pub mute_on_bar: bool,
+}
+
+impl Default for SlotMix {
+    fn default() -> Self {
+        SlotMix {
+            muted: false,
+            solo: false,
+            volume: 1.0,
+            mute_on_bar: false,
+        }
+    }
+}
+
+impl SlotMix {
+    pub fn change_volume(&mut self, delta: f32) {
+        self.volume = (self.volume + delta).clamp(0.0, 1.0);
+        debug!("Slot volume changed to {}", self.volume);
+    }
+
+    /// Apply a pending mute toggle, at the start of a bar.
+    pub fn start_bar(&mut self) {
+        if self.mute_on_bar {
+            self.muted = !self.muted;
+            self.mute_on_bar = false;
+        }
+    }
+
+    /// The note as it is heard, if the slot is heard at all, given the mix of the other slot.
+    fn apply(&self, note: Option<Note>, other: &SlotMix) -> Option<Note> {
+        if self.muted || (other.solo && !self.solo) {
+            return None;
+        }
+        let mut note = note?;
+        // Rests have velocity 0 and stay rests
+        if note.velocity > 0 {
+            note.velocity = (note.velocity as f32 * self.volume).round() as u8;
+            if note.velocity == 0 {
+                return None;
+            }
+        }
+        Some(note)
+    }
+}
+
+/// Apply mute, solo and volume of both slots to the (left, right) notes of a step.
+pub fn mix_step(
+    notes: (Option<Note>, Option<Note>),
+    left: &SlotMix,
+    right: &SlotMix,
+) -> (Option<Note>, Option<Note>) {
+    (left.apply(notes.0, right), right.apply(notes.1, left))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::NoteDuration;
+
+    #[test]
+    fn test_mix_step() {
+        let note = |velocity| Some(Note::new(60, velocity, NoteDuration::Sixteenth));
+        let step = (note(100), note(100));
+        let mut left = SlotMix::default();
+        let mut right = SlotMix::default();
+        assert_eq!(mix_step(step, &left, &right), step);
+
+        left.volume = 0.5;
+        right.muted = true;
+        assert_eq!(mix_step(step, &left, &right), (note(50), None));
+
+        // Solo silences the other slot, even if it is not muted
+        right.muted = false;
+        right.solo = true;
+        assert_eq!(mix_step(step, &left, &right), (None, note(100)));
+        left.solo = true;
+        assert_eq!(mix_step(step, &left, &right), (note(50), note(100)));
+
+        // A volume of 0 silences the notes, but rests are kept
+        left.volume = 0.0;
+        let rest = Some(Note::new(0, 0, NoteDuration::Sixteenth));
+        assert_eq!(mix_step((note(100), rest), &left, &right), (None, rest));
+
+        right.mute_on_bar = true;
+        right.start_bar();
+        assert!(right.muted && !right.mute_on_bar);
+    }
+}
@@ -1,7 +1,15 @@
 use std::fmt::{Debug, Formatter};
 
-/// NoteDuration is a helper enum to define note durations in musical notation. These durations are
-/// then converted to seconds in playback.
+/// Resolution of musical time: durations of notes are in ticks, fractions of a beat (a quarter
+/// note), so they do not depend on the tempo.
+pub const TICKS_PER_BEAT: u32 = 480;
+
+/// Every step of a sequence is a sixteenth note.
+pub const TICKS_PER_STEP: u32 = TICKS_PER_BEAT / 4;
+
+/// NoteDuration is a helper enum to define note durations in musical notation, in sixteenths.
+/// These durations are converted to ticks, and only to milliseconds in playback.
+#[derive(Clone, Copy, Debug)]
 pub enum NoteDuration {
     Sixteenth = 1,
     Eighth = 2,
@@ -13,37 +21,48 @@ pub enum NoteDuration {
     Whole = 16,
 }
 
-/// A Note is a MIDI object with pitch, velocity, duration, and a channel. Duration here is in seconds.
-#[derive(Clone, Copy, Debug)]
+impl NoteDuration {
+    pub fn ticks(self) -> u32 {
+        self as u32 * TICKS_PER_STEP
+    }
+}
+
+/// A Note is a MIDI object with pitch, velocity and duration. Duration here is in ticks of
+/// musical time, see [`TICKS_PER_BEAT`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Note {
     pub pitch: u8,
     pub velocity: u8,
-    pub duration: f32,
+    pub duration: u32,
 }
 
 impl Note {
-    pub fn new(pitch: u8, velocity: u8, note_duration: NoteDuration, bpm: f32) -> Self {
-        let duration = match note_duration {
-            NoteDuration::Sixteenth => 60000.0 / bpm / 4.0,
-            NoteDuration::Eighth => 60000.0 / bpm / 2.0,
-            NoteDuration::DottedEight => 60000.0 / bpm / 2.0 * 1.5,
-            NoteDuration::Quarter => 60000.0 / bpm,
-            NoteDuration::DottedQuarter => 60000.0 / bpm * 1.5,
-            NoteDuration::Half => 60000.0 / bpm * 2.0,
-            NoteDuration::DottedHalf => 60000.0 / bpm * 2.0 * 1.5,
-            NoteDuration::Whole => 60000.0 / bpm * 4.0,
-        };
-
+    pub fn new(pitch: u8, velocity: u8, note_duration: NoteDuration) -> Self {
         Note {
             pitch,
             velocity,
+            duration: note_duration.ticks(),
+        }
+    }
+
+    /// A rest is a Note with pitch and velocity 0.
+    pub fn rest(duration: u32) -> Self {
+        Note {
+            pitch: 0,
+            velocity: 0,
             duration,
         }
     }
+
+    /// The duration in milliseconds when a step lasts `step_duration` milliseconds. Notes are
+    /// converted to wall-clock time only when played, so tempo changes apply immediately.
+    pub fn millis(&self, step_duration: f32) -> f32 {
+        self.duration as f32 / TICKS_PER_STEP as f32 * step_duration
+    }
 }
 
 /// A Sequence is defined as a vector of Notes, produced by one single Sequencer.
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct Sequence {
     pub notes: Vec<Note>,
 }
@@ -74,9 +93,10 @@ impl Sequence {
         note.replace(".", &format!("{octave}"))
     }
 
-    fn duration_to_symbol(duration: f32, total_duration: f32) -> String {
+    fn duration_to_symbol(duration: u32, total_duration: u32) -> String {
         let total_dashes = 40;
-        let num_dashes = (duration / total_duration * total_dashes as f32).round() as usize;
+        let num_dashes =
+            (duration as f32 / total_duration.max(1) as f32 * total_dashes as f32).round() as usize;
         "-".repeat(num_dashes)
     }
 }
@@ -85,7 +105,7 @@ impl Debug for Sequence {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let mut result = String::new();
         result.push_str("Sequence: ");
-        let total_duration = self.notes.iter().map(|note| note.duration).sum::<f32>();
+        let total_duration = self.notes.iter().map(|note| note.duration).sum::<u32>();
 
         for note in &self.notes {
             let note_name = if note.pitch == 0 {
@@ -103,13 +123,13 @@ impl Debug for Sequence {
 
 impl Default for Sequence {
     fn default() -> Self {
-        let notes = vec![Note::new(0, 0, NoteDuration::Sixteenth, 120.0); 16];
+        let notes = vec![Note::new(0, 0, NoteDuration::Sixteenth); 16];
         Sequence { notes }
     }
 }
 
 /// A MixedSequence is the result of mixing two Sequences in the Mixer.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct MixedSequence {
     pub notes: Vec<(Option<Note>, Option<Note>)>,
 }
@@ -130,8 +150,8 @@ impl Default for MixedSequence {
     fn default() -> Self {
         let notes = vec![
             (
-                Some(Note::new(0, 0, NoteDuration::Sixteenth, 120.0)),
-                Some(Note::new(0, 0, NoteDuration::Sixteenth, 120.0))
+                Some(Note::new(0, 0, NoteDuration::Sixteenth)),
+                Some(Note::new(0, 0, NoteDuration::Sixteenth))
             );
             16
         ];
@@ -154,4 +174,13 @@ mod tests {
         assert_eq!(Sequence::midi_to_note_name(96), "C7");
         assert_eq!(Sequence::midi_to_note_name(127), "G9");
     }
+
+    #[test]
+    fn test_note_millis() {
+        let note = Note::new(60, 100, NoteDuration::DottedEight);
+        assert_eq!(note.duration, 3 * TICKS_PER_STEP);
+        // 125 ms per step at 120 BPM, 100 ms at 150 BPM
+        assert_eq!(note.millis(125.0), 375.0);
+        assert_eq!(note.millis(100.0), 300.0);
+    }
 }
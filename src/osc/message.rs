@@ -0,0 +1,214 @@
+use anyhow::{bail, Context, Result};
+
+/// Deepest nesting of bundles in a packet.
+const MAX_BUNDLE_DEPTH: usize = 8;
+
+/// An argument of an OSC message.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+    String(String),
+    Bool(bool),
+}
+
+impl OscArg {
+    /// The argument as a number, with true as 1. Strings and floats that are not finite have
+    /// no value.
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            OscArg::Int(value) => Some(*value as f32),
+            OscArg::Float(value) => value.is_finite().then_some(*value),
+            OscArg::Bool(value) => Some(*value as u8 as f32),
+            OscArg::String(_) => None,
+        }
+    }
+}
+
+/// An OSC 1.0 message: an address pattern followed by typed arguments.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+impl OscMessage {
+    pub fn new(address: impl Into<String>, args: Vec<OscArg>) -> Self {
+        OscMessage {
+            address: address.into(),
+            args,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut packet = Vec::new();
+        write_string(&mut packet, &self.address);
+        let tags = self
+            .args
+            .iter()
+            .map(|arg| match arg {
+                OscArg::Int(_) => 'i',
+                OscArg::Float(_) => 'f',
+                OscArg::String(_) => 's',
+                OscArg::Bool(true) => 'T',
+                OscArg::Bool(false) => 'F',
+            })
+            .collect::<String>();
+        write_string(&mut packet, &format!(",{tags}"));
+        for arg in &self.args {
+            match arg {
+                OscArg::Int(value) => packet.extend_from_slice(&value.to_be_bytes()),
+                OscArg::Float(value) => packet.extend_from_slice(&value.to_be_bytes()),
+                OscArg::String(value) => write_string(&mut packet, value),
+                OscArg::Bool(_) => {}
+            }
+        }
+        packet
+    }
+}
+
+/// Decode a packet into its messages. The messages of bundles are returned in order, and
+/// their time tags are ignored.
+pub fn decode(packet: &[u8]) -> Result<Vec<OscMessage>> {
+    decode_element(packet, 0)
+}
+
+fn decode_element(packet: &[u8], depth: usize) -> Result<Vec<OscMessage>> {
+    let mut reader = Reader {
+        packet,
+        position: 0,
+    };
+    if packet.starts_with(b"#bundle\0") {
+        if depth >= MAX_BUNDLE_DEPTH {
+            bail!("OSC bundles are nested too deeply");
+        }
+        reader.position = 16; // "#bundle" and the time tag
+        let mut messages = Vec::new();
+        while reader.position < packet.len() {
+            let size = reader.read_i32()?;
+            let size = usize::try_from(size)
+                .with_context(|| format!("Invalid OSC bundle element size {size}"))?;
+            let element = reader.read_bytes(size)?;
+            messages.extend(decode_element(element, depth + 1)?);
+        }
+        return Ok(messages);
+    }
+
+    let address = reader.read_string()?;
+    if !address.starts_with('/') {
+        bail!("Invalid OSC address {address}");
+    }
+    // Messages without a type tag string have no arguments
+    if reader.position >= packet.len() {
+        return Ok(vec![OscMessage::new(address, Vec::new())]);
+    }
+    let tags = reader.read_string()?;
+    let Some(tags) = tags.strip_prefix(',') else {
+        bail!("Invalid OSC type tags {tags}");
+    };
+    let args = tags
+        .chars()
+        .map(|tag| match tag {
+            'i' => reader.read_i32().map(OscArg::Int),
+            'f' => reader.read_f32().map(OscArg::Float),
+            's' => reader.read_string().map(OscArg::String),
+            'T' => Ok(OscArg::Bool(true)),
+            'F' => Ok(OscArg::Bool(false)),
+            _ => bail!("Unsupported OSC type tag {tag}"),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(vec![OscMessage::new(address, args)])
+}
+
+/// Write a string with a terminating null, padded to a multiple of 4 bytes.
+fn write_string(packet: &mut Vec<u8>, value: &str) {
+    packet.extend_from_slice(value.as_bytes());
+    let padding = 4 - value.len() % 4;
+    packet.extend(std::iter::repeat_n(0, padding));
+}
+
+struct Reader<'a> {
+    packet: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, size: usize) -> Result<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(size)
+            .context("OSC packet is too short")?;
+        let bytes = self
+            .packet
+            .get(self.position..end)
+            .context("OSC packet is too short")?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn read_i32(&mut self) -> Result<i32> {
+        let bytes = self.read_bytes(4)?;
+        Ok(i32::from_be_bytes(bytes.try_into()?))
+    }
+
+    fn read_f32(&mut self) -> Result<f32> {
+        let bytes = self.read_bytes(4)?;
+        Ok(f32::from_be_bytes(bytes.try_into()?))
+    }
+
+    fn read_string(&mut self) -> Result<String> {
+        let rest = self.packet.get(self.position..).unwrap_or_default();
+        let length = rest
+            .iter()
+            .position(|byte| *byte == 0)
+            .context("OSC string is not terminated")?;
+        let value = String::from_utf8(rest[..length].to_vec()).context("Invalid OSC string")?;
+        self.position += (length / 4 + 1) * 4;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let message = OscMessage::new(
+            "/slot/0/steps",
+            vec![
+                OscArg::Float(12.0),
+                OscArg::Int(-3),
+                OscArg::String("left".to_string()),
+                OscArg::Bool(true),
+            ],
+        );
+        let packet = message.encode();
+        assert_eq!(packet.len() % 4, 0);
+        assert_eq!(&packet[..16], b"/slot/0/steps\0\0\0");
+        assert_eq!(decode(&packet).unwrap(), vec![message.clone()]);
+
+        let mut bundle = b"#bundle\0\0\0\0\0\0\0\0\x01".to_vec();
+        for _ in 0..2 {
+            bundle.extend_from_slice(&(packet.len() as i32).to_be_bytes());
+            bundle.extend_from_slice(&packet);
+        }
+        assert_eq!(decode(&bundle).unwrap(), vec![message.clone(), message]);
+
+        // Negative element sizes and deeply nested bundles are refused
+        let mut negative = b"#bundle\0\0\0\0\0\0\0\0\x01".to_vec();
+        negative.extend_from_slice(&(-4i32).to_be_bytes());
+        assert!(decode(&negative).is_err());
+        let mut nested = packet;
+        for _ in 0..=MAX_BUNDLE_DEPTH {
+            let mut bundle = b"#bundle\0\0\0\0\0\0\0\0\x01".to_vec();
+            bundle.extend_from_slice(&(nested.len() as i32).to_be_bytes());
+            bundle.extend_from_slice(&nested);
+            nested = bundle;
+        }
+        assert!(decode(&nested).is_err());
+
+        assert!(decode(b"/bpm\0\0\0\0,f\0\0").is_err());
+        assert!(decode(b"bpm\0").is_err());
+    }
+}
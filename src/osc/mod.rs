@@ -0,0 +1,307 @@
+pub mod message;
+
+use anyhow::{Context, Result};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+use tokio::sync::RwLock;
+use tokio::time::{interval, Duration, MissedTickBehavior};
+
+use crate::midi::learn::Parameter;
+use crate::state::{SequencerSlot, SharedState};
+use message::{decode, OscArg, OscMessage};
+
+/// File that the OSC settings are loaded from, relative to the working directory.
+pub const OSC_FILE: &str = "osc.toml";
+
+/// How often changes of the state and the playhead are broadcast.
+const BROADCAST_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Maximum number of clients that state changes are sent to, oldest first out.
+const MAX_CLIENTS: usize = 16;
+
+/// Address the playhead, the index of the step being played, is broadcast on.
+const PLAYHEAD_ADDRESS: &str = "/playhead";
+
+/// Parameters of a sequencer, addressed as `/slot/<0|1>/<name>`.
+const SLOT_PARAMETERS: [(Parameter, &str); 8] = [
+    (Parameter::Steps, "steps"),
+    (Parameter::Pulses, "pulses"),
+    (Parameter::Phase, "phase"),
+    (Parameter::Pitch, "pitch"),
+    (Parameter::Mute, "mute"),
+    (Parameter::Solo, "solo"),
+    (Parameter::Volume, "volume"),
+    (Parameter::MuteNextBar, "mute_next_bar"),
+];
+
+/// Parameters of the whole sequencer, with their addresses.
+const GLOBAL_PARAMETERS: [(Parameter, &str); 4] = [
+    (Parameter::MixerRatio, "/mixer/ratio"),
+    (Parameter::Bpm, "/bpm"),
+    (Parameter::PlayStop, "/transport/play"),
+    (Parameter::ActiveSlot, "/active_slot"),
+];
+
+/// Settings of the OSC server.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OscConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// UDP port the server listens on.
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Addresses, e.g. `192.168.1.20:9001`, that state changes are always sent to, in
+    /// addition to every client that sent a message.
+    #[serde(default)]
+    pub targets: Vec<String>,
+}
+
+fn default_enabled() -> bool {
+    false
+}
+
+fn default_port() -> u16 {
+    9000
+}
+
+impl Default for OscConfig {
+    fn default() -> Self {
+        OscConfig {
+            enabled: default_enabled(),
+            port: default_port(),
+            targets: Vec::new(),
+        }
+    }
+}
+
+impl OscConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read OSC settings from {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse OSC settings from {}", path.display()))
+    }
+}
+
+fn slot_index(slot: &SequencerSlot) -> usize {
+    match slot {
+        SequencerSlot::Left => 0,
+        SequencerSlot::Right => 1,
+    }
+}
+
+/// The parameter at an address, with the slot for parameters of a sequencer.
+pub fn parse_address(address: &str) -> Option<(Parameter, SequencerSlot)> {
+    if let Some(&(parameter, _)) = GLOBAL_PARAMETERS
+        .iter()
+        .find(|(_, global)| *global == address)
+    {
+        return Some((parameter, SequencerSlot::Left));
+    }
+    let mut parts = address.strip_prefix("/slot/")?.split('/');
+    let slot = match parts.next()? {
+        "0" => SequencerSlot::Left,
+        "1" => SequencerSlot::Right,
+        _ => return None,
+    };
+    let name = parts.next()?;
+    let &(parameter, _) = SLOT_PARAMETERS.iter().find(|(_, n)| *n == name)?;
+    parts.next().is_none().then_some((parameter, slot))
+}
+
+/// All parameters and the playhead as messages, as they are broadcast.
+pub fn state_messages(state: &SharedState) -> Vec<OscMessage> {
+    let slots = [SequencerSlot::Left, SequencerSlot::Right];
+    let slot_messages = slots.iter().flat_map(|slot| {
+        SLOT_PARAMETERS.iter().map(move |(parameter, name)| {
+            OscMessage::new(
+                format!("/slot/{}/{}", slot_index(slot), name),
+                vec![OscArg::Float(state.slot_parameter_value(slot, *parameter))],
+            )
+        })
+    });
+    let global_messages = GLOBAL_PARAMETERS.iter().map(|(parameter, address)| {
+        OscMessage::new(
+            *address,
+            vec![OscArg::Float(state.parameter_value(*parameter))],
+        )
+    });
+    let playhead = OscMessage::new(
+        PLAYHEAD_ADDRESS,
+        vec![OscArg::Int(state.current_note_index as i32)],
+    );
+    slot_messages
+        .chain(global_messages)
+        .chain(std::iter::once(playhead))
+        .collect()
+}
+
+/// Apply a message to the state. Returns the current value of the parameter when the
+/// message has no value, so that clients can query it.
+pub fn handle_message(state: &mut SharedState, message: &OscMessage) -> Option<OscMessage> {
+    let Some((parameter, slot)) = parse_address(&message.address) else {
+        warn!("Unknown OSC address {}", message.address);
+        return None;
+    };
+    match message.args.first().and_then(OscArg::as_f32) {
+        Some(value) => {
+            state.set_slot_parameter(&slot, parameter, value);
+            None
+        }
+        None => Some(OscMessage::new(
+            message.address.clone(),
+            vec![OscArg::Float(state.slot_parameter_value(&slot, parameter))],
+        )),
+    }
+}
+
+/// Receives OSC messages over UDP to control the sequencer, and broadcasts changes of the
+/// state and the playhead to all clients.
+pub struct OscServer {
+    socket: UdpSocket,
+    shared_state: Arc<RwLock<SharedState>>,
+    clients: Vec<SocketAddr>,
+    /// Messages that were last broadcast, to only send changes.
+    sent: Vec<OscMessage>,
+}
+
+impl OscServer {
+    pub async fn bind(config: &OscConfig, shared_state: Arc<RwLock<SharedState>>) -> Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", config.port))
+            .await
+            .with_context(|| format!("Failed to listen for OSC on UDP port {}", config.port))?;
+        let mut clients = Vec::new();
+        for target in &config.targets {
+            match tokio::net::lookup_host(target).await.map(|mut a| a.next()) {
+                Ok(Some(address)) => clients.push(address),
+                _ => warn!("Unknown OSC target {target}"),
+            }
+        }
+        info!("Listening for OSC on UDP port {}", config.port);
+        Ok(OscServer {
+            socket,
+            shared_state,
+            clients,
+            sent: Vec::new(),
+        })
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        let mut broadcast = interval(BROADCAST_INTERVAL);
+        broadcast.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut buffer = [0; 4096];
+
+        loop {
+            tokio::select! {
+                received = self.socket.recv_from(&mut buffer) => {
+                    let (size, address) = received?;
+                    self.receive(&buffer[..size], address).await;
+                }
+                _ = broadcast.tick() => self.broadcast().await,
+            }
+        }
+    }
+
+    async fn receive(&mut self, packet: &[u8], address: SocketAddr) {
+        let messages = match decode(packet) {
+            Ok(messages) => messages,
+            Err(e) => {
+                warn!("Invalid OSC packet from {address}: {e:#}");
+                return;
+            }
+        };
+        if !self.clients.contains(&address) {
+            info!("New OSC client {address}");
+            if self.clients.len() >= MAX_CLIENTS {
+                self.clients.remove(0);
+            }
+            self.clients.push(address);
+            // A new client gets the whole state with the next broadcast
+            self.sent.clear();
+        }
+
+        let replies = {
+            let mut w_state = self.shared_state.write().await;
+            messages
+                .iter()
+                .filter_map(|message| handle_message(&mut w_state, message))
+                .collect::<Vec<_>>()
+        };
+        for reply in replies {
+            self.send(&reply, address).await;
+        }
+    }
+
+    /// Send the messages that changed since the last broadcast to all clients.
+    async fn broadcast(&mut self) {
+        if self.clients.is_empty() {
+            return;
+        }
+        let messages = state_messages(&*self.shared_state.read().await);
+        let changed = messages
+            .iter()
+            .filter(|message| !self.sent.contains(message))
+            .cloned()
+            .collect::<Vec<_>>();
+        for message in &changed {
+            for client in self.clients.clone() {
+                self.send(message, client).await;
+            }
+        }
+        self.sent = messages;
+    }
+
+    async fn send(&self, message: &OscMessage, address: SocketAddr) {
+        if let Err(e) = self.socket.send_to(&message.encode(), address).await {
+            error!("Failed to send OSC message to {address}: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle_message() {
+        let mut state = SharedState::new(120.0);
+        let set = |address: &str, value: f32| {
+            OscMessage::new(address.to_string(), vec![OscArg::Float(value)])
+        };
+
+        handle_message(&mut state, &set("/slot/1/steps", 12.0));
+        handle_message(&mut state, &set("/slot/0/pulses", 3.0));
+        handle_message(&mut state, &set("/bpm", 90.0));
+        handle_message(&mut state, &set("/transport/play", 1.0));
+        assert_eq!(state.right_state.steps, 12);
+        assert_eq!(state.left_state.pulses, 3);
+        assert_eq!(state.bpm, 90.0);
+        assert!(state.playing);
+
+        let query = OscMessage::new("/slot/1/steps", Vec::new());
+        assert_eq!(
+            handle_message(&mut state, &query),
+            Some(set("/slot/1/steps", 12.0))
+        );
+        assert_eq!(handle_message(&mut state, &set("/slot/2/steps", 1.0)), None);
+        assert_eq!(parse_address("/slot/0/steps/extra"), None);
+
+        let messages = state_messages(&state);
+        assert!(messages.contains(&set("/mixer/ratio", state.mixer_state.ratio)));
+        assert!(messages.contains(&OscMessage::new(PLAYHEAD_ADDRESS, vec![OscArg::Int(0)])));
+
+        // Values that are not finite are ignored
+        handle_message(&mut state, &set("/slot/0/steps", f32::NAN));
+        handle_message(&mut state, &set("/bpm", f32::NAN));
+        handle_message(&mut state, &set("/bpm", f32::INFINITY));
+        assert_eq!(state.left_state.steps, 16);
+        assert_eq!(state.bpm, 90.0);
+        state.set_slot_parameter(&SequencerSlot::Left, Parameter::Steps, f32::NAN);
+        assert_eq!(state.left_state.steps, 16);
+    }
+}
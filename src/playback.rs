@@ -6,6 +6,7 @@ use tokio::{
     time::{sleep, Duration},
 };
 
+use crate::link::session::now_micros;
 use crate::note::MixedSequence;
 use crate::state::*;
 use crate::{
@@ -13,6 +14,9 @@ use crate::{
     midi::state::MidiCommand,
 };
 
+/// Beats of the Link timeline per step.
+const LINK_STEP_BEATS: f64 = 0.25;
+
 pub struct PlaybackHandler {
     tx_midi: mpsc::Sender<MidiCommand>,
     rx_sequence: mpsc::Receiver<MixedSequence>,
@@ -39,6 +43,9 @@ impl PlaybackHandler {
         info!("Starting playback loop");
         let mut current_note_index = 0;
         let mut sequence = MixedSequence::default();
+        let (mut was_playing, mut last_channel) = (false, 0);
+        // The beat of the Link timeline that the next step is played on
+        let mut link_beat: Option<f64> = None;
 
         loop {
             if let Ok(seq) = self.rx_sequence.try_recv() {
@@ -47,6 +54,7 @@ impl PlaybackHandler {
                     seq,
                     seq.notes.len()
                 );
+                self.shared_state.write().await.mixed_sequence = seq.clone();
                 sequence = seq;
                 current_note_index = if sequence.notes.is_empty() {
                     0
@@ -55,18 +63,96 @@ impl PlaybackHandler {
                 };
             }
 
-            let (is_playing, midi_channel_for_note) = {
+            let (is_playing, midi_channel_for_note, step_duration, link_timeline, quantum) = {
                 let r_state = self.shared_state.read().await;
-                (r_state.playing, r_state.midi_channel)
+                (
+                    r_state.playing,
+                    r_state.midi_channel,
+                    r_state.step_duration(),
+                    r_state.link.timeline,
+                    r_state.time_signature.quarters_per_bar(),
+                )
             };
 
+            if is_playing && !was_playing {
+                let mut w_state = self.shared_state.write().await;
+                w_state.transport_steps = 0;
+                w_state.start_count_in();
+            }
+            if is_playing != was_playing {
+                self.tx_midi
+                    .send(MidiCommand::Transport {
+                        playing: is_playing,
+                    })
+                    .await?;
+            }
+            // Notes must not keep sounding after stopping or on the old channel
+            if (was_playing && !is_playing) || midi_channel_for_note != last_channel {
+                self.tx_midi.send(MidiCommand::AllNotesOff).await?;
+            }
+            was_playing = is_playing;
+            last_channel = midi_channel_for_note;
+
             if is_playing {
                 if sequence.notes.is_empty() {
                     sleep(Duration::from_millis(10)).await;
                     continue;
                 }
 
-                let note = sequence.notes[current_note_index];
+                // With Link, steps are played on the session's timeline, starting on a bar
+                let step_duration = match link_timeline {
+                    Some(timeline) => {
+                        let now = now_micros();
+                        let bar_micros = (timeline.micros_per_beat as f64 * quantum) as i64;
+                        let beat = match link_beat {
+                            None => {
+                                current_note_index = 0;
+                                timeline.next_bar(now, quantum)
+                            }
+                            // The timeline jumped, e.g. when joining another session
+                            Some(beat)
+                                if timeline.time_at(beat).saturating_sub(now).saturating_abs()
+                                    > bar_micros =>
+                            {
+                                debug!("Resyncing playback to the Link timeline");
+                                (timeline.beat_at(now) / LINK_STEP_BEATS).ceil() * LINK_STEP_BEATS
+                            }
+                            Some(beat) => beat,
+                        };
+                        let start = timeline.time_at(beat);
+                        if start > now {
+                            sleep(Duration::from_micros((start - now) as u64)).await;
+                        }
+                        link_beat = Some(beat + LINK_STEP_BEATS);
+                        timeline
+                            .time_at(beat + LINK_STEP_BEATS)
+                            .saturating_sub(now_micros())
+                            .max(0) as f32
+                            / 1000.0
+                    }
+                    None => {
+                        link_beat = None;
+                        step_duration
+                    }
+                };
+
+                let (click, counting_in) = {
+                    let r_state = self.shared_state.read().await;
+                    (r_state.click(), r_state.count_in > 0)
+                };
+                if let Some(click) = click {
+                    self.tx_midi.send(MidiCommand::Click { click }).await?;
+                }
+
+                // Nothing but the click is played during the count-in
+                let note = if counting_in {
+                    (None, None)
+                } else {
+                    self.shared_state
+                        .read()
+                        .await
+                        .mix_step(sequence.notes[current_note_index])
+                };
                 debug!(
                     "Playing note: {:?} at index {}/{}",
                     note,
@@ -78,23 +164,34 @@ impl PlaybackHandler {
                     .send(MidiCommand::PlayNotes {
                         notes: (note),
                         channel: midi_channel_for_note,
+                        step_duration,
                     })
                     .await?;
 
-                // Move to the next note
-                current_note_index = (current_note_index + 1) % sequence.notes.len();
-
                 // Quickly update current_note_index
-                {
+                if counting_in {
+                    self.shared_state.write().await.count_in -= 1;
+                } else {
+                    // Move to the next note
+                    current_note_index = (current_note_index + 1) % sequence.notes.len();
+
                     let mut w_state = self.shared_state.write().await;
+                    if w_state.advance_transport() {
+                        current_note_index = 0;
+                    }
                     w_state.current_note_index = current_note_index;
+                    w_state.record_played_step(note);
+                    w_state.advance_recording();
+                    w_state.advance_morph();
+                    w_state.advance_tempo_ramp();
+                    w_state.advance_arrangement();
                 }
 
                 let r_state = self.shared_state.read().await;
                 if let Some(mut tx) = self.tx_gui.lock().unwrap().clone() {
-                    if let Err(e) =
-                        tx.try_send(Message::ReceivedEvent(Event::StateChanged(r_state.clone())))
-                    {
+                    if let Err(e) = tx.try_send(Message::ReceivedEvent(Event::StateChanged(
+                        Box::new(r_state.clone()),
+                    ))) {
                         error!(
                             "Playback: Error sending Message::ReceivedEvent to GUI: {:?}",
                             e
@@ -103,6 +200,7 @@ impl PlaybackHandler {
                 }
                 drop(r_state);
             } else {
+                link_beat = None;
                 tokio::time::sleep(Duration::from_millis(50)).await;
             }
 
@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::history::PerformanceState;
+
+/// Number of scenes that can be stored and recalled with the number keys.
+pub const SCENE_SLOTS: usize = 8;
+
+/// File that scenes are saved to and loaded from, relative to the working directory.
+pub const SCENES_FILE: &str = "scenes.toml";
+
+/// Number of steps in a bar of 4/4, the default time signature. Every step is a sixteenth note.
+pub const STEPS_PER_BAR: usize = 16;
+
+/// A Scene is a named snapshot of the complete performance state.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Scene {
+    pub slot: usize,
+    pub name: String,
+    pub state: PerformanceState,
+}
+
+/// Holds all stored scenes, at most one per slot.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SceneBank {
+    #[serde(default)]
+    scenes: Vec<Scene>,
+}
+
+impl SceneBank {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read scenes from {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse scenes from {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents = toml::to_string(self).context("Failed to serialize scenes")?;
+        fs::write(path, contents)
+            .with_context(|| format!("Failed to write scenes to {}", path.display()))
+    }
+
+    pub fn get(&self, slot: usize) -> Option<&Scene> {
+        self.scenes.iter().find(|scene| scene.slot == slot)
+    }
+
+    /// Store a scene in the given slot, replacing any scene that was already there.
+    pub fn store(&mut self, slot: usize, name: String, state: PerformanceState) {
+        self.scenes.retain(|scene| scene.slot != slot);
+        self.scenes.push(Scene { slot, name, state });
+        self.scenes.sort_by_key(|scene| scene.slot);
+    }
+}
+
+/// A Morph gradually interpolates the performance state from one scene to another.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Morph {
+    pub from: PerformanceState,
+    pub to: PerformanceState,
+    pub total_steps: usize,
+    pub elapsed_steps: usize,
+}
+
+impl Morph {
+    pub fn new(
+        from: PerformanceState,
+        to: PerformanceState,
+        bars: usize,
+        steps_per_bar: usize,
+    ) -> Self {
+        Morph {
+            from,
+            to,
+            total_steps: (bars * steps_per_bar).max(1),
+            elapsed_steps: 0,
+        }
+    }
+
+    /// Advance the morph by one step, returning the interpolated state.
+    pub fn advance(&mut self) -> PerformanceState {
+        self.elapsed_steps = (self.elapsed_steps + 1).min(self.total_steps);
+        let t = self.elapsed_steps as f32 / self.total_steps as f32;
+        self.from.interpolate(&self.to, t)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed_steps >= self.total_steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mixer::state::MixerState;
+    use crate::sequencers::common::SequencerType;
+    use crate::sequencers::euclidean::state::{EuclideanSequencerState, PitchLane, MAX_PITCH_LANE};
+    use crate::sequencers::step::state::StepSequencerState;
+
+    fn performance_state(steps: usize, bpm: f32) -> PerformanceState {
+        let mut left_state = EuclideanSequencerState::new();
+        left_state.steps = steps;
+        PerformanceState {
+            left_state,
+            right_state: EuclideanSequencerState::new(),
+            mixer_state: MixerState::new(),
+            bpm,
+            left_step_state: StepSequencerState::new(),
+            right_step_state: StepSequencerState::new(),
+            left_sequencer_type: SequencerType::Euclidean,
+            right_sequencer_type: SequencerType::Euclidean,
+        }
+    }
+
+    #[test]
+    fn test_morph() {
+        let mut morph = Morph::new(
+            performance_state(8, 100.),
+            performance_state(16, 140.),
+            1,
+            STEPS_PER_BAR,
+        );
+
+        let mut state = morph.from;
+        for _ in 0..STEPS_PER_BAR / 2 {
+            state = morph.advance();
+        }
+        assert_eq!(state.left_state.steps, 12);
+        assert_eq!(state.bpm, 120.);
+        assert!(!morph.is_finished());
+
+        while !morph.is_finished() {
+            state = morph.advance();
+        }
+        assert_eq!(state, performance_state(16, 140.));
+    }
+
+    #[test]
+    fn test_scene_bank_roundtrip() {
+        let mut bank = SceneBank::default();
+        bank.store(3, "Chorus".to_string(), performance_state(12, 128.));
+        bank.store(0, "Intro".to_string(), performance_state(8, 110.));
+        bank.store(3, "Chorus 2".to_string(), performance_state(10, 128.));
+
+        let serialized = toml::to_string(&bank).unwrap();
+        let deserialized: SceneBank = toml::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, bank);
+        assert_eq!(deserialized.get(3).unwrap().name, "Chorus 2");
+        assert!(deserialized.get(1).is_none());
+    }
+
+    #[test]
+    fn test_invalid_states_are_clamped() {
+        let state: EuclideanSequencerState =
+            toml::from_str("steps = 0\npulses = 5\nphase = 3\npitch = 200").unwrap();
+        assert_eq!(
+            (state.steps, state.pulses, state.phase, state.pitch),
+            (1, 1, 0, 127)
+        );
+        assert_eq!(state.beat_locations(), vec![0]);
+
+        let state: EuclideanSequencerState =
+            toml::from_str("steps = 40\npulses = 20\nphase = 17\npitch = 60").unwrap();
+        assert_eq!((state.steps, state.pulses, state.phase), (16, 16, 1));
+
+        let mut lane = toml::to_string(&PitchLane::from_pitches(60, &[60, 62, 67])).unwrap();
+        lane = lane.replace("len = 3", "len = 99");
+        let lane: PitchLane = toml::from_str(&lane).unwrap();
+        assert_eq!(lane.offsets().len(), MAX_PITCH_LANE);
+    }
+}
@@ -0,0 +1,97 @@
+pub mod state;
+
+use crate::note::Sequence;
+use crate::sequencers::arpeggiator::state::ArpeggiatorState;
+use crate::sequencers::common::{Sequencer, SequencerType};
+use crate::sequencers::euclidean::state::EuclideanSequencerState;
+
+use crate::state::{SequencerSlot, SharedState};
+use anyhow::Result;
+use log::debug;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::sync::RwLock;
+
+/// Everything the arpeggio of a slot is generated from.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct ArpeggiatorInput {
+    state: ArpeggiatorState,
+    chords: Vec<Vec<u8>>,
+    steps_per_bar: usize,
+    rhythm: Option<EuclideanSequencerState>,
+    generation: u64,
+}
+
+/// Arpeggiates held or laned chords, while its slot is set to the arpeggiator type.
+pub struct ArpeggiatorSequencer {
+    sequencer_slot: SequencerSlot,
+    tx_sequence: mpsc::Sender<(Option<Sequence>, Option<Sequence>)>,
+    cached_input: ArpeggiatorInput,
+    shared_state: Arc<RwLock<SharedState>>,
+}
+
+impl ArpeggiatorSequencer {
+    pub fn new(
+        sequencer_slot: SequencerSlot,
+        tx_sequence: mpsc::Sender<(Option<Sequence>, Option<Sequence>)>,
+        shared_state: Arc<RwLock<SharedState>>,
+    ) -> Self {
+        ArpeggiatorSequencer {
+            sequencer_slot,
+            tx_sequence,
+            cached_input: ArpeggiatorInput::default(),
+            shared_state,
+        }
+    }
+}
+
+impl Sequencer for ArpeggiatorSequencer {
+    async fn generate_sequence(&self) -> Sequence {
+        let input = &self.cached_input;
+        input
+            .state
+            .to_sequence(&input.chords, input.steps_per_bar, input.rhythm.as_ref())
+    }
+
+    async fn run(&mut self) -> Result<()> {
+        let mut previous_input = None;
+
+        loop {
+            let input = {
+                let r_state = self.shared_state.read().await;
+                (r_state.sequencer_type(&self.sequencer_slot) == SequencerType::Arpeggiator).then(
+                    || ArpeggiatorInput {
+                        state: r_state.arpeggiator(&self.sequencer_slot).clone(),
+                        chords: r_state.arpeggiator_chords(&self.sequencer_slot),
+                        steps_per_bar: r_state.steps_per_bar(),
+                        rhythm: r_state.arpeggiator_rhythm(&self.sequencer_slot),
+                        generation: r_state.generation,
+                    },
+                )
+            };
+
+            // The arpeggio is sent again when the slot switches back to the arpeggiator type
+            if input != previous_input {
+                if let Some(input) = &input {
+                    debug!(
+                        "Arpeggiator {:?} new input: {:?}",
+                        self.sequencer_slot, input
+                    );
+                    self.cached_input = input.clone();
+                    let sequence = self.generate_sequence().await;
+                    match self.sequencer_slot {
+                        SequencerSlot::Left => {
+                            self.tx_sequence.send((Some(sequence), None)).await?
+                        }
+                        SequencerSlot::Right => {
+                            self.tx_sequence.send((None, Some(sequence))).await?
+                        }
+                    };
+                }
+                previous_input = input;
+            }
+
+            tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+        }
+    }
+}
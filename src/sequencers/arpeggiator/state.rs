@@ -0,0 +1,396 @@
+use log::info;
+use rand::random_range;
+use std::fmt;
+
+use crate::note::{Note, Sequence, TICKS_PER_STEP};
+use crate::sequencers::euclidean::state::EuclideanSequencerState;
+use crate::sequencers::step::state::MIN_GATE;
+
+/// Maximum number of chords in a chord lane, one per bar.
+pub const MAX_CHORDS: usize = 8;
+
+/// Where the arpeggiator takes its chords from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChordInput {
+    /// The notes held on the MIDI input.
+    #[default]
+    Midi,
+    /// A lane of chords, one per bar.
+    Lane,
+}
+
+impl ChordInput {
+    pub const ALL: [ChordInput; 2] = [ChordInput::Midi, ChordInput::Lane];
+}
+
+impl fmt::Display for ChordInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChordInput::Midi => write!(f, "MIDI input"),
+            ChordInput::Lane => write!(f, "Chord lane"),
+        }
+    }
+}
+
+/// The order the notes of a chord are played in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ArpOrder {
+    #[default]
+    Up,
+    Down,
+    UpDown,
+    Random,
+    AsPlayed,
+}
+
+impl ArpOrder {
+    pub const ALL: [ArpOrder; 5] = [
+        ArpOrder::Up,
+        ArpOrder::Down,
+        ArpOrder::UpDown,
+        ArpOrder::Random,
+        ArpOrder::AsPlayed,
+    ];
+}
+
+impl fmt::Display for ArpOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArpOrder::Up => write!(f, "Up"),
+            ArpOrder::Down => write!(f, "Down"),
+            ArpOrder::UpDown => write!(f, "Up/down"),
+            ArpOrder::Random => write!(f, "Random"),
+            ArpOrder::AsPlayed => write!(f, "As played"),
+        }
+    }
+}
+
+/// How often the arpeggiator plays a note.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ArpRate {
+    #[default]
+    Sixteenth,
+    Eighth,
+    Quarter,
+}
+
+impl ArpRate {
+    pub const ALL: [ArpRate; 3] = [ArpRate::Sixteenth, ArpRate::Eighth, ArpRate::Quarter];
+
+    pub fn steps(&self) -> usize {
+        match self {
+            ArpRate::Sixteenth => 1,
+            ArpRate::Eighth => 2,
+            ArpRate::Quarter => 4,
+        }
+    }
+}
+
+impl fmt::Display for ArpRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArpRate::Sixteenth => write!(f, "1/16"),
+            ArpRate::Eighth => write!(f, "1/8"),
+            ArpRate::Quarter => write!(f, "1/4"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChordQuality {
+    #[default]
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Sus4,
+    Major7,
+    Minor7,
+    Dominant7,
+}
+
+impl ChordQuality {
+    pub const ALL: [ChordQuality; 8] = [
+        ChordQuality::Major,
+        ChordQuality::Minor,
+        ChordQuality::Diminished,
+        ChordQuality::Augmented,
+        ChordQuality::Sus4,
+        ChordQuality::Major7,
+        ChordQuality::Minor7,
+        ChordQuality::Dominant7,
+    ];
+
+    /// Semitones of the notes above the root.
+    pub fn intervals(&self) -> &'static [u8] {
+        match self {
+            ChordQuality::Major => &[0, 4, 7],
+            ChordQuality::Minor => &[0, 3, 7],
+            ChordQuality::Diminished => &[0, 3, 6],
+            ChordQuality::Augmented => &[0, 4, 8],
+            ChordQuality::Sus4 => &[0, 5, 7],
+            ChordQuality::Major7 => &[0, 4, 7, 11],
+            ChordQuality::Minor7 => &[0, 3, 7, 10],
+            ChordQuality::Dominant7 => &[0, 4, 7, 10],
+        }
+    }
+}
+
+impl fmt::Display for ChordQuality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChordQuality::Major => write!(f, "maj"),
+            ChordQuality::Minor => write!(f, "min"),
+            ChordQuality::Diminished => write!(f, "dim"),
+            ChordQuality::Augmented => write!(f, "aug"),
+            ChordQuality::Sus4 => write!(f, "sus4"),
+            ChordQuality::Major7 => write!(f, "maj7"),
+            ChordQuality::Minor7 => write!(f, "min7"),
+            ChordQuality::Dominant7 => write!(f, "7"),
+        }
+    }
+}
+
+/// A chord of a chord lane.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Chord {
+    pub root: u8,
+    pub quality: ChordQuality,
+}
+
+impl Default for Chord {
+    fn default() -> Self {
+        Chord {
+            root: 60,
+            quality: ChordQuality::Major,
+        }
+    }
+}
+
+impl Chord {
+    pub fn pitches(&self) -> Vec<u8> {
+        self.quality
+            .intervals()
+            .iter()
+            .map(|interval| self.root.saturating_add(*interval).min(127))
+            .collect()
+    }
+}
+
+/// The notes held on the MIDI input, in the order they were played.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HeldNotes {
+    /// Keys that are down.
+    pressed: Vec<u8>,
+    /// Notes that are arpeggiated. With latch they stay after the keys are released, until
+    /// a new chord is played.
+    chord: Vec<u8>,
+}
+
+impl HeldNotes {
+    pub fn note_on(&mut self, pitch: u8) {
+        if self.pressed.is_empty() {
+            self.chord.clear();
+        }
+        if !self.pressed.contains(&pitch) {
+            self.pressed.push(pitch);
+        }
+        if !self.chord.contains(&pitch) {
+            self.chord.push(pitch);
+        }
+    }
+
+    pub fn note_off(&mut self, pitch: u8, latch: bool) {
+        self.pressed.retain(|p| *p != pitch);
+        if !latch {
+            self.chord.retain(|p| *p != pitch);
+        }
+    }
+
+    pub fn chord(&self) -> &[u8] {
+        &self.chord
+    }
+}
+
+/// An arpeggiator plays the notes of a chord one after another.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArpeggiatorState {
+    pub input: ChordInput,
+    pub order: ArpOrder,
+    /// Number of octaves the chord is played over, from 1 to 4.
+    pub octaves: usize,
+    pub rate: ArpRate,
+    /// Length of the notes as a fraction of a step, from MIN_GATE to 1.
+    pub gate: f32,
+    /// Keep playing a chord from the MIDI input after its keys are released.
+    pub latch: bool,
+    /// Play a note on every pulse of the Euclidean pattern of the other slot, instead of at
+    /// the rate.
+    pub rhythm_gate: bool,
+    pub chord_lane: Vec<Chord>,
+}
+
+impl ArpeggiatorState {
+    pub fn new() -> Self {
+        ArpeggiatorState {
+            input: ChordInput::Midi,
+            order: ArpOrder::Up,
+            octaves: 1,
+            rate: ArpRate::Sixteenth,
+            gate: 0.5,
+            latch: false,
+            rhythm_gate: false,
+            chord_lane: vec![Chord::default()],
+        }
+    }
+
+    pub fn add_chord(&mut self) {
+        if self.chord_lane.len() < MAX_CHORDS {
+            let chord = self.chord_lane.last().copied().unwrap_or_default();
+            self.chord_lane.push(chord);
+            info!("Chord lane: {} chords", self.chord_lane.len());
+        }
+    }
+
+    /// Remove a chord from the lane, keeping at least one.
+    pub fn remove_chord(&mut self, index: usize) {
+        if self.chord_lane.len() > 1 && index < self.chord_lane.len() {
+            self.chord_lane.remove(index);
+            info!("Chord lane: {} chords", self.chord_lane.len());
+        }
+    }
+
+    /// The notes of a chord in the order they are played, over all octaves. Random order
+    /// picks from the notes going up.
+    pub fn pattern(&self, chord: &[u8]) -> Vec<u8> {
+        let mut notes = chord.to_vec();
+        if self.order != ArpOrder::AsPlayed {
+            notes.sort();
+            notes.dedup();
+        }
+        let mut notes = (0..self.octaves.clamp(1, 4) as u8)
+            .flat_map(|octave| notes.iter().map(move |pitch| pitch + 12 * octave))
+            .filter(|pitch| *pitch <= 127)
+            .collect::<Vec<_>>();
+
+        match self.order {
+            ArpOrder::Down => notes.reverse(),
+            ArpOrder::UpDown if notes.len() > 2 => {
+                // The highest and lowest notes are not repeated when turning around
+                let down = notes[1..notes.len() - 1]
+                    .iter()
+                    .rev()
+                    .copied()
+                    .collect::<Vec<_>>();
+                notes.extend(down);
+            }
+            _ => {}
+        }
+        notes
+    }
+
+    /// One bar per chord, with a note at the rate or on every pulse of the rhythm. The pattern
+    /// starts again on every chord.
+    pub fn to_sequence(
+        &self,
+        chords: &[Vec<u8>],
+        steps_per_bar: usize,
+        rhythm: Option<&EuclideanSequencerState>,
+    ) -> Sequence {
+        let rest = Note::rest(TICKS_PER_STEP);
+        let duration =
+            ((self.gate.clamp(MIN_GATE, 1.0) * TICKS_PER_STEP as f32).round() as u32).max(1);
+        let beats = rhythm.map(|rhythm| (rhythm.beat_locations(), rhythm.steps.max(1)));
+
+        let mut notes = Vec::new();
+        for chord in chords.iter().take(MAX_CHORDS) {
+            let pattern = self.pattern(chord);
+            let mut index = 0;
+            for step in 0..steps_per_bar {
+                let plays = match &beats {
+                    Some((beats, steps)) => beats.contains(&(step % steps)),
+                    None => step % self.rate.steps() == 0,
+                };
+                if !plays || pattern.is_empty() {
+                    notes.push(rest);
+                    continue;
+                }
+                let pitch = match self.order {
+                    ArpOrder::Random => pattern[random_range(0..pattern.len())],
+                    _ => pattern[index % pattern.len()],
+                };
+                index += 1;
+                notes.push(Note {
+                    pitch,
+                    velocity: 100,
+                    duration,
+                });
+            }
+        }
+        if notes.is_empty() {
+            notes = vec![rest; steps_per_bar.max(1)];
+        }
+        Sequence { notes }
+    }
+}
+
+impl Default for ArpeggiatorState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pitches(sequence: &Sequence) -> Vec<u8> {
+        sequence.notes.iter().map(|note| note.pitch).collect()
+    }
+
+    #[test]
+    fn test_arpeggiate() {
+        let mut state = ArpeggiatorState::new();
+        let chord = [67, 60, 64];
+        assert_eq!(state.pattern(&chord), vec![60, 64, 67]);
+
+        state.order = ArpOrder::AsPlayed;
+        state.octaves = 2;
+        assert_eq!(state.pattern(&chord), vec![67, 60, 64, 79, 72, 76]);
+
+        state.order = ArpOrder::UpDown;
+        state.octaves = 1;
+        assert_eq!(state.pattern(&chord), vec![60, 64, 67, 64]);
+
+        state.order = ArpOrder::Down;
+        state.rate = ArpRate::Eighth;
+        let sequence = state.to_sequence(&[chord.to_vec()], 8, None);
+        assert_eq!(pitches(&sequence), vec![67, 0, 64, 0, 60, 0, 67, 0]);
+
+        // Every pulse of the other slot plays the next note, and every chord lasts a bar
+        let mut rhythm = EuclideanSequencerState::new();
+        rhythm.steps = 4;
+        rhythm.pulses = 1;
+        let chords = [chord.to_vec(), Chord::default().pitches()];
+        let sequence = state.to_sequence(&chords, 8, Some(&rhythm));
+        assert_eq!(
+            pitches(&sequence),
+            vec![67, 0, 0, 0, 64, 0, 0, 0, 67, 0, 0, 0, 64, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_held_notes() {
+        let mut held = HeldNotes::default();
+        held.note_on(64);
+        held.note_on(60);
+        held.note_off(64, false);
+        assert_eq!(held.chord(), &[60]);
+
+        // With latch the chord stays until a new one is played
+        held.note_off(60, true);
+        assert_eq!(held.chord(), &[60]);
+        held.note_on(62);
+        assert_eq!(held.chord(), &[62]);
+    }
+}
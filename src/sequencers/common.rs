@@ -1,7 +1,37 @@
 use crate::note::Sequence;
 use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
 pub trait Sequencer {
     fn generate_sequence(&self) -> impl std::future::Future<Output = Sequence> + Send;
     fn run(&mut self) -> impl std::future::Future<Output = Result<(), Error>> + Send;
 }
+
+/// The kind of sequencer that generates the sequence of a slot.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SequencerType {
+    #[default]
+    Euclidean,
+    Step,
+    Arpeggiator,
+}
+
+impl SequencerType {
+    pub const ALL: [SequencerType; 3] = [
+        SequencerType::Euclidean,
+        SequencerType::Step,
+        SequencerType::Arpeggiator,
+    ];
+}
+
+impl fmt::Display for SequencerType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SequencerType::Euclidean => write!(f, "Euclidean"),
+            SequencerType::Step => write!(f, "Step"),
+            SequencerType::Arpeggiator => write!(f, "Arpeggiator"),
+        }
+    }
+}
@@ -43,7 +43,7 @@ impl Gui {
         }
     }
 
-    pub fn view(&self) -> Element<Message> {
+    pub fn view(&self) -> Element<'_, Message> {
         let canvas = Canvas::new(self).width(Length::Fill).height(Length::Fill);
         let content = column![canvas].align_x(Center);
         container(content)
@@ -76,15 +76,27 @@ impl canvas::Program<Message> for Gui {
         let start_x = center.x - 1.5 * CIRCLE_SPACING - 2. * CIRCLE_RADIUS;
         let start_y = center.y - 1.5 * CIRCLE_SPACING - 2. * CIRCLE_RADIUS;
 
-        let sequencer_state = if self.slot == SequencerSlot::Left {
-            self.state.left_state
+        let (sequencer_state, fixed_sequence) = if self.slot == SequencerSlot::Left {
+            (self.state.left_state, &self.state.left_fixed_sequence)
         } else {
-            self.state.right_state
+            (self.state.right_state, &self.state.right_fixed_sequence)
         };
 
-        let beat_locations = (0..sequencer_state.pulses)
-            .map(|i| (i * sequencer_state.steps) / sequencer_state.pulses)
-            .collect::<Vec<_>>();
+        // A fixed sequence is shown one bar of 16 steps at a time
+        let (steps, beat_locations, current_step) = match fixed_sequence {
+            Some(sequence) if !sequence.notes.is_empty() => {
+                let index = self.state.current_note_index % sequence.notes.len();
+                let bar_start = index / 16 * 16;
+                let bar = &sequence.notes[bar_start..(bar_start + 16).min(sequence.notes.len())];
+                let hits = (0..bar.len()).filter(|i| bar[*i].pitch != 0).collect();
+                (bar.len(), hits, index - bar_start)
+            }
+            _ => (
+                sequencer_state.steps,
+                sequencer_state.beat_locations(),
+                self.state.current_note_index % sequencer_state.steps,
+            ),
+        };
 
         for row in 0..4 {
             for col in 0..4 {
@@ -106,12 +118,12 @@ impl canvas::Program<Message> for Gui {
                 // pulses and current playing note
                 let color = if beat_locations.contains(&(4 * row + col)) {
                     self.theme.accent_color
-                } else if 4 * row + col >= sequencer_state.steps {
+                } else if 4 * row + col >= steps {
                     self.theme.accent_color_muted
                 } else {
                     self.theme.surface_color
                 };
-                if 4 * row + col == self.state.current_note_index % sequencer_state.steps {
+                if 4 * row + col == current_step {
                     frame.fill(&circle, self.theme.primary_color);
                 } else {
                     frame.fill(&circle, color);
@@ -139,7 +151,11 @@ impl canvas::Program<Message> for Gui {
         );
 
         // show note info - text
-        let note_info = Sequence::midi_to_note_name(sequencer_state.pitch);
+        let note_info = if fixed_sequence.is_some() {
+            "Imported".to_string()
+        } else {
+            Sequence::midi_to_note_name(sequencer_state.pitch)
+        };
         let text = Text {
             content: note_info,
             position: box_center,
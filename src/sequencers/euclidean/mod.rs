@@ -1,8 +1,8 @@
 pub mod gui;
 pub mod state;
 
-use crate::note::{Note, NoteDuration, Sequence};
-use crate::sequencers::common::Sequencer;
+use crate::note::Sequence;
+use crate::sequencers::common::{Sequencer, SequencerType};
 use crate::sequencers::euclidean::state::EuclideanSequencerState;
 
 use crate::state::{SequencerSlot, SharedState};
@@ -36,31 +36,7 @@ impl EuclideanSequencer {
 
 impl Sequencer for EuclideanSequencer {
     async fn generate_sequence(&self) -> Sequence {
-        let bpm = self.shared_state.read().await.bpm;
-
-        if self.cached_state.pulses == 0 {
-            // Handle zero pulses case
-            let note = Note::new(0, 0, NoteDuration::Sixteenth, bpm);
-            return Sequence {
-                notes: vec![note; self.cached_state.steps],
-            };
-        }
-
-        let mut sequence = Sequence::empty();
-
-        // Bresenham line algorithm cus it looks easier
-        let beat_locations = (0..self.cached_state.pulses)
-            .map(|i| (i * self.cached_state.steps) / self.cached_state.pulses)
-            .collect::<Vec<_>>();
-
-        for i in 0..self.cached_state.steps {
-            let note = if beat_locations.contains(&(i % self.cached_state.steps)) {
-                Note::new(self.cached_state.pitch, 100, NoteDuration::Sixteenth, bpm)
-            } else {
-                Note::new(0, 0, NoteDuration::Sixteenth, bpm)
-            };
-            sequence.notes.push(note);
-        }
+        let sequence = self.cached_state.to_sequence();
         debug!(
             "Generated sequence {:?} of length {}",
             sequence,
@@ -71,20 +47,42 @@ impl Sequencer for EuclideanSequencer {
 
     async fn run(&mut self) -> Result<()> {
         let mut previous_state = EuclideanSequencerState::new();
+        let mut previous_fixed_sequence = None;
+        let mut previous_generation = 0;
+        let mut was_active = true;
 
         loop {
-            let state = match self.sequencer_slot {
-                SequencerSlot::Left => self.shared_state.read().await.left_state,
-                SequencerSlot::Right => self.shared_state.read().await.right_state,
+            let (state, fixed_sequence, generation, active) = {
+                let r_state = self.shared_state.read().await;
+                let (state, fixed_sequence) = match self.sequencer_slot {
+                    SequencerSlot::Left => {
+                        (r_state.left_state, r_state.left_fixed_sequence.clone())
+                    }
+                    SequencerSlot::Right => {
+                        (r_state.right_state, r_state.right_fixed_sequence.clone())
+                    }
+                };
+                let active =
+                    r_state.sequencer_type(&self.sequencer_slot) == SequencerType::Euclidean;
+                (state, fixed_sequence, r_state.generation, active)
             };
 
-            if state != previous_state {
+            // Another type of sequencer plays the slot, until it is switched back
+            if active
+                && (!was_active
+                    || state != previous_state
+                    || fixed_sequence != previous_fixed_sequence
+                    || generation != previous_generation)
+            {
                 debug!(
                     "Euclidean sequencer {:?} new state: {:?}",
                     self.sequencer_slot, state
                 );
                 self.cached_state = state;
-                let sequence = self.generate_sequence().await;
+                let sequence = match &fixed_sequence {
+                    Some(sequence) => sequence.clone(),
+                    None => self.generate_sequence().await,
+                };
                 {
                     match self.sequencer_slot {
                         SequencerSlot::Left => {
@@ -100,7 +98,10 @@ impl Sequencer for EuclideanSequencer {
                     };
                 }
                 previous_state = state;
+                previous_fixed_sequence = fixed_sequence;
+                previous_generation = generation;
             }
+            was_active = active;
 
             tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
         }
@@ -1,4 +1,7 @@
 use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::note::{Note, NoteDuration, Sequence};
 
 pub enum EuclideanSequencerInput {
     IncreaseSteps,
@@ -13,12 +16,101 @@ pub enum EuclideanSequencerInput {
     DecreaseOctave,
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Maximum number of pitches in a pitch lane, one for each possible pulse.
+pub const MAX_PITCH_LANE: usize = 16;
+
+/// A PitchLane holds pitch offsets from the sequencer pitch. Each pulse uses the next offset,
+/// so a melody can be played on an Euclidean rhythm and still be transposed as a whole.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredPitchLane")]
+pub struct PitchLane {
+    offsets: [i8; MAX_PITCH_LANE],
+    len: usize,
+}
+
+/// A [PitchLane] as it is read from a scenes file, before its length is checked.
+#[derive(Deserialize)]
+struct StoredPitchLane {
+    offsets: [i8; MAX_PITCH_LANE],
+    len: usize,
+}
+
+impl From<StoredPitchLane> for PitchLane {
+    fn from(stored: StoredPitchLane) -> Self {
+        PitchLane {
+            offsets: stored.offsets,
+            len: stored.len.min(MAX_PITCH_LANE),
+        }
+    }
+}
+
+impl PitchLane {
+    /// Create a lane from absolute pitches, relative to the given base pitch.
+    pub fn from_pitches(base: u8, pitches: &[u8]) -> Self {
+        let mut lane = PitchLane::default();
+        for pitch in pitches.iter().take(MAX_PITCH_LANE) {
+            lane.push((*pitch as i16 - base as i16).clamp(i8::MIN as i16, i8::MAX as i16) as i8);
+        }
+        lane
+    }
+
+    /// Add an offset to the end of the lane. Returns false if the lane is full.
+    pub fn push(&mut self, offset: i8) -> bool {
+        if self.len >= MAX_PITCH_LANE {
+            return false;
+        }
+        self.offsets[self.len] = offset;
+        self.len += 1;
+        true
+    }
+
+    pub fn offsets(&self) -> &[i8] {
+        &self.offsets[..self.len]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        *self = PitchLane::default();
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(from = "StoredEuclideanSequencerState")]
 pub struct EuclideanSequencerState {
     pub steps: usize,
     pub pulses: usize,
     pub phase: usize,
     pub pitch: u8,
+    pub pitch_lane: PitchLane,
+}
+
+/// An [EuclideanSequencerState] as it is read from a scenes file, before it is checked.
+#[derive(Deserialize)]
+struct StoredEuclideanSequencerState {
+    steps: usize,
+    pulses: usize,
+    phase: usize,
+    pitch: u8,
+    #[serde(default)]
+    pitch_lane: PitchLane,
+}
+
+impl From<StoredEuclideanSequencerState> for EuclideanSequencerState {
+    /// Values out of range are clamped, so that an edited file can not give a pattern
+    /// without steps.
+    fn from(stored: StoredEuclideanSequencerState) -> Self {
+        let steps = stored.steps.clamp(1, 16);
+        EuclideanSequencerState {
+            steps,
+            pulses: stored.pulses.min(steps),
+            phase: stored.phase % steps,
+            pitch: stored.pitch.min(127),
+            pitch_lane: stored.pitch_lane,
+        }
+    }
 }
 
 impl EuclideanSequencerState {
@@ -28,7 +120,56 @@ impl EuclideanSequencerState {
             pulses: 0,
             phase: 0,
             pitch: 60,
+            pitch_lane: PitchLane::default(),
+        }
+    }
+
+    /// Steps on which a pulse falls, spreading the pulses as evenly as possible and rotating
+    /// them by the phase.
+    pub fn beat_locations(&self) -> Vec<usize> {
+        if self.steps == 0 {
+            return vec![];
         }
+        // Bresenham line algorithm cus it looks easier
+        (0..self.pulses)
+            .map(|i| ((i * self.steps) / self.pulses + self.phase) % self.steps)
+            .collect()
+    }
+
+    /// The sequence of the pattern, with a note on every pulse and a rest on the other steps.
+    pub fn to_sequence(&self) -> Sequence {
+        let rest = Note::new(0, 0, NoteDuration::Sixteenth);
+        if self.pulses == 0 {
+            // Handle zero pulses case
+            return Sequence {
+                notes: vec![rest; self.steps],
+            };
+        }
+
+        let mut sequence = Sequence::empty();
+        let beat_locations = self.beat_locations();
+
+        let mut pulse = 0;
+        for i in 0..self.steps {
+            let note = if beat_locations.contains(&i) {
+                let pitch = self.pitch_for_pulse(pulse);
+                pulse += 1;
+                Note::new(pitch, 100, NoteDuration::Sixteenth)
+            } else {
+                rest
+            };
+            sequence.notes.push(note);
+        }
+        sequence
+    }
+
+    /// Pitch of the nth pulse, taken from the pitch lane if there is one.
+    pub fn pitch_for_pulse(&self, pulse: usize) -> u8 {
+        let offsets = self.pitch_lane.offsets();
+        if offsets.is_empty() {
+            return self.pitch;
+        }
+        (self.pitch as i16 + offsets[pulse % offsets.len()] as i16).clamp(0, 127) as u8
     }
 
     pub fn increase_steps(&mut self) {
@@ -65,10 +206,26 @@ impl EuclideanSequencerState {
     }
 
     pub fn decrease_phase(&mut self) {
-        self.phase = (self.phase - 1) % self.steps;
+        self.phase = (self.phase + self.steps - 1) % self.steps;
         info!("Phase: {}", self.phase);
     }
 
+    /// Linearly interpolate towards another state, with t between 0 and 1.
+    pub fn interpolate(&self, other: &Self, t: f32) -> Self {
+        let lerp = |a: f32, b: f32| (a + (b - a) * t).round();
+        EuclideanSequencerState {
+            steps: lerp(self.steps as f32, other.steps as f32) as usize,
+            pulses: lerp(self.pulses as f32, other.pulses as f32) as usize,
+            phase: lerp(self.phase as f32, other.phase as f32) as usize,
+            pitch: lerp(self.pitch as f32, other.pitch as f32) as u8,
+            pitch_lane: if t < 0.5 {
+                self.pitch_lane
+            } else {
+                other.pitch_lane
+            },
+        }
+    }
+
     pub fn change_pitch(&mut self, amount: i8) {
         self.pitch = (self.pitch as i8 + amount) as u8;
         self.pitch = self.pitch.clamp(20, 108);
@@ -88,5 +245,6 @@ impl PartialEq for EuclideanSequencerState {
             && self.pulses == other.pulses
             && self.phase == other.phase
             && self.pitch == other.pitch
+            && self.pitch_lane == other.pitch_lane
     }
 }
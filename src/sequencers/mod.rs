@@ -1,2 +1,4 @@
+pub mod arpeggiator;
 pub mod common;
 pub mod euclidean;
+pub mod step;
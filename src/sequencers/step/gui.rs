@@ -0,0 +1,205 @@
+use iced::{
+    alignment::{Horizontal, Vertical},
+    border::Radius,
+    mouse,
+    widget::{
+        canvas::{self, event, Canvas, Event, Frame, Path, Stroke, Text},
+        column, container,
+    },
+    Alignment::Center,
+    Element, Length, Point, Rectangle, Renderer, Size, Subscription,
+};
+
+use crate::{
+    gui::CustomTheme, note::Sequence, sequencers::step::state::MAX_STEPS, state::SequencerSlot,
+    SharedState,
+};
+
+const CIRCLE_RADIUS: f32 = 20.0;
+const CIRCLE_BORDER_RADIUS: f32 = CIRCLE_RADIUS + 2.0;
+const ACTIVE_CIRCLE_BORDER_RADIUS: f32 = CIRCLE_RADIUS + 4.0;
+const CIRCLE_SPACING: f32 = 60.0;
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    FromApp(Box<SharedState>),
+    /// Switch a step on or off, and select it.
+    ToggleStep(SequencerSlot, usize),
+    /// Select a step to edit its pitch, velocity and gate.
+    SelectStep(SequencerSlot, usize),
+}
+
+pub struct Gui {
+    state: SharedState,
+    slot: SequencerSlot,
+    theme: CustomTheme,
+}
+
+impl Gui {
+    pub fn new(slot: SequencerSlot) -> Self {
+        Self {
+            state: SharedState::new(120.0),
+            slot,
+            theme: CustomTheme::default(),
+        }
+    }
+
+    pub fn subscription(&self) -> Subscription<Message> {
+        Subscription::none()
+    }
+
+    pub fn update(&mut self, message: Message) {
+        if let Message::FromApp(new_state) = message {
+            self.state = *new_state;
+        }
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let canvas = Canvas::new(self).width(Length::Fill).height(Length::Fill);
+        let content = column![canvas].align_x(Center);
+        container(content)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .align_x(Center)
+            .align_y(Center)
+            .into()
+    }
+
+    /// Center of the circle of each step, row by row.
+    fn step_centers(size: Size) -> impl Iterator<Item = Point> {
+        let start_x = size.width / 2.0 - 1.5 * CIRCLE_SPACING - 2. * CIRCLE_RADIUS;
+        let start_y = size.height / 2.0 - 1.5 * CIRCLE_SPACING - 2. * CIRCLE_RADIUS;
+        (0..MAX_STEPS).map(move |step| {
+            Point::new(
+                start_x + CIRCLE_SPACING * ((step % 4) as f32 + 0.5),
+                start_y + CIRCLE_SPACING * ((step / 4) as f32 + 0.5),
+            )
+        })
+    }
+}
+
+impl canvas::Program<Message> for Gui {
+    type State = ();
+
+    fn update(
+        &self,
+        _state: &mut (),
+        event: Event,
+        bounds: Rectangle,
+        cursor: mouse::Cursor,
+    ) -> (event::Status, Option<Message>) {
+        let Event::Mouse(mouse::Event::ButtonPressed(button)) = event else {
+            return (event::Status::Ignored, None);
+        };
+        let Some(position) = cursor.position_in(bounds) else {
+            return (event::Status::Ignored, None);
+        };
+        let Some(step) = Self::step_centers(bounds.size())
+            .position(|center| center.distance(position) <= CIRCLE_BORDER_RADIUS)
+        else {
+            return (event::Status::Ignored, None);
+        };
+
+        // Left click toggles a step, right click only selects it
+        let message = match button {
+            mouse::Button::Left => Message::ToggleStep(self.slot.clone(), step),
+            mouse::Button::Right => Message::SelectStep(self.slot.clone(), step),
+            _ => return (event::Status::Ignored, None),
+        };
+        (event::Status::Captured, Some(message))
+    }
+
+    fn draw(
+        &self,
+        _state: &(),
+        renderer: &Renderer,
+        _theme: &iced::Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<canvas::Geometry> {
+        let mut frame = Frame::new(renderer, bounds.size());
+        let step_state = self.state.step_state(&self.slot);
+        let is_active = self.state.active_sequencer == self.slot;
+        let current_step = self.state.current_note_index % step_state.length;
+
+        for (index, circle_center) in Self::step_centers(bounds.size()).enumerate() {
+            let circle = Path::circle(circle_center, CIRCLE_RADIUS);
+
+            // circle outline
+            let bg_circle = if is_active {
+                Path::circle(circle_center, ACTIVE_CIRCLE_BORDER_RADIUS)
+            } else {
+                Path::circle(circle_center, CIRCLE_BORDER_RADIUS)
+            };
+            frame.fill(&bg_circle, self.theme.primary_color_muted);
+
+            // steps that are on and current playing note
+            let color = if index == current_step {
+                self.theme.primary_color
+            } else if step_state.steps[index].on {
+                self.theme.accent_color
+            } else if index >= step_state.length {
+                self.theme.accent_color_muted
+            } else {
+                self.theme.surface_color
+            };
+            frame.fill(&circle, color);
+
+            // selected step of the active sequencer
+            if is_active && index == self.state.step_cursor {
+                frame.stroke(
+                    &Path::circle(circle_center, ACTIVE_CIRCLE_BORDER_RADIUS),
+                    Stroke::default()
+                        .with_color(self.theme.secondary_color)
+                        .with_width(3.0),
+                );
+            }
+        }
+
+        // show step info - rounded rectangle
+        const BOX_PADDING_FROM_CIRCLES: f32 = 15.0;
+        const BOX_HEIGHT: f32 = 40.0;
+        const BOX_CORNER_RADIUS: f32 = 8.0;
+
+        let grid_width = 4. * CIRCLE_SPACING;
+        let center = frame.center();
+        let start_x = center.x - 1.5 * CIRCLE_SPACING - 2. * CIRCLE_RADIUS;
+        let start_y = center.y - 1.5 * CIRCLE_SPACING - 2. * CIRCLE_RADIUS;
+
+        let box_top_left = Point::new(start_x, start_y + grid_width + BOX_PADDING_FROM_CIRCLES);
+        let box_size = Size::new(grid_width, BOX_HEIGHT);
+
+        let rounded_rect_path =
+            Path::rounded_rectangle(box_top_left, box_size, Radius::new(BOX_CORNER_RADIUS));
+        frame.fill(&rounded_rect_path, self.theme.primary_color_muted);
+
+        let box_center = Point::new(
+            box_top_left.x + box_size.width / 2.0,
+            box_top_left.y + box_size.height / 2.0,
+        );
+
+        // show step info - text
+        let note_info = if is_active {
+            let step = step_state.steps[self.state.step_cursor];
+            format!(
+                "{}: {}",
+                self.state.step_cursor + 1,
+                Sequence::midi_to_note_name(step.pitch)
+            )
+        } else {
+            "Step".to_string()
+        };
+        let text = Text {
+            content: note_info,
+            position: box_center,
+            color: self.theme.primary_text_color,
+            size: iced::Pixels(20.0),
+            horizontal_alignment: Horizontal::Center,
+            vertical_alignment: Vertical::Center,
+            ..Text::default()
+        };
+        frame.fill_text(text);
+
+        vec![frame.into_geometry()]
+    }
+}
@@ -0,0 +1,80 @@
+pub mod gui;
+pub mod state;
+
+use crate::note::Sequence;
+use crate::sequencers::common::{Sequencer, SequencerType};
+use crate::sequencers::step::state::StepSequencerState;
+
+use crate::state::{SequencerSlot, SharedState};
+use anyhow::Result;
+use log::debug;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::sync::RwLock;
+
+/// Plays a step pattern that is edited by hand, while its slot is set to the step type.
+pub struct StepSequencer {
+    sequencer_slot: SequencerSlot,
+    tx_sequence: mpsc::Sender<(Option<Sequence>, Option<Sequence>)>,
+    cached_state: StepSequencerState,
+    shared_state: Arc<RwLock<SharedState>>,
+}
+
+impl StepSequencer {
+    pub fn new(
+        sequencer_slot: SequencerSlot,
+        tx_sequence: mpsc::Sender<(Option<Sequence>, Option<Sequence>)>,
+        shared_state: Arc<RwLock<SharedState>>,
+    ) -> Self {
+        StepSequencer {
+            sequencer_slot,
+            tx_sequence,
+            cached_state: StepSequencerState::new(),
+            shared_state,
+        }
+    }
+}
+
+impl Sequencer for StepSequencer {
+    async fn generate_sequence(&self) -> Sequence {
+        self.cached_state.to_sequence()
+    }
+
+    async fn run(&mut self) -> Result<()> {
+        let mut previous_state = None;
+
+        loop {
+            let (state, sequencer_type) = {
+                let r_state = self.shared_state.read().await;
+                (
+                    *r_state.step_state(&self.sequencer_slot),
+                    r_state.sequencer_type(&self.sequencer_slot),
+                )
+            };
+
+            // The pattern is sent again when the slot switches back to the step type
+            let state = (sequencer_type == SequencerType::Step).then_some(state);
+            if state != previous_state {
+                if let Some(state) = state {
+                    debug!(
+                        "Step sequencer {:?} new state: {:?}",
+                        self.sequencer_slot, state
+                    );
+                    self.cached_state = state;
+                    let sequence = self.generate_sequence().await;
+                    match self.sequencer_slot {
+                        SequencerSlot::Left => {
+                            self.tx_sequence.send((Some(sequence), None)).await?
+                        }
+                        SequencerSlot::Right => {
+                            self.tx_sequence.send((None, Some(sequence))).await?
+                        }
+                    };
+                }
+                previous_state = state;
+            }
+
+            tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::note::{Note, Sequence, TICKS_PER_STEP};
+
+/// Number of steps of a step pattern, one for each cell of the 4x4 grid.
+pub const MAX_STEPS: usize = 16;
+
+/// Shortest gate, as a fraction of a step.
+pub const MIN_GATE: f32 = 0.1;
+
+/// A step of a step pattern, played as a note when it is on.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Step {
+    pub on: bool,
+    pub pitch: u8,
+    pub velocity: u8,
+    /// Length of the note as a fraction of a step, from MIN_GATE to 1.
+    pub gate: f32,
+}
+
+impl Default for Step {
+    fn default() -> Self {
+        Step {
+            on: false,
+            pitch: 60,
+            velocity: 100,
+            gate: 0.5,
+        }
+    }
+}
+
+impl Step {
+    pub fn change_pitch(&mut self, amount: i8) {
+        self.pitch = (self.pitch as i16 + amount as i16).clamp(20, 108) as u8;
+        info!("Step pitch: {}", self.pitch);
+    }
+
+    pub fn change_velocity(&mut self, amount: i8) {
+        self.velocity = (self.velocity as i16 + amount as i16).clamp(1, 127) as u8;
+        info!("Step velocity: {}", self.velocity);
+    }
+
+    pub fn change_gate(&mut self, amount: f32) {
+        self.gate = (self.gate + amount).clamp(MIN_GATE, 1.0);
+        info!("Step gate: {:.1}", self.gate);
+    }
+
+    fn note(&self) -> Note {
+        let duration = ((self.gate * TICKS_PER_STEP as f32).round() as u32).max(1);
+        if self.on {
+            Note {
+                pitch: self.pitch,
+                velocity: self.velocity,
+                duration,
+            }
+        } else {
+            Note::rest(TICKS_PER_STEP)
+        }
+    }
+}
+
+/// A pattern of steps that are switched on and off by hand, each with its own pitch,
+/// velocity and gate.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredStepSequencerState")]
+pub struct StepSequencerState {
+    pub steps: [Step; MAX_STEPS],
+    /// Number of steps that are played, from 1 to MAX_STEPS.
+    pub length: usize,
+}
+
+/// A [StepSequencerState] as it is read from a scenes file, before its length is checked.
+#[derive(Deserialize)]
+struct StoredStepSequencerState {
+    steps: [Step; MAX_STEPS],
+    length: usize,
+}
+
+impl From<StoredStepSequencerState> for StepSequencerState {
+    fn from(stored: StoredStepSequencerState) -> Self {
+        StepSequencerState {
+            steps: stored.steps,
+            length: stored.length.clamp(1, MAX_STEPS),
+        }
+    }
+}
+
+impl StepSequencerState {
+    pub fn new() -> Self {
+        StepSequencerState {
+            steps: [Step::default(); MAX_STEPS],
+            length: MAX_STEPS,
+        }
+    }
+
+    pub fn toggle(&mut self, index: usize) {
+        if let Some(step) = self.steps.get_mut(index) {
+            step.on = !step.on;
+            info!("Step {}: {}", index + 1, step.on);
+        }
+    }
+
+    pub fn set_length(&mut self, length: usize) {
+        self.length = length.clamp(1, MAX_STEPS);
+        info!("Step pattern length: {}", self.length);
+    }
+
+    /// A pattern with a step for each of the first MAX_STEPS notes of a sequence, so that a
+    /// generated or imported sequence can be edited by hand.
+    pub fn from_sequence(sequence: &Sequence) -> Self {
+        let mut state = StepSequencerState::new();
+        for (step, note) in state.steps.iter_mut().zip(&sequence.notes) {
+            if note.pitch != 0 && note.velocity > 0 {
+                *step = Step {
+                    on: true,
+                    pitch: note.pitch,
+                    velocity: note.velocity,
+                    gate: (note.duration as f32 / TICKS_PER_STEP as f32).clamp(MIN_GATE, 1.0),
+                };
+            }
+        }
+        state.length = sequence.notes.len().clamp(1, MAX_STEPS);
+        state
+    }
+
+    pub fn to_sequence(&self) -> Sequence {
+        Sequence {
+            notes: self.steps[..self.length].iter().map(Step::note).collect(),
+        }
+    }
+
+    /// Steps can not be interpolated, so a morph switches to the other pattern halfway.
+    pub fn interpolate(&self, other: &Self, t: f32) -> Self {
+        if t < 0.5 {
+            *self
+        } else {
+            *other
+        }
+    }
+}
+
+impl Default for StepSequencerState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sequencers::euclidean::state::{EuclideanSequencerState, PitchLane};
+
+    #[test]
+    fn test_to_sequence() {
+        let mut state = StepSequencerState::new();
+        state.set_length(4);
+        state.toggle(1);
+        state.steps[1].change_pitch(3);
+        state.steps[1].change_velocity(-40);
+        state.steps[1].change_gate(0.5);
+        state.toggle(6);
+
+        let sequence = state.to_sequence();
+        assert_eq!(sequence.notes.len(), 4);
+        assert_eq!(sequence.notes[0], Note::rest(TICKS_PER_STEP));
+        assert_eq!(
+            sequence.notes[1],
+            Note {
+                pitch: 63,
+                velocity: 60,
+                duration: TICKS_PER_STEP,
+            }
+        );
+
+        // Gates are never shorter than MIN_GATE
+        state.steps[1].change_gate(-2.0);
+        assert_eq!(
+            state.to_sequence().notes[1].duration,
+            (MIN_GATE * TICKS_PER_STEP as f32).round() as u32
+        );
+    }
+
+    #[test]
+    fn test_freeze_euclidean_pattern() {
+        let mut euclidean = EuclideanSequencerState::new();
+        euclidean.steps = 8;
+        euclidean.pulses = 3;
+        euclidean.pitch_lane = PitchLane::from_pitches(60, &[60, 63, 67]);
+
+        let sequence = euclidean.to_sequence();
+        let state = StepSequencerState::from_sequence(&sequence);
+        assert_eq!(state.length, 8);
+        let on = (0..MAX_STEPS)
+            .filter(|i| state.steps[*i].on)
+            .collect::<Vec<_>>();
+        assert_eq!(on, euclidean.beat_locations());
+        assert_eq!(state.steps[on[1]].pitch, 63);
+        assert_eq!(state.to_sequence(), sequence);
+    }
+
+    #[test]
+    fn test_length_is_clamped_on_load() {
+        let stored = toml::to_string(&StepSequencerState::new()).unwrap();
+        for (length, clamped) in [(0, 1), (40, MAX_STEPS)] {
+            let stored = stored.replace("length = 16", &format!("length = {length}"));
+            let state: StepSequencerState = toml::from_str(&stored).unwrap();
+            assert_eq!(state.length, clamped);
+            assert_eq!(state.to_sequence().notes.len(), clamped);
+        }
+    }
+}
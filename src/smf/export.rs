@@ -0,0 +1,195 @@
+use anyhow::{Context, Result};
+use midly::num::{u15, u24, u28, u4, u7};
+use midly::{Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind};
+use std::path::Path;
+
+use crate::note::Note;
+use crate::smf::{TICKS_PER_QUARTER, TICKS_PER_STEP};
+use crate::transport::TimeSignature;
+
+/// Settings for exporting to a Standard MIDI File.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExportOptions {
+    pub bpm: f32,
+    pub time_signature: TimeSignature,
+    /// MIDI channels (0-15) of the left and right slot tracks.
+    pub channels: (u8, u8),
+}
+
+/// Convert steps of (left, right) notes to a Type 1 Standard MIDI File with a tempo track,
+/// followed by one track per sequencer slot.
+pub fn export_steps(
+    steps: &[(Option<Note>, Option<Note>)],
+    options: &ExportOptions,
+) -> Smf<'static> {
+    let mut smf = Smf::new(Header::new(
+        Format::Parallel,
+        Timing::Metrical(u15::new(TICKS_PER_QUARTER)),
+    ));
+
+    let microseconds_per_quarter = (60_000_000.0 / options.bpm).round() as u32;
+    smf.tracks.push(to_track(vec![
+        (0, TrackEventKind::Meta(MetaMessage::TrackName(b"Tempo"))),
+        (
+            0,
+            // The denominator is a power of two, with a MIDI clock of 24 ticks per quarter
+            TrackEventKind::Meta(MetaMessage::TimeSignature(
+                options.time_signature.beats,
+                options.time_signature.unit.trailing_zeros() as u8,
+                24,
+                8,
+            )),
+        ),
+        (
+            0,
+            TrackEventKind::Meta(MetaMessage::Tempo(u24::new(microseconds_per_quarter))),
+        ),
+    ]));
+
+    let left = steps.iter().map(|(left, _)| *left);
+    let right = steps.iter().map(|(_, right)| *right);
+    smf.tracks
+        .push(slot_track(b"Left", left, options.channels.0));
+    smf.tracks
+        .push(slot_track(b"Right", right, options.channels.1));
+
+    smf
+}
+
+pub fn write_file(
+    steps: &[(Option<Note>, Option<Note>)],
+    options: &ExportOptions,
+    path: &Path,
+) -> Result<()> {
+    export_steps(steps, options)
+        .save(path)
+        .with_context(|| format!("Failed to write MIDI file to {}", path.display()))
+}
+
+fn slot_track(
+    name: &'static [u8],
+    notes: impl Iterator<Item = Option<Note>>,
+    channel: u8,
+) -> Vec<TrackEvent<'static>> {
+    let channel = u4::new(channel & 0x0F);
+    let mut events = vec![(0, TrackEventKind::Meta(MetaMessage::TrackName(name)))];
+
+    for (step, note) in notes.enumerate() {
+        // Notes with pitch 0 are rests
+        let Some(note) = note.filter(|note| note.pitch != 0) else {
+            continue;
+        };
+
+        let start = step as u32 * TICKS_PER_STEP;
+        let length = note.duration;
+        let key = u7::new(note.pitch & 0x7F);
+
+        events.push((
+            start,
+            TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOn {
+                    key,
+                    vel: u7::new(note.velocity & 0x7F),
+                },
+            },
+        ));
+        events.push((
+            start + length.max(1),
+            TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOff {
+                    key,
+                    vel: u7::new(0),
+                },
+            },
+        ));
+    }
+
+    to_track(events)
+}
+
+/// Sort events by absolute tick, with note offs before note ons on the same tick, and
+/// convert them to delta times.
+fn to_track(mut events: Vec<(u32, TrackEventKind<'static>)>) -> Vec<TrackEvent<'static>> {
+    events.sort_by_key(|(tick, kind)| {
+        let is_note_on = matches!(
+            kind,
+            TrackEventKind::Midi {
+                message: MidiMessage::NoteOn { .. },
+                ..
+            }
+        );
+        (*tick, is_note_on)
+    });
+
+    let mut last_tick = 0;
+    let mut track = events
+        .into_iter()
+        .map(|(tick, kind)| {
+            let delta = u28::new(tick - last_tick);
+            last_tick = tick;
+            TrackEvent { delta, kind }
+        })
+        .collect::<Vec<_>>();
+    track.push(TrackEvent {
+        delta: u28::new(0),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+
+    track
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::NoteDuration;
+
+    #[test]
+    fn test_export_steps() {
+        let bpm = 120.;
+        let note = |pitch| Some(Note::new(pitch, 100, NoteDuration::Sixteenth));
+        let steps = vec![(note(60), None), (note(0), note(64)), (note(62), note(67))];
+
+        let mut bytes = Vec::new();
+        export_steps(
+            &steps,
+            &ExportOptions {
+                bpm,
+                time_signature: TimeSignature::new(7, 8),
+                channels: (0, 9),
+            },
+        )
+        .write_std(&mut bytes)
+        .unwrap();
+        let smf = Smf::parse(&bytes).unwrap();
+
+        assert_eq!(smf.header.format, Format::Parallel);
+        assert_eq!(smf.tracks.len(), 3);
+        assert!(smf.tracks[0]
+            .iter()
+            .any(|e| e.kind == TrackEventKind::Meta(MetaMessage::Tempo(u24::new(500_000)))));
+        assert!(smf.tracks[0]
+            .iter()
+            .any(|e| e.kind == TrackEventKind::Meta(MetaMessage::TimeSignature(7, 3, 24, 8))));
+
+        let note_ons = |track: &Vec<TrackEvent>| {
+            let mut tick = 0;
+            track
+                .iter()
+                .filter_map(|e| {
+                    tick += e.delta.as_int();
+                    match e.kind {
+                        TrackEventKind::Midi {
+                            channel,
+                            message: MidiMessage::NoteOn { key, .. },
+                        } => Some((tick, channel.as_int(), key.as_int())),
+                        _ => None,
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(note_ons(&smf.tracks[1]), vec![(0, 0, 60), (240, 0, 62)]);
+        assert_eq!(note_ons(&smf.tracks[2]), vec![(120, 9, 64), (240, 9, 67)]);
+    }
+}
@@ -0,0 +1,277 @@
+use anyhow::{anyhow, Context, Result};
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::note::{Note, NoteDuration, Sequence};
+use crate::scene::STEPS_PER_BAR;
+use crate::sequencers::euclidean::state::{EuclideanSequencerState, PitchLane};
+
+/// Imported tracks are cut off after this many bars.
+pub const MAX_IMPORT_BARS: usize = 16;
+
+/// Tempo used to quantize SMPTE timed files without a tempo event.
+const DEFAULT_BPM: f32 = 120.0;
+
+/// How an imported track is used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Play the track as a fixed step sequence in the slot.
+    #[default]
+    Sequence,
+    /// Fit the rhythm of the track onto the Euclidean steps, pulses and phase.
+    Rhythm,
+    /// Use the pitches of the track as the pitch lane of the Euclidean sequencer.
+    Pitches,
+}
+
+impl ImportMode {
+    pub const ALL: [ImportMode; 3] = [
+        ImportMode::Sequence,
+        ImportMode::Rhythm,
+        ImportMode::Pitches,
+    ];
+}
+
+impl fmt::Display for ImportMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportMode::Sequence => write!(f, "Step sequence"),
+            ImportMode::Rhythm => write!(f, "Euclidean rhythm"),
+            ImportMode::Pitches => write!(f, "Pitches"),
+        }
+    }
+}
+
+/// A note of an imported track, quantized to the step grid.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImportedNote {
+    pub pitch: u8,
+    pub velocity: u8,
+}
+
+/// A track of a Standard MIDI File, quantized to one note per step.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportedTrack {
+    pub index: usize,
+    pub name: String,
+    pub steps: Vec<Option<ImportedNote>>,
+}
+
+impl fmt::Display for ImportedTrack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let notes = self.steps.iter().flatten().count();
+        write!(f, "{}: {} ({} notes)", self.index + 1, self.name, notes)
+    }
+}
+
+/// Read all tracks with notes from a Standard MIDI File, in bars of `steps_per_bar`.
+pub fn read_tracks(path: &Path, steps_per_bar: usize) -> Result<Vec<ImportedTrack>> {
+    let bytes =
+        fs::read(path).with_context(|| format!("Failed to read MIDI file {}", path.display()))?;
+    let smf = Smf::parse(&bytes)
+        .map_err(|e| anyhow!("Failed to parse MIDI file {}: {}", path.display(), e))?;
+    Ok(parse_tracks(&smf, steps_per_bar))
+}
+
+pub fn parse_tracks(smf: &Smf, steps_per_bar: usize) -> Vec<ImportedTrack> {
+    let ticks_per_step = ticks_per_step(smf);
+    let steps_per_bar = steps_per_bar.max(1);
+    let max_steps = MAX_IMPORT_BARS * steps_per_bar;
+
+    smf.tracks
+        .iter()
+        .enumerate()
+        .filter_map(|(index, track)| {
+            let mut name = format!("Track {}", index + 1);
+            let mut steps: Vec<Option<ImportedNote>> = Vec::new();
+            let mut tick = 0u64;
+
+            for event in track {
+                tick += event.delta.as_int() as u64;
+                match event.kind {
+                    TrackEventKind::Meta(MetaMessage::TrackName(bytes)) => {
+                        name = String::from_utf8_lossy(bytes).trim().to_string();
+                    }
+                    TrackEventKind::Midi {
+                        message: MidiMessage::NoteOn { key, vel },
+                        ..
+                    } if vel.as_int() > 0 => {
+                        let step = (tick as f64 / ticks_per_step).round() as usize;
+                        if step >= max_steps {
+                            continue;
+                        }
+                        if steps.len() <= step {
+                            steps.resize(step + 1, None);
+                        }
+                        // Slots are monophonic, so keep the loudest note on a step
+                        let note = ImportedNote {
+                            pitch: key.as_int(),
+                            velocity: vel.as_int(),
+                        };
+                        if steps[step].is_none_or(|other| other.velocity < note.velocity) {
+                            steps[step] = Some(note);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            if steps.is_empty() {
+                return None;
+            }
+            // Round up to whole bars
+            steps.resize(steps.len().div_ceil(steps_per_bar) * steps_per_bar, None);
+
+            Some(ImportedTrack { index, name, steps })
+        })
+        .collect()
+}
+
+/// Number of file ticks in one sixteenth step. For SMPTE timed files this depends on the
+/// tempo, taken from the first tempo event in the file.
+fn ticks_per_step(smf: &Smf) -> f64 {
+    match smf.header.timing {
+        Timing::Metrical(ticks_per_quarter) => ticks_per_quarter.as_int() as f64 / 4.0,
+        Timing::Timecode(fps, subframes) => {
+            let ticks_per_second = fps.as_f32() as f64 * subframes as f64;
+            let seconds_per_quarter = smf
+                .tracks
+                .iter()
+                .flatten()
+                .find_map(|event| match event.kind {
+                    TrackEventKind::Meta(MetaMessage::Tempo(tempo)) => {
+                        Some(tempo.as_int() as f64 / 1_000_000.0)
+                    }
+                    _ => None,
+                })
+                .unwrap_or(60.0 / DEFAULT_BPM as f64);
+            ticks_per_second * seconds_per_quarter / 4.0
+        }
+    }
+}
+
+impl ImportedTrack {
+    /// Convert to a Sequence with one note per sixteenth step.
+    pub fn to_sequence(&self) -> Sequence {
+        let duration = NoteDuration::Sixteenth.ticks();
+        let notes = self
+            .steps
+            .iter()
+            .map(|step| match step {
+                Some(note) => Note {
+                    pitch: note.pitch,
+                    velocity: note.velocity,
+                    duration,
+                },
+                None => Note::rest(duration),
+            })
+            .collect();
+        Sequence { notes }
+    }
+
+    /// Fit the rhythm of the first bar onto an Euclidean pattern, picking the phase that
+    /// matches the most onsets.
+    pub fn to_euclidean(&self, state: EuclideanSequencerState) -> EuclideanSequencerState {
+        let steps = self.steps.len().min(STEPS_PER_BAR);
+        let onsets = (0..steps)
+            .filter(|i| self.steps[*i].is_some())
+            .collect::<Vec<_>>();
+
+        let mut fitted = EuclideanSequencerState {
+            steps,
+            pulses: onsets.len(),
+            phase: 0,
+            ..state
+        };
+        fitted.phase = (0..steps)
+            .max_by_key(|phase| {
+                let candidate = EuclideanSequencerState {
+                    phase: *phase,
+                    ..fitted
+                };
+                let matches = candidate
+                    .beat_locations()
+                    .iter()
+                    .filter(|step| onsets.contains(step))
+                    .count();
+                // Prefer the smallest phase on equal matches
+                (matches, steps - phase)
+            })
+            .unwrap_or(0);
+
+        fitted
+    }
+
+    /// Use the pitches of the track as a pitch lane, relative to its first pitch.
+    pub fn to_pitch_lane(&self, state: EuclideanSequencerState) -> EuclideanSequencerState {
+        let pitches = self
+            .steps
+            .iter()
+            .flatten()
+            .map(|note| note.pitch)
+            .collect::<Vec<_>>();
+        let Some(base) = pitches.first() else {
+            return state;
+        };
+
+        EuclideanSequencerState {
+            pitch: *base,
+            pitch_lane: PitchLane::from_pitches(*base, &pitches),
+            ..state
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smf::export::{export_steps, ExportOptions};
+    use crate::transport::TimeSignature;
+
+    #[test]
+    fn test_import_exported_steps() {
+        let bpm = 120.;
+        let note = |pitch| Some(Note::new(pitch, 100, NoteDuration::Sixteenth));
+        let rest = || Some(Note::new(0, 0, NoteDuration::Sixteenth));
+
+        // Three pulses in eight steps with a melody, rotated by one step
+        let mut steps = vec![(rest(), None); 8];
+        steps[1].0 = note(60);
+        steps[3].0 = note(63);
+        steps[6].0 = note(67);
+
+        let smf = export_steps(
+            &steps,
+            &ExportOptions {
+                bpm,
+                time_signature: TimeSignature::default(),
+                channels: (0, 0),
+            },
+        );
+        let tracks = parse_tracks(&smf, STEPS_PER_BAR);
+        assert_eq!(tracks.len(), 1);
+        // Tracks are rounded up to bars of the time signature
+        assert_eq!(parse_tracks(&smf, 12)[0].steps.len(), 12);
+
+        let track = &tracks[0];
+        assert_eq!(track.name, "Left");
+        assert_eq!(track.steps.len(), STEPS_PER_BAR);
+        assert_eq!(track.to_sequence().notes[3].pitch, 63);
+
+        let mut state = EuclideanSequencerState::new();
+        state.steps = 8;
+        let rhythm = ImportedTrack {
+            steps: track.steps[..8].to_vec(),
+            ..track.clone()
+        }
+        .to_euclidean(state);
+        assert_eq!((rhythm.steps, rhythm.pulses, rhythm.phase), (8, 3, 1));
+        assert_eq!(rhythm.beat_locations(), vec![1, 3, 6]);
+
+        let melody = track.to_pitch_lane(state);
+        assert_eq!(melody.pitch, 60);
+        assert_eq!(melody.pitch_lane.offsets(), &[0, 3, 7]);
+    }
+}
@@ -0,0 +1,15 @@
+pub mod export;
+pub mod import;
+
+use crate::note::TICKS_PER_BEAT;
+pub use crate::note::TICKS_PER_STEP;
+
+/// Resolution of exported and imported Standard MIDI Files, the same as the musical time of
+/// notes, so that durations are exported unchanged.
+pub const TICKS_PER_QUARTER: u16 = TICKS_PER_BEAT as u16;
+
+/// File the current sequence is exported to, relative to the working directory.
+pub const SEQUENCE_EXPORT_FILE: &str = "sequence.mid";
+
+/// File the last bars of live output are exported to, relative to the working directory.
+pub const LIVE_EXPORT_FILE: &str = "live.mid";
@@ -1,8 +1,28 @@
 use core::fmt;
-use log::info;
+use log::{info, warn};
+use std::collections::VecDeque;
+use std::time::Instant;
 
-use crate::mixer::state::MixerState;
+use crate::arrangement::{Arrangement, ArrangementEvent, ArrangementPosition};
+use crate::history::{Edit, History, PerformanceState};
+use crate::link::LinkState;
+use crate::metronome::{count_in_position, Click, MetronomeConfig};
+use crate::midi::learn::{Control, ControlAction, MidiMap, Parameter};
+use crate::midi::recording::{Playhead, Recording};
+use crate::mixer::state::{mix_step, MixerState, SlotMix};
+use crate::note::{MixedSequence, Note, Sequence};
+use crate::scene::{Morph, SceneBank};
+use crate::sequencers::arpeggiator::state::{ArpeggiatorState, ChordInput, HeldNotes};
+use crate::sequencers::common::SequencerType;
 use crate::sequencers::euclidean::state::EuclideanSequencerState;
+use crate::sequencers::step::state::{Step, StepSequencerState, MAX_STEPS};
+use crate::smf::export::ExportOptions;
+use crate::smf::import::{ImportMode, ImportedTrack};
+use crate::tempo::{clamp_bpm, Nudge, TapTempo, TempoRamp};
+use crate::transport::{BarAction, Position, TimeSignature};
+
+/// Number of bars of played output that are kept for exporting.
+pub const MAX_RECORDED_BARS: usize = 64;
 
 #[derive(Clone, Debug, Default, PartialEq)]
 pub enum SequencerSlot {
@@ -15,14 +35,57 @@ pub enum SequencerSlot {
 pub struct SharedState {
     pub playing: bool,
     pub bpm: f32,
+    /// Temporary change of the played tempo, not of `bpm`.
+    pub nudge: Nudge,
+    pub tap_tempo: TapTempo,
+    pub tempo_ramp: Option<TempoRamp>,
+    pub ramp_bars: usize,
+    pub time_signature: TimeSignature,
+    /// Steps played since the transport started, counting bars and beats.
+    pub transport_steps: usize,
+    pub bar_action: BarAction,
+    /// Incremented to make the sequencers regenerate their sequences.
+    pub generation: u64,
+    pub metronome: MetronomeConfig,
+    /// Steps left to count in before playing.
+    pub count_in: usize,
     pub midi_channel: u8,
     pub active_sequencer: SequencerSlot,
     pub current_note_index: usize,
     pub left_state: EuclideanSequencerState,
     pub right_state: EuclideanSequencerState,
+    pub left_sequencer_type: SequencerType,
+    pub right_sequencer_type: SequencerType,
+    pub left_step_state: StepSequencerState,
+    pub right_step_state: StepSequencerState,
+    /// Step of the active step sequencer that is edited with the keyboard.
+    pub step_cursor: usize,
+    /// The generating type of each slot, that unfreezing returns to.
+    pub left_generator_type: SequencerType,
+    pub right_generator_type: SequencerType,
+    pub left_arpeggiator: ArpeggiatorState,
+    pub right_arpeggiator: ArpeggiatorState,
+    pub held_notes: HeldNotes,
+    /// A fixed sequence, e.g. imported from a MIDI file, is played instead of the generated one.
+    pub left_fixed_sequence: Option<Sequence>,
+    pub right_fixed_sequence: Option<Sequence>,
     pub mixer_state: MixerState,
+    pub left_mix: SlotMix,
+    pub right_mix: SlotMix,
     pub clock_ticks: u32,
     pub quarter_notes: u32,
+    pub history: History,
+    pub scenes: SceneBank,
+    pub morph: Option<Morph>,
+    pub morph_bars: usize,
+    pub arrangement: Arrangement,
+    pub arrangement_position: Option<ArrangementPosition>,
+    pub mixed_sequence: MixedSequence,
+    pub played_steps: VecDeque<(Option<Note>, Option<Note>)>,
+    pub step_started: Option<Instant>,
+    pub recording: Recording,
+    pub midi_map: MidiMap,
+    pub link: LinkState,
 }
 
 impl SharedState {
@@ -30,23 +93,405 @@ impl SharedState {
         SharedState {
             playing: false,
             bpm,
+            nudge: Nudge::None,
+            tap_tempo: TapTempo::default(),
+            tempo_ramp: None,
+            ramp_bars: 4,
+            time_signature: TimeSignature::default(),
+            transport_steps: 0,
+            bar_action: BarAction::None,
+            generation: 0,
+            metronome: MetronomeConfig::default(),
+            count_in: 0,
             midi_channel: 0,
             active_sequencer: SequencerSlot::Left,
             current_note_index: 0,
             left_state: EuclideanSequencerState::new(),
             right_state: EuclideanSequencerState::new(),
+            left_sequencer_type: SequencerType::Euclidean,
+            right_sequencer_type: SequencerType::Euclidean,
+            left_step_state: StepSequencerState::new(),
+            right_step_state: StepSequencerState::new(),
+            step_cursor: 0,
+            left_generator_type: SequencerType::Euclidean,
+            right_generator_type: SequencerType::Euclidean,
+            left_arpeggiator: ArpeggiatorState::new(),
+            right_arpeggiator: ArpeggiatorState::new(),
+            held_notes: HeldNotes::default(),
+            left_fixed_sequence: None,
+            right_fixed_sequence: None,
             mixer_state: MixerState::new(),
+            left_mix: SlotMix::default(),
+            right_mix: SlotMix::default(),
             clock_ticks: 0,
             quarter_notes: 0,
+            history: History::default(),
+            scenes: SceneBank::default(),
+            morph: None,
+            morph_bars: 4,
+            arrangement: Arrangement::default(),
+            arrangement_position: None,
+            mixed_sequence: MixedSequence::default(),
+            played_steps: VecDeque::new(),
+            step_started: None,
+            recording: Recording::default(),
+            midi_map: MidiMap::default(),
+            link: LinkState::default(),
+        }
+    }
+
+    pub fn performance_state(&self) -> PerformanceState {
+        PerformanceState {
+            left_state: self.left_state,
+            right_state: self.right_state,
+            mixer_state: self.mixer_state,
+            bpm: self.bpm,
+            left_step_state: self.left_step_state,
+            right_step_state: self.right_step_state,
+            left_sequencer_type: self.left_sequencer_type,
+            right_sequencer_type: self.right_sequencer_type,
+        }
+    }
+
+    fn restore_performance_state(&mut self, state: PerformanceState) {
+        self.left_state = state.left_state;
+        self.right_state = state.right_state;
+        self.mixer_state = state.mixer_state;
+        self.bpm = state.bpm;
+        self.left_step_state = state.left_step_state;
+        self.right_step_state = state.right_step_state;
+        if state.left_sequencer_type != self.left_sequencer_type {
+            self.set_sequencer_type(&SequencerSlot::Left, state.left_sequencer_type);
+        }
+        if state.right_sequencer_type != self.right_sequencer_type {
+            self.set_sequencer_type(&SequencerSlot::Right, state.right_sequencer_type);
+        }
+    }
+
+    /// Apply an edit to the state and record it in the undo history if it changed anything.
+    /// Manual edits cancel a running morph, and tempo edits a running tempo ramp.
+    pub fn apply_edit(&mut self, edit: Edit, f: impl FnOnce(&mut Self)) {
+        if edit != Edit::Scene {
+            self.morph = None;
+        }
+        if edit == Edit::Bpm {
+            self.tempo_ramp = None;
+        }
+        let before = self.performance_state();
+        f(self);
+        if self.performance_state() != before {
+            self.history
+                .record(before, edit, self.active_sequencer.clone());
+        }
+    }
+
+    /// Apply the edits made by `f` as one undo entry.
+    pub fn apply_edit_group(&mut self, f: impl FnOnce(&mut Self)) {
+        // The entries recorded by the single edits go to a history that is thrown away
+        let history = std::mem::replace(&mut self.history, History::new(0));
+        let before = self.performance_state();
+        f(self);
+        self.history = history;
+        if self.performance_state() != before {
+            self.history
+                .record(before, Edit::Group, self.active_sequencer.clone());
+        }
+    }
+
+    pub fn undo(&mut self) {
+        self.morph = None;
+        match self.history.undo(self.performance_state()) {
+            Some(state) => {
+                self.restore_performance_state(state);
+                info!("Undo");
+            }
+            None => info!("Nothing to undo"),
+        }
+    }
+
+    pub fn redo(&mut self) {
+        self.morph = None;
+        match self.history.redo(self.performance_state()) {
+            Some(state) => {
+                self.restore_performance_state(state);
+                info!("Redo");
+            }
+            None => info!("Nothing to redo"),
+        }
+    }
+
+    pub fn store_scene(&mut self, slot: usize, name: Option<String>) {
+        let name = name.unwrap_or_else(|| format!("Scene {}", slot + 1));
+        info!("Storing scene {} as '{}'", slot + 1, name);
+        self.scenes.store(slot, name, self.performance_state());
+    }
+
+    pub fn recall_scene(&mut self, slot: usize) {
+        let Some(scene) = self.scenes.get(slot) else {
+            info!("No scene stored in slot {}", slot + 1);
+            return;
+        };
+        info!("Recalling scene {} '{}'", slot + 1, scene.name);
+
+        let state = scene.state;
+        self.morph = None;
+        self.apply_edit(Edit::Scene, |s| s.restore_performance_state(state));
+    }
+
+    /// Recall a scene without recording it in the undo history, used by the arrangement.
+    fn play_scene(&mut self, slot: usize) {
+        match self.scenes.get(slot) {
+            Some(scene) => {
+                info!("Arrangement playing scene {} '{}'", slot + 1, scene.name);
+                let state = scene.state;
+                self.morph = None;
+                self.restore_performance_state(state);
+            }
+            None => warn!("Arrangement refers to empty scene slot {}", slot + 1),
+        }
+    }
+
+    /// Start interpolating from the current state to a stored scene over `morph_bars` bars.
+    pub fn morph_to_scene(&mut self, slot: usize) {
+        let Some(scene) = self.scenes.get(slot) else {
+            info!("No scene stored in slot {}", slot + 1);
+            return;
+        };
+        info!(
+            "Morphing to scene {} '{}' over {} bars",
+            slot + 1,
+            scene.name,
+            self.morph_bars
+        );
+
+        let current = self.performance_state();
+        self.morph = Some(Morph::new(
+            current,
+            scene.state,
+            self.morph_bars,
+            self.steps_per_bar(),
+        ));
+        self.history
+            .record(current, Edit::Scene, self.active_sequencer.clone());
+    }
+
+    /// Called by playback on every step to move a running morph forward.
+    pub fn advance_morph(&mut self) {
+        if let Some(morph) = self.morph.as_mut() {
+            let state = morph.advance();
+            if morph.is_finished() {
+                self.morph = None;
+                info!("Morph finished");
+            }
+            self.restore_performance_state(state);
+        }
+    }
+
+    pub fn toggle_arrangement(&mut self) {
+        if self.arrangement_position.take().is_some() {
+            info!("Arrangement mode off");
+            return;
+        }
+
+        match self.arrangement.start() {
+            Some((position, scene)) => {
+                info!("Arrangement mode on");
+                self.arrangement_position = Some(position);
+                self.play_scene(scene);
+            }
+            None => warn!("Arrangement has no sections"),
+        }
+    }
+
+    /// Called by playback on every step to move through the arrangement.
+    pub fn advance_arrangement(&mut self) {
+        let Some(position) = self.arrangement_position.as_mut() else {
+            return;
+        };
+
+        match self
+            .arrangement
+            .advance(position, self.time_signature.steps_per_bar())
+        {
+            Some(ArrangementEvent::Section(scene)) => self.play_scene(scene),
+            Some(ArrangementEvent::Finished) => {
+                info!("Arrangement finished");
+                self.arrangement_position = None;
+                self.playing = false;
+            }
+            None => {}
+        }
+    }
+
+    /// Remember a played step, so that the last bars of live output can be exported.
+    pub fn record_played_step(&mut self, step: (Option<Note>, Option<Note>)) {
+        self.played_steps.push_back(step);
+        while self.played_steps.len() > MAX_RECORDED_BARS * self.steps_per_bar() {
+            self.played_steps.pop_front();
+        }
+    }
+
+    pub fn last_played_steps(&self, bars: usize) -> Vec<(Option<Note>, Option<Note>)> {
+        let count = (bars * self.steps_per_bar()).min(self.played_steps.len());
+        self.played_steps
+            .range(self.played_steps.len() - count..)
+            .copied()
+            .collect()
+    }
+
+    pub fn export_options(&self) -> ExportOptions {
+        ExportOptions {
+            bpm: self.bpm,
+            time_signature: self.time_signature,
+            channels: (self.midi_channel, self.midi_channel),
         }
     }
 
-    pub fn increase_bpm(&mut self) {
-        self.bpm += 1.0;
+    /// Change the tempo by a fine or coarse step.
+    pub fn change_bpm(&mut self, delta: f32) {
+        self.apply_edit(Edit::Bpm, |s| {
+            // Round away the error of adding fine steps
+            s.bpm = clamp_bpm(((s.bpm + delta) * 10.0).round() / 10.0)
+        });
+    }
+
+    pub fn tap_tempo(&mut self) {
+        if let Some(bpm) = self.tap_tempo.tap(Instant::now()) {
+            let bpm = (bpm * 10.0).round() / 10.0;
+            self.apply_edit(Edit::Bpm, |s| s.bpm = bpm);
+            info!("Tapped {bpm} BPM");
+        }
     }
 
-    pub fn decrease_bpm(&mut self) {
-        self.bpm -= 1.0;
+    /// Start changing the tempo gradually to a target over `ramp_bars` bars.
+    pub fn start_tempo_ramp(&mut self, bpm: f32) {
+        let ramp = TempoRamp::new(self.bpm, bpm, self.ramp_bars, self.steps_per_bar());
+        info!(
+            "Ramping tempo to {} BPM over {} bars",
+            ramp.to, self.ramp_bars
+        );
+        self.tempo_ramp = Some(ramp);
+    }
+
+    /// Called by playback on every step to move a running tempo ramp forward.
+    pub fn advance_tempo_ramp(&mut self) {
+        if let Some(ramp) = self.tempo_ramp.as_mut() {
+            self.bpm = ramp.advance();
+            if ramp.is_finished() {
+                self.tempo_ramp = None;
+                info!("Tempo ramp finished");
+            }
+        }
+    }
+
+    pub fn steps_per_bar(&self) -> usize {
+        self.time_signature.steps_per_bar()
+    }
+
+    /// The bar, beat and step of the transport.
+    pub fn position(&self) -> Position {
+        Position::at(self.transport_steps, &self.time_signature)
+    }
+
+    /// Called by playback on every step, returning whether the patterns restart because a
+    /// bar starts. Sequences are regenerated a step ahead, so that they are ready for the bar.
+    pub fn advance_transport(&mut self) -> bool {
+        self.transport_steps += 1;
+        let bar_start = self.position().is_bar_start();
+        if bar_start {
+            self.left_mix.start_bar();
+            self.right_mix.start_bar();
+        }
+        match self.bar_action {
+            BarAction::None => false,
+            BarAction::Restart => bar_start,
+            BarAction::Regenerate => {
+                if Position::at(self.transport_steps + 1, &self.time_signature).is_bar_start() {
+                    self.generation += 1;
+                }
+                false
+            }
+        }
+    }
+
+    pub fn slot_mix(&self, slot: &SequencerSlot) -> &SlotMix {
+        match slot {
+            SequencerSlot::Left => &self.left_mix,
+            SequencerSlot::Right => &self.right_mix,
+        }
+    }
+
+    pub fn slot_mix_mut(&mut self, slot: &SequencerSlot) -> &mut SlotMix {
+        match slot {
+            SequencerSlot::Left => &mut self.left_mix,
+            SequencerSlot::Right => &mut self.right_mix,
+        }
+    }
+
+    /// The notes of a step as they are heard, after mute, solo and volume of the slots.
+    pub fn mix_step(&self, notes: (Option<Note>, Option<Note>)) -> (Option<Note>, Option<Note>) {
+        mix_step(notes, &self.left_mix, &self.right_mix)
+    }
+
+    pub fn toggle_mute(&mut self, slot: &SequencerSlot) {
+        let mix = self.slot_mix_mut(slot);
+        mix.muted = !mix.muted;
+        mix.mute_on_bar = false;
+        info!("Muted {:?}: {}", slot, mix.muted);
+    }
+
+    pub fn toggle_solo(&mut self, slot: &SequencerSlot) {
+        let mix = self.slot_mix_mut(slot);
+        mix.solo = !mix.solo;
+        info!("Soloed {:?}: {}", slot, mix.solo);
+    }
+
+    /// Toggle the mute of a slot at the start of the next bar, or cancel a pending toggle.
+    pub fn toggle_mute_on_bar(&mut self, slot: &SequencerSlot) {
+        let mix = self.slot_mix_mut(slot);
+        mix.mute_on_bar = !mix.mute_on_bar;
+        info!("Mute {:?} on the next bar: {}", slot, mix.mute_on_bar);
+    }
+
+    /// Change the metronome settings. They are returned to be saved once the state is
+    /// unlocked, so that writing the file does not hold up playback.
+    pub fn edit_metronome(&mut self, f: impl FnOnce(&mut MetronomeConfig)) -> MetronomeConfig {
+        f(&mut self.metronome);
+        self.metronome
+    }
+
+    /// Turn the click on or off, without stopping the transport.
+    pub fn toggle_metronome(&mut self) -> MetronomeConfig {
+        let metronome = self.edit_metronome(|metronome| metronome.enabled = !metronome.enabled);
+        info!("Metronome: {}", metronome.enabled);
+        metronome
+    }
+
+    /// Called when the transport starts, counting in first while recording is armed.
+    pub fn start_count_in(&mut self) {
+        self.count_in = if self.recording.armed {
+            self.metronome.count_in_bars * self.steps_per_bar()
+        } else {
+            0
+        };
+    }
+
+    /// The click of the current step, during the count-in or while the metronome is on.
+    pub fn click(&self) -> Option<Click> {
+        if self.count_in > 0 {
+            return self
+                .metronome
+                .click(&count_in_position(self.count_in, &self.time_signature));
+        }
+        if !self.metronome.enabled {
+            return None;
+        }
+        self.metronome.click(&self.position())
+    }
+
+    /// The tempo that is played, including a nudge.
+    pub fn tempo(&self) -> f32 {
+        self.bpm * self.nudge.factor()
     }
 
     pub fn change_midi_channel(&mut self) {
@@ -88,6 +533,412 @@ impl SharedState {
         }
     }
 
+    pub fn sequencer_type(&self, slot: &SequencerSlot) -> SequencerType {
+        match slot {
+            SequencerSlot::Left => self.left_sequencer_type,
+            SequencerSlot::Right => self.right_sequencer_type,
+        }
+    }
+
+    pub fn set_sequencer_type(&mut self, slot: &SequencerSlot, sequencer_type: SequencerType) {
+        match slot {
+            SequencerSlot::Left => self.left_sequencer_type = sequencer_type,
+            SequencerSlot::Right => self.right_sequencer_type = sequencer_type,
+        }
+        if sequencer_type != SequencerType::Step {
+            match slot {
+                SequencerSlot::Left => self.left_generator_type = sequencer_type,
+                SequencerSlot::Right => self.right_generator_type = sequencer_type,
+            }
+        }
+        info!("{:?} sequencer type: {}", slot, sequencer_type);
+    }
+
+    /// Whether the active slot plays a step pattern, so that keys edit its steps.
+    pub fn is_step_active(&self) -> bool {
+        self.sequencer_type(&self.active_sequencer) == SequencerType::Step
+    }
+
+    pub fn step_state(&self, slot: &SequencerSlot) -> &StepSequencerState {
+        match slot {
+            SequencerSlot::Left => &self.left_step_state,
+            SequencerSlot::Right => &self.right_step_state,
+        }
+    }
+
+    pub fn step_state_mut(&mut self, slot: &SequencerSlot) -> &mut StepSequencerState {
+        match slot {
+            SequencerSlot::Left => &mut self.left_step_state,
+            SequencerSlot::Right => &mut self.right_step_state,
+        }
+    }
+
+    /// Move the step cursor on the 4x4 grid, wrapping around.
+    pub fn move_step_cursor(&mut self, delta: isize) {
+        self.step_cursor =
+            (self.step_cursor as isize + delta).rem_euclid(MAX_STEPS as isize) as usize;
+    }
+
+    /// Select a step of a slot for editing, making the slot active.
+    pub fn select_step(&mut self, slot: &SequencerSlot, index: usize) {
+        self.active_sequencer = slot.clone();
+        self.step_cursor = index.min(MAX_STEPS - 1);
+    }
+
+    /// Switch the step under the cursor of the active step sequencer on or off.
+    pub fn toggle_step(&mut self) {
+        let (slot, index) = (self.active_sequencer.clone(), self.step_cursor);
+        self.apply_edit(Edit::Step, |s| s.step_state_mut(&slot).toggle(index));
+    }
+
+    /// Change the step under the cursor of the active step sequencer.
+    pub fn edit_step(&mut self, f: impl FnOnce(&mut Step)) {
+        let (slot, index) = (self.active_sequencer.clone(), self.step_cursor);
+        self.apply_edit(Edit::Step, |s| f(&mut s.step_state_mut(&slot).steps[index]));
+    }
+
+    /// The sequence a slot currently generates, or its imported sequence.
+    pub fn slot_sequence(&self, slot: &SequencerSlot) -> Sequence {
+        match self.sequencer_type(slot) {
+            SequencerType::Step => self.step_state(slot).to_sequence(),
+            SequencerType::Arpeggiator => self.arpeggiator(slot).to_sequence(
+                &self.arpeggiator_chords(slot),
+                self.steps_per_bar(),
+                self.arpeggiator_rhythm(slot).as_ref(),
+            ),
+            SequencerType::Euclidean => match slot {
+                SequencerSlot::Left => self.left_fixed_sequence.clone(),
+                SequencerSlot::Right => self.right_fixed_sequence.clone(),
+            }
+            .unwrap_or_else(|| self.slot_state(slot).to_sequence()),
+        }
+    }
+
+    /// Turn the sequence that a slot currently generates into a step pattern that can be
+    /// edited step by step. The generator keeps its settings for unfreezing. Sequences
+    /// longer than a step pattern are cut off.
+    pub fn freeze(&mut self, slot: &SequencerSlot) {
+        let sequence = self.slot_sequence(slot);
+        if sequence.notes.len() > MAX_STEPS {
+            warn!(
+                "Only the first {} of {} steps of the {:?} sequencer are frozen",
+                MAX_STEPS,
+                sequence.notes.len(),
+                slot
+            );
+        }
+        let step_state = StepSequencerState::from_sequence(&sequence);
+        // The pattern and the type change are undone together
+        self.apply_edit(Edit::SequencerType, |s| {
+            *s.step_state_mut(slot) = step_state;
+            s.set_sequencer_type(slot, SequencerType::Step);
+        });
+        info!("Froze {:?} sequencer", slot);
+    }
+
+    /// Return a frozen slot to its generator, keeping the step pattern for later.
+    pub fn unfreeze(&mut self, slot: &SequencerSlot) {
+        let generator_type = match slot {
+            SequencerSlot::Left => self.left_generator_type,
+            SequencerSlot::Right => self.right_generator_type,
+        };
+        self.apply_edit(Edit::SequencerType, |s| {
+            s.set_sequencer_type(slot, generator_type)
+        });
+        info!("Unfroze {:?} sequencer", slot);
+    }
+
+    pub fn arpeggiator(&self, slot: &SequencerSlot) -> &ArpeggiatorState {
+        match slot {
+            SequencerSlot::Left => &self.left_arpeggiator,
+            SequencerSlot::Right => &self.right_arpeggiator,
+        }
+    }
+
+    pub fn arpeggiator_mut(&mut self, slot: &SequencerSlot) -> &mut ArpeggiatorState {
+        match slot {
+            SequencerSlot::Left => &mut self.left_arpeggiator,
+            SequencerSlot::Right => &mut self.right_arpeggiator,
+        }
+    }
+
+    /// The chords the arpeggiator of a slot plays, one per bar.
+    pub fn arpeggiator_chords(&self, slot: &SequencerSlot) -> Vec<Vec<u8>> {
+        let arpeggiator = self.arpeggiator(slot);
+        match arpeggiator.input {
+            ChordInput::Midi => vec![self.held_notes.chord().to_vec()],
+            ChordInput::Lane => arpeggiator
+                .chord_lane
+                .iter()
+                .map(|chord| chord.pitches())
+                .collect(),
+        }
+    }
+
+    /// The Euclidean pattern of the other slot, if the arpeggiator of a slot uses it as rhythm.
+    pub fn arpeggiator_rhythm(&self, slot: &SequencerSlot) -> Option<EuclideanSequencerState> {
+        let other = match slot {
+            SequencerSlot::Left => SequencerSlot::Right,
+            SequencerSlot::Right => SequencerSlot::Left,
+        };
+        self.arpeggiator(slot)
+            .rhythm_gate
+            .then(|| *self.slot_state(&other))
+    }
+
+    fn active_fixed_sequence_mut(&mut self) -> &mut Option<Sequence> {
+        match self.active_sequencer {
+            SequencerSlot::Left => &mut self.left_fixed_sequence,
+            SequencerSlot::Right => &mut self.right_fixed_sequence,
+        }
+    }
+
+    fn active_state_mut(&mut self) -> &mut EuclideanSequencerState {
+        let slot = self.active_sequencer.clone();
+        self.slot_state_mut(&slot)
+    }
+
+    fn slot_state_mut(&mut self, slot: &SequencerSlot) -> &mut EuclideanSequencerState {
+        match slot {
+            SequencerSlot::Left => &mut self.left_state,
+            SequencerSlot::Right => &mut self.right_state,
+        }
+    }
+
+    /// Use an imported MIDI track in the active sequencer.
+    pub fn import_track(&mut self, track: &ImportedTrack, mode: ImportMode) {
+        info!(
+            "Importing track '{}' as {} into {:?} sequencer",
+            track.name, mode, self.active_sequencer
+        );
+
+        match mode {
+            ImportMode::Sequence => {
+                let sequence = track.to_sequence();
+                *self.active_fixed_sequence_mut() = Some(sequence);
+            }
+            ImportMode::Rhythm => {
+                *self.active_fixed_sequence_mut() = None;
+                self.apply_edit(Edit::Import, |s| {
+                    let state = s.active_state_mut();
+                    *state = track.to_euclidean(*state);
+                });
+            }
+            ImportMode::Pitches => {
+                *self.active_fixed_sequence_mut() = None;
+                self.apply_edit(Edit::Import, |s| {
+                    let state = s.active_state_mut();
+                    *state = track.to_pitch_lane(*state);
+                });
+            }
+        }
+    }
+
+    /// Go back to the generated sequence and drop the pitch lane of the active sequencer.
+    pub fn clear_import(&mut self) {
+        *self.active_fixed_sequence_mut() = None;
+        self.apply_edit(Edit::Import, |s| s.active_state_mut().pitch_lane.clear());
+    }
+
+    /// Duration of one sixteenth step in milliseconds.
+    pub fn step_duration(&self) -> f32 {
+        60000.0 / self.tempo() / 4.0
+    }
+
+    /// The step of the active sequencer that is currently sounding, if playing.
+    fn playhead(&self) -> Option<Playhead> {
+        let started = self.step_started.filter(|_| self.playing)?;
+        let mixed_len = self.mixed_sequence.notes.len().max(1);
+        let slot_len = match self.active_sequencer {
+            SequencerSlot::Left => self.left_fixed_sequence.as_ref(),
+            SequencerSlot::Right => self.right_fixed_sequence.as_ref(),
+        }
+        .map(|sequence| sequence.notes.len())
+        .unwrap_or(self.steps_per_bar())
+        .max(1);
+
+        let mixed_step = (self.current_note_index + mixed_len - 1) % mixed_len;
+        Some(Playhead {
+            step: mixed_step % slot_len,
+            fraction: started.elapsed().as_secs_f32() * 1000.0 / self.step_duration(),
+        })
+    }
+
+    /// Notes are only recorded into Euclidean slots, which play the recorded sequence.
+    fn records_active_slot(&self) -> bool {
+        self.sequencer_type(&self.active_sequencer) == SequencerType::Euclidean
+    }
+
+    pub fn toggle_recording(&mut self) {
+        if !self.recording.armed && !self.records_active_slot() {
+            warn!(
+                "Recording needs a Euclidean sequencer, the {:?} one is {}",
+                self.active_sequencer,
+                self.sequencer_type(&self.active_sequencer)
+            );
+            return;
+        }
+        self.recording.toggle();
+    }
+
+    pub fn record_note_on(&mut self, pitch: u8, velocity: u8) {
+        self.held_notes.note_on(pitch);
+        // An arpeggiator only plays the held notes, and step slots have no sequence to record to
+        if !self.records_active_slot() {
+            return;
+        }
+        let playhead = self.playhead();
+        let steps_per_bar = self.steps_per_bar();
+        let (state, fixed_sequence) = match self.active_sequencer {
+            SequencerSlot::Left => (&mut self.left_state, &mut self.left_fixed_sequence),
+            SequencerSlot::Right => (&mut self.right_state, &mut self.right_fixed_sequence),
+        };
+        self.recording.note_on(
+            pitch,
+            velocity,
+            state,
+            fixed_sequence,
+            steps_per_bar,
+            playhead,
+        );
+    }
+
+    pub fn record_note_off(&mut self, pitch: u8) {
+        // Held chords are latched while an arpeggiator with latch plays them
+        let latch = [SequencerSlot::Left, SequencerSlot::Right]
+            .iter()
+            .any(|slot| {
+                self.sequencer_type(slot) == SequencerType::Arpeggiator
+                    && self.arpeggiator(slot).latch
+            });
+        self.held_notes.note_off(pitch, latch);
+        self.recording.note_off(pitch);
+    }
+
+    /// Called by playback at the start of every step.
+    pub fn advance_recording(&mut self) {
+        self.step_started = Some(Instant::now());
+        if !self.records_active_slot() {
+            return;
+        }
+        if let Some(playhead) = self.playhead() {
+            let fixed_sequence = match self.active_sequencer {
+                SequencerSlot::Left => &mut self.left_fixed_sequence,
+                SequencerSlot::Right => &mut self.right_fixed_sequence,
+            };
+            self.recording.advance(fixed_sequence, playhead);
+        }
+    }
+
+    fn slot_state(&self, slot: &SequencerSlot) -> &EuclideanSequencerState {
+        match slot {
+            SequencerSlot::Left => &self.left_state,
+            SequencerSlot::Right => &self.right_state,
+        }
+    }
+
+    /// Current value of a parameter that can be mapped to a MIDI control.
+    pub fn parameter_value(&self, parameter: Parameter) -> f32 {
+        self.slot_parameter_value(&self.active_sequencer, parameter)
+    }
+
+    /// Current value of a parameter, taking parameters of a sequencer from the given slot.
+    pub fn slot_parameter_value(&self, slot: &SequencerSlot, parameter: Parameter) -> f32 {
+        let state = self.slot_state(slot);
+        match parameter {
+            Parameter::Steps => state.steps as f32,
+            Parameter::Pulses => state.pulses as f32,
+            Parameter::Phase => state.phase as f32,
+            Parameter::Pitch => state.pitch as f32,
+            Parameter::MixerRatio => self.mixer_state.ratio,
+            Parameter::Bpm => self.bpm,
+            Parameter::PlayStop => self.playing as u8 as f32,
+            Parameter::ActiveSlot => (self.active_sequencer == SequencerSlot::Right) as u8 as f32,
+            Parameter::Mute => self.slot_mix(slot).muted as u8 as f32,
+            Parameter::Solo => self.slot_mix(slot).solo as u8 as f32,
+            Parameter::Volume => self.slot_mix(slot).volume,
+            Parameter::MuteNextBar => self.slot_mix(slot).mute_on_bar as u8 as f32,
+        }
+    }
+
+    /// Set a parameter from a MIDI control. Parameters of a sequencer apply to the active one.
+    pub fn set_parameter(&mut self, parameter: Parameter, value: f32) {
+        let slot = self.active_sequencer.clone();
+        self.set_slot_parameter(&slot, parameter, value);
+    }
+
+    /// Set a parameter, applying parameters of a sequencer to the given slot. Values that are
+    /// not finite are ignored.
+    pub fn set_slot_parameter(&mut self, slot: &SequencerSlot, parameter: Parameter, value: f32) {
+        if !value.is_finite() {
+            warn!("Ignoring {value} for {parameter:?}");
+            return;
+        }
+        // Only the mixer ratio and the volume are not whole numbers
+        let ratio = value;
+        let value = value.round();
+        match parameter {
+            Parameter::Steps => self.apply_edit(Edit::Steps, |s| {
+                let state = s.slot_state_mut(slot);
+                state.steps = value.clamp(1.0, 16.0) as usize;
+                state.phase %= state.steps;
+            }),
+            Parameter::Pulses => self.apply_edit(Edit::Pulses, |s| {
+                s.slot_state_mut(slot).pulses = value.clamp(0.0, 16.0) as usize
+            }),
+            Parameter::Phase => self.apply_edit(Edit::Phase, |s| {
+                let state = s.slot_state_mut(slot);
+                state.phase = value.max(0.0) as usize % state.steps.max(1);
+            }),
+            Parameter::Pitch => self.apply_edit(Edit::Pitch, |s| {
+                s.slot_state_mut(slot).pitch = value.clamp(0.0, 127.0) as u8
+            }),
+            Parameter::MixerRatio => {
+                self.apply_edit(Edit::MixerRatio, |s| s.mixer_state.set_ratio(ratio))
+            }
+            Parameter::Bpm => self.apply_edit(Edit::Bpm, |s| s.bpm = clamp_bpm(value)),
+            Parameter::PlayStop => self.playing = value > 0.0,
+            Parameter::ActiveSlot => {
+                self.active_sequencer = if value > 0.0 {
+                    SequencerSlot::Right
+                } else {
+                    SequencerSlot::Left
+                }
+            }
+            Parameter::Mute => self.slot_mix_mut(slot).muted = value > 0.0,
+            Parameter::Solo => self.slot_mix_mut(slot).solo = value > 0.0,
+            Parameter::Volume => self.slot_mix_mut(slot).volume = ratio.clamp(0.0, 1.0),
+            Parameter::MuteNextBar => self.slot_mix_mut(slot).mute_on_bar = value > 0.0,
+        }
+    }
+
+    /// Handle a value from a MIDI control, returning None if the control is not mapped.
+    pub fn handle_control(&mut self, control: Control, value: u8) -> Option<ControlAction> {
+        let mut midi_map = std::mem::take(&mut self.midi_map);
+        let action = midi_map.handle(control, value, |parameter| self.parameter_value(parameter));
+        self.midi_map = midi_map;
+
+        match action? {
+            ControlAction::Set(parameter, value) => self.set_parameter(parameter, value),
+            ControlAction::Trigger(Parameter::PlayStop) => {
+                self.playing = !self.playing;
+                info!("Playing: {}", self.playing);
+            }
+            ControlAction::Trigger(Parameter::ActiveSlot) => self.switch_active_sequencer(),
+            ControlAction::Trigger(Parameter::Mute) => {
+                self.toggle_mute(&self.active_sequencer.clone())
+            }
+            ControlAction::Trigger(Parameter::Solo) => {
+                self.toggle_solo(&self.active_sequencer.clone())
+            }
+            ControlAction::Trigger(Parameter::MuteNextBar) => {
+                self.toggle_mute_on_bar(&self.active_sequencer.clone())
+            }
+            ControlAction::Trigger(_) | ControlAction::Learned(_) => {}
+        }
+        action
+    }
+
     pub fn switch_active_sequencer(&mut self) {
         match self.active_sequencer {
             SequencerSlot::Left => self.active_sequencer = SequencerSlot::Right,
@@ -102,13 +953,61 @@ impl fmt::Debug for SharedState {
         f.debug_struct("Shared State")
             .field("playing", &self.playing)
             .field("bpm", &self.bpm)
+            .field("nudge", &self.nudge)
+            .field("tempo ramp", &self.tempo_ramp)
+            .field("time signature", &self.time_signature)
+            .field("position", &self.position())
             .field("midi channel", &self.midi_channel)
             .field("active sequencer", &self.active_sequencer)
             .field("left sequencer state", &self.left_state)
             .field("right sequencer state", &self.right_state)
+            .field("left sequencer type", &self.left_sequencer_type)
+            .field("right sequencer type", &self.right_sequencer_type)
+            .field("left step state", &self.left_step_state)
+            .field("right step state", &self.right_step_state)
+            .field("left arpeggiator", &self.left_arpeggiator)
+            .field("right arpeggiator", &self.right_arpeggiator)
+            .field("held notes", &self.held_notes)
+            .field("left fixed sequence", &self.left_fixed_sequence)
+            .field("right fixed sequence", &self.right_fixed_sequence)
             .field("mixer state", &self.mixer_state)
+            .field("left mix", &self.left_mix)
+            .field("right mix", &self.right_mix)
             .field("clock ticks", &self.clock_ticks)
             .field("quarter notes", &self.quarter_notes)
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recording_needs_euclidean_slot() {
+        let mut state = SharedState::new(120.0);
+        state.set_sequencer_type(&SequencerSlot::Left, SequencerType::Step);
+        state.toggle_recording();
+        assert!(!state.recording.armed);
+
+        // Notes are not recorded when the slot changes type after arming
+        state.set_sequencer_type(&SequencerSlot::Left, SequencerType::Euclidean);
+        state.toggle_recording();
+        assert!(state.recording.armed);
+        state.set_sequencer_type(&SequencerSlot::Left, SequencerType::Step);
+        state.record_note_on(60, 100);
+        state.record_note_off(60);
+        assert_eq!(state.left_fixed_sequence, None);
+
+        // An arpeggiator only takes the held notes
+        state.set_sequencer_type(&SequencerSlot::Left, SequencerType::Arpeggiator);
+        state.record_note_on(64, 100);
+        assert_eq!(state.held_notes.chord(), &[64]);
+        assert_eq!(state.left_fixed_sequence, None);
+        state.record_note_off(64);
+
+        state.set_sequencer_type(&SequencerSlot::Left, SequencerType::Euclidean);
+        state.record_note_on(60, 100);
+        assert!(state.left_fixed_sequence.is_some());
+    }
+}
@@ -0,0 +1,119 @@
+use std::sync::{Arc, Mutex};
+
+use crate::midi::error::MidiError;
+use crate::synth::Synth;
+
+/// Number of frames rendered at a time, about 6 ms at 44.1 kHz.
+#[cfg(feature = "synth")]
+const PERIOD_FRAMES: usize = 256;
+
+/// Plays the synth on the default ALSA audio device from its own thread, until dropped.
+pub struct AudioOutput {
+    #[cfg(feature = "synth")]
+    running: Arc<std::sync::atomic::AtomicBool>,
+    #[cfg(feature = "synth")]
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+#[cfg(feature = "synth")]
+impl AudioOutput {
+    pub fn start(synth: Arc<Mutex<Synth>>) -> Result<Self, MidiError> {
+        use std::sync::atomic::AtomicBool;
+        use std::sync::mpsc;
+
+        let running = Arc::new(AtomicBool::new(true));
+        let (tx_started, rx_started) = mpsc::sync_channel(1);
+        let thread = {
+            let running = running.clone();
+            std::thread::spawn(move || match open_device(&synth) {
+                Ok(pcm) => {
+                    let _ = tx_started.send(Ok(()));
+                    play(&pcm, &synth, &running);
+                }
+                Err(e) => {
+                    let _ = tx_started.send(Err(e));
+                }
+            })
+        };
+
+        rx_started
+            .recv()
+            .map_err(|e| MidiError::Audio(e.to_string()))
+            .and_then(|started| started.map_err(|e| MidiError::Audio(e.to_string())))?;
+        log::info!("Started the built-in synth");
+        Ok(AudioOutput {
+            running,
+            thread: Some(thread),
+        })
+    }
+}
+
+#[cfg(not(feature = "synth"))]
+impl AudioOutput {
+    pub fn start(_synth: Arc<Mutex<Synth>>) -> Result<Self, MidiError> {
+        Err(MidiError::Audio(
+            "built without the synth feature".to_string(),
+        ))
+    }
+}
+
+#[cfg(feature = "synth")]
+impl Drop for AudioOutput {
+    fn drop(&mut self) {
+        self.running
+            .store(false, std::sync::atomic::Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Open the default device for 16 bit mono playback, at the sample rate of the synth or the
+/// closest one the device supports.
+#[cfg(feature = "synth")]
+fn open_device(synth: &Mutex<Synth>) -> alsa::Result<alsa::PCM> {
+    use alsa::pcm::{Access, Format, HwParams};
+    use alsa::{Direction, ValueOr, PCM};
+
+    let pcm = PCM::new("default", Direction::Playback, false)?;
+    {
+        let mut synth = synth.lock().unwrap();
+        let hwp = HwParams::any(&pcm)?;
+        hwp.set_channels(1)?;
+        synth.sample_rate = hwp.set_rate_near(synth.sample_rate, ValueOr::Nearest)?;
+        hwp.set_format(Format::s16())?;
+        hwp.set_access(Access::RWInterleaved)?;
+        hwp.set_period_size_near(PERIOD_FRAMES as alsa::pcm::Frames, ValueOr::Nearest)?;
+        hwp.set_buffer_size_near(4 * PERIOD_FRAMES as alsa::pcm::Frames)?;
+        pcm.hw_params(&hwp)?;
+    }
+    Ok(pcm)
+}
+
+#[cfg(feature = "synth")]
+fn play(pcm: &alsa::PCM, synth: &Mutex<Synth>, running: &std::sync::atomic::AtomicBool) {
+    let io = match pcm.io_i16() {
+        Ok(io) => io,
+        Err(e) => {
+            log::error!("Failed to play the built-in synth: {e}");
+            return;
+        }
+    };
+    let mut buffer = [0.0; PERIOD_FRAMES];
+    let mut samples = [0i16; PERIOD_FRAMES];
+
+    while running.load(std::sync::atomic::Ordering::Relaxed) {
+        synth.lock().unwrap().render(&mut buffer);
+        for (sample, value) in samples.iter_mut().zip(buffer) {
+            *sample = (value * i16::MAX as f32) as i16;
+        }
+        // Writing blocks until the device has room, which paces the rendering
+        if let Err(e) = io.writei(&samples) {
+            if let Err(e) = pcm.try_recover(e, true) {
+                log::error!("Failed to play the built-in synth: {e}");
+                return;
+            }
+        }
+    }
+    let _ = pcm.drain();
+}
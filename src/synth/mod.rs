@@ -0,0 +1,267 @@
+pub mod audio;
+pub mod render;
+pub mod timbre;
+
+use crate::state::SequencerSlot;
+use timbre::{Adsr, SynthConfig, Timbre, Waveform};
+
+/// Sample rate the synth renders at, unless the audio device asks for another one.
+pub const SAMPLE_RATE: u32 = 44100;
+
+/// Maximum number of notes sounding at once. The oldest note is cut off to play a new one.
+pub const MAX_VOICES: usize = 16;
+
+/// Gain of every voice, leaving headroom for several voices playing together.
+const VOICE_GAIN: f32 = 0.2;
+
+/// Voices of metronome clicks use their own short timbre, after the timbres of the slots.
+const CLICK_VOICE: usize = 2;
+const CLICK_TIMBRE: Timbre = Timbre {
+    waveform: Waveform::Sine,
+    envelope: Adsr {
+        attack: 0.001,
+        decay: 0.04,
+        sustain: 0.0,
+        release: 0.01,
+    },
+    volume: 1.0,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    /// Fading out linearly by the given amount per sample.
+    Release(f32),
+    Done,
+}
+
+/// Level of a voice over time, following the ADSR of its timbre.
+#[derive(Clone, Copy, Debug)]
+struct Envelope {
+    stage: Stage,
+    level: f32,
+}
+
+impl Envelope {
+    fn new() -> Self {
+        Envelope {
+            stage: Stage::Attack,
+            level: 0.0,
+        }
+    }
+
+    fn release(&mut self, adsr: &Adsr, sample_rate: f32) {
+        let samples = (adsr.release * sample_rate).max(1.0);
+        self.stage = Stage::Release(self.level / samples);
+    }
+
+    fn next(&mut self, adsr: &Adsr, sample_rate: f32) -> f32 {
+        match self.stage {
+            Stage::Attack => {
+                self.level += 1.0 / (adsr.attack * sample_rate).max(1.0);
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level -= (1.0 - adsr.sustain) / (adsr.decay * sample_rate).max(1.0);
+                if self.level <= adsr.sustain {
+                    self.level = adsr.sustain;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => self.level = adsr.sustain,
+            Stage::Release(step) => {
+                self.level -= step;
+                if self.level <= 0.0 {
+                    self.level = 0.0;
+                    self.stage = Stage::Done;
+                }
+            }
+            Stage::Done => {}
+        }
+        self.level
+    }
+}
+
+/// A sounding note.
+#[derive(Clone, Copy, Debug)]
+struct Voice {
+    slot: usize,
+    pitch: u8,
+    gain: f32,
+    /// Position in the waveform, from 0 to 1.
+    phase: f32,
+    envelope: Envelope,
+}
+
+impl Voice {
+    fn is_released(&self) -> bool {
+        matches!(self.envelope.stage, Stage::Release(_) | Stage::Done)
+    }
+}
+
+fn slot_index(slot: &SequencerSlot) -> usize {
+    match slot {
+        SequencerSlot::Left => 0,
+        SequencerSlot::Right => 1,
+    }
+}
+
+/// Frequency of a MIDI pitch in Hz, with A4 (69) at 440 Hz.
+pub fn frequency(pitch: u8) -> f32 {
+    440.0 * 2f32.powf((pitch as f32 - 69.0) / 12.0)
+}
+
+/// A small polyphonic synth that plays the notes of both sequencer slots, each with its own
+/// timbre, and renders them to mono samples.
+pub struct Synth {
+    pub sample_rate: u32,
+    timbres: [Timbre; 2],
+    voices: Vec<Voice>,
+}
+
+impl Synth {
+    pub fn new(sample_rate: u32, config: &SynthConfig) -> Self {
+        Synth {
+            sample_rate,
+            timbres: [config.left, config.right],
+            voices: Vec::with_capacity(MAX_VOICES),
+        }
+    }
+
+    pub fn set_timbres(&mut self, config: &SynthConfig) {
+        self.timbres = [config.left, config.right];
+    }
+
+    /// Number of notes that are still audible, including released ones.
+    pub fn active_voices(&self) -> usize {
+        self.voices.len()
+    }
+
+    pub fn note_on(&mut self, slot: &SequencerSlot, pitch: u8, velocity: u8) {
+        if velocity == 0 {
+            self.note_off(slot, pitch);
+            return;
+        }
+        self.start_voice(slot_index(slot), pitch, velocity);
+    }
+
+    /// Play a metronome click, which fades out by itself.
+    pub fn click(&mut self, pitch: u8, velocity: u8) {
+        self.start_voice(CLICK_VOICE, pitch, velocity);
+    }
+
+    fn start_voice(&mut self, slot: usize, pitch: u8, velocity: u8) {
+        if self.voices.len() >= MAX_VOICES {
+            self.voices.remove(0);
+        }
+        self.voices.push(Voice {
+            slot,
+            pitch,
+            gain: velocity as f32 / 127.0,
+            phase: 0.0,
+            envelope: Envelope::new(),
+        });
+    }
+
+    /// Release the oldest sounding voice of the note.
+    pub fn note_off(&mut self, slot: &SequencerSlot, pitch: u8) {
+        let slot = slot_index(slot);
+        let sample_rate = self.sample_rate as f32;
+        if let Some(voice) = self
+            .voices
+            .iter_mut()
+            .find(|voice| voice.slot == slot && voice.pitch == pitch && !voice.is_released())
+        {
+            voice
+                .envelope
+                .release(&self.timbres[slot].envelope, sample_rate);
+        }
+    }
+
+    /// Release all notes, or cut them off immediately on a panic.
+    pub fn all_notes_off(&mut self, panic: bool) {
+        if panic {
+            self.voices.clear();
+            return;
+        }
+        let sample_rate = self.sample_rate as f32;
+        let timbres = self.timbres;
+        for voice in self.voices.iter_mut().filter(|voice| !voice.is_released()) {
+            let timbre = timbres.get(voice.slot).unwrap_or(&CLICK_TIMBRE);
+            voice.envelope.release(&timbre.envelope, sample_rate);
+        }
+    }
+
+    /// Fill the buffer with the next samples, from -1 to 1.
+    pub fn render(&mut self, buffer: &mut [f32]) {
+        let sample_rate = self.sample_rate as f32;
+        buffer.fill(0.0);
+        let timbres = self.timbres;
+        for voice in self.voices.iter_mut() {
+            let timbre = timbres.get(voice.slot).unwrap_or(&CLICK_TIMBRE);
+            let increment = frequency(voice.pitch) / sample_rate;
+            for sample in buffer.iter_mut() {
+                let level = voice.envelope.next(&timbre.envelope, sample_rate);
+                *sample += timbre.waveform.sample(voice.phase)
+                    * level
+                    * voice.gain
+                    * timbre.volume
+                    * VOICE_GAIN;
+                voice.phase = (voice.phase + increment).fract();
+            }
+        }
+        // Voices that decayed to a sustain of 0, like clicks, are silent for good
+        self.voices.retain(|voice| {
+            voice.envelope.stage != Stage::Done
+                && !(voice.envelope.stage == Stage::Sustain && voice.envelope.level == 0.0)
+        });
+        for sample in buffer.iter_mut() {
+            *sample = sample.clamp(-1.0, 1.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_synth() {
+        let mut synth = Synth::new(1000, &SynthConfig::default());
+        let mut buffer = [0.0; 100];
+
+        synth.note_on(&SequencerSlot::Left, 69, 127);
+        synth.note_on(&SequencerSlot::Right, 69, 127);
+        synth.render(&mut buffer);
+        assert!(buffer.iter().any(|sample| sample.abs() > 0.01));
+        assert_eq!(synth.active_voices(), 2);
+
+        // Voices fade out after the release of their timbre, and are then removed
+        synth.note_off(&SequencerSlot::Left, 69);
+        synth.render(&mut buffer[..50]);
+        assert_eq!(synth.active_voices(), 2);
+        synth.render(&mut buffer);
+        assert_eq!(synth.active_voices(), 1);
+
+        synth.all_notes_off(true);
+        synth.render(&mut buffer);
+        assert!(buffer.iter().all(|sample| *sample == 0.0));
+
+        // Clicks fade out by themselves
+        synth.click(84, 127);
+        synth.render(&mut buffer);
+        assert!(buffer.iter().any(|sample| sample.abs() > 0.01));
+        assert_eq!(synth.active_voices(), 0);
+
+        for pitch in 0..MAX_VOICES as u8 + 4 {
+            synth.note_on(&SequencerSlot::Left, pitch, 100);
+        }
+        assert_eq!(synth.active_voices(), MAX_VOICES);
+        assert_eq!(frequency(81), 880.0);
+    }
+}
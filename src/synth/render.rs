@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::note::Note;
+use crate::state::SequencerSlot;
+use crate::synth::timbre::SynthConfig;
+use crate::synth::{Synth, SAMPLE_RATE};
+
+/// File the current sequence is rendered to, relative to the working directory.
+pub const RENDER_FILE: &str = "render.wav";
+
+/// Silence rendered after the last step, so that released notes can fade out.
+const TAIL_SECONDS: f32 = 1.0;
+
+/// Settings for rendering steps to audio.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderOptions {
+    pub bpm: f32,
+    pub bars: usize,
+    /// Length of a bar in the time signature.
+    pub steps_per_bar: usize,
+    pub sample_rate: u32,
+}
+
+impl RenderOptions {
+    pub fn new(bpm: f32, bars: usize, steps_per_bar: usize) -> Self {
+        RenderOptions {
+            bpm,
+            bars,
+            steps_per_bar,
+            sample_rate: SAMPLE_RATE,
+        }
+    }
+}
+
+/// A note on or off at a sample position.
+struct SynthEvent {
+    sample: usize,
+    slot: SequencerSlot,
+    pitch: u8,
+    /// Velocity of a note on, or None for a note off.
+    velocity: Option<u8>,
+}
+
+/// Render steps of (left, right) notes on the built-in synth, looping them for the given
+/// number of bars. Rendering does not depend on any audio device or on the clock, so the
+/// same steps always render to the same samples.
+pub fn render_steps(
+    steps: &[(Option<Note>, Option<Note>)],
+    options: &RenderOptions,
+    config: &SynthConfig,
+) -> Vec<f32> {
+    let sample_rate = options.sample_rate as f32;
+    let step_millis = 60000.0 / options.bpm / 4.0;
+    let step_samples = step_millis / 1000.0 * sample_rate;
+    let step_count = options.bars * options.steps_per_bar;
+    let length = (step_count as f32 * step_samples + TAIL_SECONDS * sample_rate) as usize;
+
+    let mut events = Vec::new();
+    for (step, (left, right)) in steps.iter().cycle().take(step_count).enumerate() {
+        let start = (step as f32 * step_samples) as usize;
+        for (slot, note) in [(SequencerSlot::Left, *left), (SequencerSlot::Right, *right)] {
+            // Notes with pitch or velocity 0 are rests
+            let Some(note) = note.filter(|note| note.pitch != 0 && note.velocity != 0) else {
+                continue;
+            };
+            let duration = (note.millis(step_millis) / 1000.0 * sample_rate).max(1.0) as usize;
+            events.push(SynthEvent {
+                sample: start,
+                slot: slot.clone(),
+                pitch: note.pitch,
+                velocity: Some(note.velocity),
+            });
+            events.push(SynthEvent {
+                sample: start + duration,
+                slot,
+                pitch: note.pitch,
+                velocity: None,
+            });
+        }
+    }
+    // Note offs go before note ons at the same sample, so that repeated notes retrigger
+    events.sort_by_key(|event| (event.sample, event.velocity.is_some()));
+
+    let mut synth = Synth::new(options.sample_rate, config);
+    let mut samples = vec![0.0; length];
+    let mut position = 0;
+    for event in events.iter().filter(|event| event.sample < length) {
+        synth.render(&mut samples[position..event.sample]);
+        position = event.sample;
+        match event.velocity {
+            Some(velocity) => synth.note_on(&event.slot, event.pitch, velocity),
+            None => synth.note_off(&event.slot, event.pitch),
+        }
+    }
+    synth.render(&mut samples[position..]);
+    samples
+}
+
+/// Encode mono samples as a 16 bit PCM WAV file.
+pub fn encode_wav(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let data_size = samples.len() as u32 * 2;
+    let mut wav = Vec::with_capacity(44 + data_size as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * 2).to_le_bytes()); // bytes per second
+    wav.extend_from_slice(&2u16.to_le_bytes()); // bytes per frame
+    wav.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+    for sample in samples {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        wav.extend_from_slice(&value.to_le_bytes());
+    }
+    wav
+}
+
+pub fn write_file(
+    steps: &[(Option<Note>, Option<Note>)],
+    options: &RenderOptions,
+    config: &SynthConfig,
+    path: &Path,
+) -> Result<()> {
+    let samples = render_steps(steps, options, config);
+    fs::write(path, encode_wav(&samples, options.sample_rate))
+        .with_context(|| format!("Failed to write WAV file to {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::NoteDuration;
+
+    #[test]
+    fn test_render_steps() {
+        let note = Note::new(60, 100, NoteDuration::Sixteenth);
+        let steps = vec![(Some(note), None), (None, Some(note)), (None, None)];
+        let options = RenderOptions {
+            bpm: 120.0,
+            bars: 2,
+            steps_per_bar: 16,
+            sample_rate: 8000,
+        };
+        let config = SynthConfig::default();
+
+        let samples = render_steps(&steps, &options, &config);
+        // 32 steps of 125 ms, followed by the tail
+        assert_eq!(samples.len(), 32 * 1000 + 8000);
+        assert!(samples[..1000].iter().any(|sample| sample.abs() > 0.01));
+        assert!(samples[samples.len() - 100..].iter().all(|s| *s == 0.0));
+        // Rendering is deterministic
+        assert_eq!(samples, render_steps(&steps, &options, &config));
+        assert!(render_steps(&[], &options, &config)
+            .iter()
+            .all(|s| *s == 0.0));
+
+        // Bars of 7/8 have 14 steps
+        let seven_eight = RenderOptions {
+            steps_per_bar: 14,
+            ..options
+        };
+        assert_eq!(
+            render_steps(&steps, &seven_eight, &config).len(),
+            28 * 1000 + 8000
+        );
+
+        let wav = encode_wav(&samples, options.sample_rate);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[8..12], b"WAVE");
+        assert_eq!(wav.len(), 44 + samples.len() * 2);
+    }
+}
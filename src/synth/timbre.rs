@@ -0,0 +1,160 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::state::SequencerSlot;
+
+/// File that the synth settings are saved to and loaded from, relative to the working directory.
+pub const SYNTH_FILE: &str = "synth.toml";
+
+/// Shape of the oscillator.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Waveform {
+    Sine,
+    Triangle,
+    #[default]
+    Saw,
+    Square,
+}
+
+impl Waveform {
+    pub const ALL: [Waveform; 4] = [
+        Waveform::Sine,
+        Waveform::Triangle,
+        Waveform::Saw,
+        Waveform::Square,
+    ];
+
+    /// Value of the waveform at a phase from 0 to 1.
+    pub fn sample(&self, phase: f32) -> f32 {
+        match self {
+            Waveform::Sine => (phase * std::f32::consts::TAU).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Saw => 2.0 * phase - 1.0,
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Waveform::Sine => write!(f, "Sine"),
+            Waveform::Triangle => write!(f, "Triangle"),
+            Waveform::Saw => write!(f, "Saw"),
+            Waveform::Square => write!(f, "Square"),
+        }
+    }
+}
+
+/// Envelope of a note: attack, decay and release in seconds, sustain as a level from 0 to 1.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Adsr {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl Default for Adsr {
+    fn default() -> Self {
+        Adsr {
+            attack: 0.005,
+            decay: 0.15,
+            sustain: 0.6,
+            release: 0.1,
+        }
+    }
+}
+
+/// Sound of the notes of one sequencer slot.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Timbre {
+    #[serde(default)]
+    pub waveform: Waveform,
+    #[serde(default)]
+    pub envelope: Adsr,
+    /// Volume from 0 to 1.
+    #[serde(default = "default_volume")]
+    pub volume: f32,
+}
+
+fn default_volume() -> f32 {
+    0.8
+}
+
+/// Settings of the built-in synth, with a timbre per sequencer slot.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SynthConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub left: Timbre,
+    #[serde(default = "default_right")]
+    pub right: Timbre,
+}
+
+/// The right slot defaults to a softer sound, so that both slots can be told apart.
+fn default_right() -> Timbre {
+    Timbre {
+        waveform: Waveform::Triangle,
+        envelope: Adsr {
+            attack: 0.02,
+            decay: 0.3,
+            sustain: 0.7,
+            release: 0.25,
+        },
+        volume: default_volume(),
+    }
+}
+
+impl Default for SynthConfig {
+    fn default() -> Self {
+        SynthConfig {
+            enabled: false,
+            left: Timbre {
+                volume: default_volume(),
+                ..Timbre::default()
+            },
+            right: default_right(),
+        }
+    }
+}
+
+impl SynthConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read synth settings from {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse synth settings from {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents = toml::to_string(self).context("Failed to serialize synth settings")?;
+        fs::write(path, contents)
+            .with_context(|| format!("Failed to write synth settings to {}", path.display()))
+    }
+
+    pub fn timbre(&self, slot: &SequencerSlot) -> &Timbre {
+        match slot {
+            SequencerSlot::Left => &self.left,
+            SequencerSlot::Right => &self.right,
+        }
+    }
+
+    pub fn timbre_mut(&mut self, slot: &SequencerSlot) -> &mut Timbre {
+        match slot {
+            SequencerSlot::Left => &mut self.left,
+            SequencerSlot::Right => &mut self.right,
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Slowest and fastest tempo that can be set.
+pub const MIN_BPM: f32 = 20.0;
+pub const MAX_BPM: f32 = 300.0;
+
+/// Tempo changes of the fine and coarse adjustments, in BPM.
+pub const FINE_BPM_STEP: f32 = 0.1;
+pub const COARSE_BPM_STEP: f32 = 1.0;
+
+/// How much faster or slower the transport plays while nudged, for beat-matching.
+pub const NUDGE_AMOUNT: f32 = 0.04;
+
+/// Number of taps that the tempo is averaged over.
+const MAX_TAPS: usize = 5;
+
+/// A pause between taps longer than this starts tapping a new tempo.
+const TAP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Clamp a tempo to the range that can be set. A tempo that is not a number gives the
+/// slowest tempo, as NaN would pass through the clamp.
+pub fn clamp_bpm(bpm: f32) -> f32 {
+    if bpm.is_nan() {
+        return MIN_BPM;
+    }
+    bpm.clamp(MIN_BPM, MAX_BPM)
+}
+
+/// The direction the transport is temporarily nudged in while a nudge key or button is held.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Nudge {
+    #[default]
+    None,
+    Slower,
+    Faster,
+}
+
+impl Nudge {
+    /// Factor that the tempo is played at.
+    pub fn factor(&self) -> f32 {
+        match self {
+            Nudge::None => 1.0,
+            Nudge::Slower => 1.0 - NUDGE_AMOUNT,
+            Nudge::Faster => 1.0 + NUDGE_AMOUNT,
+        }
+    }
+}
+
+/// Measures the tempo from the intervals between taps.
+#[derive(Clone, Debug, Default)]
+pub struct TapTempo {
+    taps: VecDeque<Instant>,
+}
+
+impl TapTempo {
+    /// Register a tap, returning the tempo once there are at least two taps.
+    pub fn tap(&mut self, now: Instant) -> Option<f32> {
+        if self
+            .taps
+            .back()
+            .is_some_and(|last| now.duration_since(*last) > TAP_TIMEOUT)
+        {
+            self.taps.clear();
+        }
+        self.taps.push_back(now);
+        if self.taps.len() > MAX_TAPS {
+            self.taps.pop_front();
+        }
+
+        let first = self.taps.front()?;
+        let intervals = self.taps.len() - 1;
+        let span = now.duration_since(*first).as_secs_f32();
+        (intervals > 0 && span > 0.0).then(|| clamp_bpm(60.0 * intervals as f32 / span))
+    }
+}
+
+/// A TempoRamp gradually changes the tempo over a number of bars, advanced by the transport.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TempoRamp {
+    pub from: f32,
+    pub to: f32,
+    pub total_steps: usize,
+    pub elapsed_steps: usize,
+}
+
+impl TempoRamp {
+    pub fn new(from: f32, to: f32, bars: usize, steps_per_bar: usize) -> Self {
+        TempoRamp {
+            from,
+            to: clamp_bpm(to),
+            total_steps: (bars * steps_per_bar).max(1),
+            elapsed_steps: 0,
+        }
+    }
+
+    /// Advance the ramp by one step, returning the tempo of the next step.
+    pub fn advance(&mut self) -> f32 {
+        self.elapsed_steps = (self.elapsed_steps + 1).min(self.total_steps);
+        let t = self.elapsed_steps as f32 / self.total_steps as f32;
+        self.from + (self.to - self.from) * t
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed_steps >= self.total_steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::STEPS_PER_BAR;
+
+    #[test]
+    fn test_tap_tempo() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut tap_tempo = TapTempo::default();
+
+        assert_eq!(tap_tempo.tap(at(0)), None);
+        assert_eq!(tap_tempo.tap(at(500)), Some(120.0));
+        assert_eq!(tap_tempo.tap(at(1000)), Some(120.0));
+        // Uneven taps are averaged
+        assert_eq!(tap_tempo.tap(at(1700)), Some(60.0 * 3.0 / 1.7));
+
+        // A long pause starts over
+        assert_eq!(tap_tempo.tap(at(5000)), None);
+        assert_eq!(tap_tempo.tap(at(5750)), Some(80.0));
+    }
+
+    #[test]
+    fn test_tempo_ramp() {
+        let mut ramp = TempoRamp::new(100.0, 140.0, 1, STEPS_PER_BAR);
+        assert_eq!(ramp.advance(), 102.5);
+        for _ in 1..STEPS_PER_BAR - 1 {
+            ramp.advance();
+        }
+        assert!(!ramp.is_finished());
+        assert_eq!(ramp.advance(), 140.0);
+        assert!(ramp.is_finished());
+        assert_eq!(TempoRamp::new(100.0, 1000.0, 2, STEPS_PER_BAR).to, MAX_BPM);
+    }
+
+    #[test]
+    fn test_clamp_bpm() {
+        assert_eq!(clamp_bpm(90.0), 90.0);
+        assert_eq!(clamp_bpm(f32::INFINITY), MAX_BPM);
+        assert_eq!(clamp_bpm(f32::NAN), MIN_BPM);
+    }
+}
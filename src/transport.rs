@@ -0,0 +1,133 @@
+use core::fmt;
+
+/// A time signature, e.g. 4/4 or 7/8. Every step is a sixteenth note, so the unit of a beat
+/// is a quarter, an eighth or a sixteenth note.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeSignature {
+    pub beats: u8,
+    pub unit: u8,
+}
+
+impl TimeSignature {
+    pub const ALL: [TimeSignature; 9] = [
+        TimeSignature::new(2, 4),
+        TimeSignature::new(3, 4),
+        TimeSignature::new(4, 4),
+        TimeSignature::new(5, 4),
+        TimeSignature::new(7, 4),
+        TimeSignature::new(5, 8),
+        TimeSignature::new(6, 8),
+        TimeSignature::new(7, 8),
+        TimeSignature::new(12, 8),
+    ];
+
+    pub const fn new(beats: u8, unit: u8) -> Self {
+        TimeSignature { beats, unit }
+    }
+
+    pub fn steps_per_beat(&self) -> usize {
+        (16 / self.unit.clamp(1, 16)) as usize
+    }
+
+    pub fn steps_per_bar(&self) -> usize {
+        (self.beats as usize * self.steps_per_beat()).max(1)
+    }
+
+    /// Length of a bar in quarter notes, which is the quantum of Link.
+    pub fn quarters_per_bar(&self) -> f64 {
+        self.steps_per_bar() as f64 / 4.0
+    }
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        TimeSignature::new(4, 4)
+    }
+}
+
+impl fmt::Display for TimeSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.beats, self.unit)
+    }
+}
+
+/// The position of the transport, counted from 1 like on a bar counter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub bar: usize,
+    pub beat: usize,
+    pub step: usize,
+}
+
+impl Position {
+    /// The position after a number of steps played since starting.
+    pub fn at(steps: usize, time_signature: &TimeSignature) -> Self {
+        let steps_per_bar = time_signature.steps_per_bar();
+        let in_bar = steps % steps_per_bar;
+        Position {
+            bar: steps / steps_per_bar + 1,
+            beat: in_bar / time_signature.steps_per_beat() + 1,
+            step: in_bar % time_signature.steps_per_beat() + 1,
+        }
+    }
+
+    pub fn is_bar_start(&self) -> bool {
+        self.beat == 1 && self.step == 1
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.bar, self.beat, self.step)
+    }
+}
+
+/// What the sequencers do at the start of every bar.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BarAction {
+    #[default]
+    None,
+    /// Restart the patterns from their first step, so that patterns of other lengths line up
+    /// with the bars.
+    Restart,
+    /// Regenerate the sequences, so that the random choices of the mixer change every bar.
+    Regenerate,
+}
+
+impl BarAction {
+    pub const ALL: [BarAction; 3] = [BarAction::None, BarAction::Restart, BarAction::Regenerate];
+}
+
+impl fmt::Display for BarAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BarAction::None => write!(f, "Nothing"),
+            BarAction::Restart => write!(f, "Restart"),
+            BarAction::Regenerate => write!(f, "Regenerate"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position() {
+        let four_four = TimeSignature::default();
+        assert_eq!(four_four.steps_per_bar(), 16);
+        assert_eq!(Position::at(0, &four_four).to_string(), "1.1.1");
+        assert_eq!(Position::at(21, &four_four).to_string(), "2.2.2");
+
+        // 7/8 counts eighths, two steps each
+        let seven_eight = TimeSignature::new(7, 8);
+        assert_eq!(seven_eight.steps_per_bar(), 14);
+        assert_eq!(seven_eight.quarters_per_bar(), 3.5);
+        assert_eq!(Position::at(13, &seven_eight).to_string(), "1.7.2");
+        assert!(Position::at(14, &seven_eight).is_bar_start());
+
+        let five_four = TimeSignature::new(5, 4);
+        assert_eq!(Position::at(19, &five_four).to_string(), "1.5.4");
+        assert!(Position::at(20, &five_four).is_bar_start());
+    }
+}
@@ -0,0 +1,606 @@
+pub mod websocket;
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, RwLock};
+use tokio::time::{interval, timeout, Duration, MissedTickBehavior};
+
+use crate::arrangement::{Arrangement, ARRANGEMENT_FILE};
+use crate::midi::learn::Parameter;
+use crate::scene::{SceneBank, SCENES_FILE, SCENE_SLOTS};
+use crate::state::{SequencerSlot, SharedState};
+use websocket::{accept_key, close_frame, pong_frame, read_frame, text_frame, Frame};
+
+/// File that the web server settings are loaded from, relative to the working directory.
+pub const WEB_FILE: &str = "web.toml";
+
+/// The control page, served on `/`.
+const INDEX_HTML: &str = include_str!("index.html");
+
+/// How often state changes and the playhead are sent to WebSocket clients.
+const STREAM_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Largest request body that is accepted.
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+/// Maximum number of header lines of a request.
+const MAX_HEADERS: usize = 64;
+
+/// Longest request line or header line that is accepted.
+const MAX_LINE_LENGTH: usize = 8 * 1024;
+
+/// Time a client has to send the whole request, so that slow clients can not hold on to
+/// connections.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Parameters of a sequencer, by their name in the JSON state.
+const SLOT_PARAMETERS: [(Parameter, &str); 8] = [
+    (Parameter::Steps, "steps"),
+    (Parameter::Pulses, "pulses"),
+    (Parameter::Phase, "phase"),
+    (Parameter::Pitch, "pitch"),
+    (Parameter::Mute, "mute"),
+    (Parameter::Solo, "solo"),
+    (Parameter::Volume, "volume"),
+    (Parameter::MuteNextBar, "mute_next_bar"),
+];
+
+/// Parameters of the whole sequencer, by their name in the JSON state.
+const GLOBAL_PARAMETERS: [(Parameter, &str); 4] = [
+    (Parameter::MixerRatio, "mixer_ratio"),
+    (Parameter::Bpm, "bpm"),
+    (Parameter::PlayStop, "playing"),
+    (Parameter::ActiveSlot, "active_slot"),
+];
+
+const SLOTS: [SequencerSlot; 2] = [SequencerSlot::Left, SequencerSlot::Right];
+
+/// Settings of the web server.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WebConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// TCP port the server listens on.
+    #[serde(default = "default_port")]
+    pub port: u16,
+}
+
+fn default_enabled() -> bool {
+    false
+}
+
+fn default_port() -> u16 {
+    8080
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        WebConfig {
+            enabled: default_enabled(),
+            port: default_port(),
+        }
+    }
+}
+
+impl WebConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read web settings from {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse web settings from {}", path.display()))
+    }
+}
+
+/// The state as it is read from the API and streamed over the WebSocket.
+pub fn state_json(state: &SharedState) -> Value {
+    let slots = SLOTS
+        .iter()
+        .map(|slot| {
+            let mut members = SLOT_PARAMETERS
+                .iter()
+                .map(|(parameter, name)| {
+                    let value = state.slot_parameter_value(slot, *parameter);
+                    let value = if parameter.is_toggle() {
+                        Value::Bool(value > 0.0)
+                    } else {
+                        json!(value)
+                    };
+                    (name.to_string(), value)
+                })
+                .collect::<Map<_, _>>();
+            let sequencer_state = match slot {
+                SequencerSlot::Left => &state.left_state,
+                SequencerSlot::Right => &state.right_state,
+            };
+            members.insert("beats".to_string(), json!(sequencer_state.beat_locations()));
+            Value::Object(members)
+        })
+        .collect();
+
+    let mut members = Map::new();
+    members.insert("slots".to_string(), Value::Array(slots));
+    members.extend(GLOBAL_PARAMETERS.iter().map(|(parameter, name)| {
+        let value = state.parameter_value(*parameter);
+        let value = match parameter {
+            Parameter::PlayStop => Value::Bool(value > 0.0),
+            _ => json!(value),
+        };
+        (name.to_string(), value)
+    }));
+    members.insert("playhead".to_string(), json!(state.current_note_index));
+    let scenes = (0..SCENE_SLOTS)
+        .map(|slot| state.scenes.get(slot).map(|scene| scene.name.clone()))
+        .collect::<Vec<_>>();
+    members.insert("scenes".to_string(), json!(scenes));
+    Value::Object(members)
+}
+
+/// A number of the JSON state, with true as 1.
+fn as_f32(value: &Value) -> Option<f32> {
+    match value {
+        Value::Bool(value) => Some(*value as u8 as f32),
+        value => value.as_f64().map(|value| value as f32),
+    }
+}
+
+/// Set the parameters in a JSON object, in the format of [state_json]. Members that are left
+/// out keep their value. The whole object is checked first, so that nothing is changed if
+/// any member is invalid, and the changes are undone in one go.
+pub fn apply_json(state: &mut SharedState, json: &Value) -> Result<()> {
+    let Value::Object(members) = json else {
+        bail!("Expected a JSON object");
+    };
+    let mut edits: Vec<(Option<SequencerSlot>, Parameter, f32)> = Vec::new();
+    for (name, value) in members {
+        if name == "slots" {
+            let slots = value.as_array().context("Expected an array of slots")?;
+            for (slot, values) in SLOTS.iter().zip(slots) {
+                for (parameter, name) in SLOT_PARAMETERS {
+                    if let Some(value) = values.get(name).and_then(as_f32) {
+                        edits.push((Some(slot.clone()), parameter, value));
+                    }
+                }
+            }
+            continue;
+        }
+        match GLOBAL_PARAMETERS.iter().find(|(_, global)| global == name) {
+            Some((parameter, _)) => {
+                let value =
+                    as_f32(value).with_context(|| format!("Expected a number for {name}"))?;
+                edits.push((None, *parameter, value));
+            }
+            // Read-only members are accepted, so that a state read from the API can be sent back
+            None if ["playhead", "scenes"].contains(&name.as_str()) => {}
+            None => bail!("Unknown parameter {name}"),
+        }
+    }
+
+    state.apply_edit_group(|state| {
+        for (slot, parameter, value) in edits {
+            match slot {
+                Some(slot) => state.set_slot_parameter(&slot, parameter, value),
+                None => state.set_parameter(parameter, value),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Files of a project to load, e.g. `{"scenes": "live.toml"}`. Files that are left out are
+/// loaded from the default files.
+#[derive(Debug, Default, Deserialize)]
+pub struct ProjectFiles {
+    scenes: Option<String>,
+    arrangement: Option<String>,
+}
+
+/// A file in the working directory. Paths are refused, so that clients on the network can
+/// not read other files.
+fn project_file(name: &str) -> Result<&Path> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+        bail!("{name:?} is not a file name in the working directory");
+    }
+    Ok(Path::new(name))
+}
+
+/// Load scenes and an arrangement from the files of a project.
+pub fn load_project(state: &mut SharedState, files: &ProjectFiles) -> Result<()> {
+    let scenes = SceneBank::load(project_file(
+        files.scenes.as_deref().unwrap_or(SCENES_FILE),
+    )?)?;
+    let arrangement = match &files.arrangement {
+        Some(name) => Arrangement::load(project_file(name)?)?,
+        None => Arrangement::load(Path::new(ARRANGEMENT_FILE)).unwrap_or_default(),
+    };
+    info!("Loaded project over the web API");
+    state.scenes = scenes;
+    state.arrangement = arrangement;
+    state.arrangement_position = None;
+    Ok(())
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn json(json: Value) -> Self {
+        Response {
+            status: "200 OK",
+            content_type: "application/json",
+            body: json.to_string(),
+        }
+    }
+
+    fn error(status: &'static str, message: impl fmt::Display) -> Self {
+        let error = json!({ "error": message.to_string() });
+        Response {
+            status,
+            content_type: "application/json",
+            body: error.to_string(),
+        }
+    }
+}
+
+/// Read a line of the request head into `line`, refusing lines that are too long.
+async fn read_line(reader: &mut (impl AsyncBufRead + Unpin), line: &mut String) -> Result<()> {
+    line.clear();
+    let length = reader.take(MAX_LINE_LENGTH as u64).read_line(line).await?;
+    if length == MAX_LINE_LENGTH && !line.ends_with('\n') {
+        bail!("HTTP request line longer than {MAX_LINE_LENGTH} bytes");
+    }
+    Ok(())
+}
+
+async fn read_request(reader: &mut (impl AsyncBufRead + Unpin)) -> Result<Request> {
+    let mut line = String::new();
+    read_line(reader, &mut line).await?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        bail!("Invalid HTTP request line {line:?}");
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+
+    let mut headers = Vec::new();
+    loop {
+        read_line(reader, &mut line).await?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if headers.len() >= MAX_HEADERS {
+            bail!("Too many HTTP headers");
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    let mut request = Request {
+        method,
+        path,
+        headers,
+        body: String::new(),
+    };
+    let length = request
+        .header("Content-Length")
+        .and_then(|length| length.parse::<usize>().ok())
+        .unwrap_or(0);
+    if length > MAX_BODY_SIZE {
+        bail!("HTTP request body of {length} bytes is too large");
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+    request.body = String::from_utf8(body).context("HTTP request body is not UTF-8")?;
+    Ok(request)
+}
+
+/// Answer a request of the REST API or for the control page.
+async fn route(request: &Request, shared_state: &RwLock<SharedState>) -> Response {
+    let path = request.path.split('?').next().unwrap_or_default();
+    let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+    fn body<T: DeserializeOwned>(request: &Request) -> Result<T> {
+        let body = if request.body.is_empty() {
+            "{}"
+        } else {
+            &request.body
+        };
+        serde_json::from_str(body).context("Invalid JSON")
+    }
+
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", [""]) => Response {
+            status: "200 OK",
+            content_type: "text/html; charset=utf-8",
+            body: INDEX_HTML.to_string(),
+        },
+        ("GET", ["api", "state"]) => Response::json(state_json(&*shared_state.read().await)),
+        ("PUT" | "POST", ["api", "state"]) => {
+            let mut w_state = shared_state.write().await;
+            match body(request).and_then(|json| apply_json(&mut w_state, &json)) {
+                Ok(()) => Response::json(state_json(&w_state)),
+                Err(e) => Response::error("400 Bad Request", format!("{e:#}")),
+            }
+        }
+        ("POST", ["api", "scenes", number, "recall"]) => match number.parse::<usize>() {
+            Ok(number) if (1..=SCENE_SLOTS).contains(&number) => {
+                let mut w_state = shared_state.write().await;
+                w_state.recall_scene(number - 1);
+                Response::json(state_json(&w_state))
+            }
+            _ => Response::error("404 Not Found", format!("No scene {number}")),
+        },
+        ("POST", ["api", "project", "load"]) => {
+            let mut w_state = shared_state.write().await;
+            match body(request).and_then(|files| load_project(&mut w_state, &files)) {
+                Ok(()) => Response::json(state_json(&w_state)),
+                Err(e) => {
+                    // The details can quote the files, so they are only logged
+                    warn!("Failed to load project over the web API: {e:#}");
+                    Response::error("400 Bad Request", "Failed to load project")
+                }
+            }
+        }
+        (_, ["api", "state"]) | (_, [""]) => {
+            Response::error("405 Method Not Allowed", "Method not allowed")
+        }
+        _ => Response::error("404 Not Found", format!("Not found: {path}")),
+    }
+}
+
+async fn write_response(writer: &mut OwnedWriteHalf, response: Response) -> Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    writer.write_all(head.as_bytes()).await?;
+    writer.write_all(response.body.as_bytes()).await?;
+    Ok(())
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    shared_state: Arc<RwLock<SharedState>>,
+) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    // The same buffered reader is used for WebSocket frames, which may follow the request
+    let mut reader = BufReader::new(reader);
+    let request = timeout(READ_TIMEOUT, read_request(&mut reader))
+        .await
+        .context("Timed out reading the HTTP request")??;
+
+    let upgrade = request
+        .header("Upgrade")
+        .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"));
+    match request.header("Sec-WebSocket-Key") {
+        Some(key) if upgrade && request.path == "/ws" => {
+            let head = format!(
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept_key(key)
+            );
+            writer.write_all(head.as_bytes()).await?;
+            stream_state(reader, writer, shared_state).await
+        }
+        _ => {
+            let response = route(&request, &shared_state).await;
+            write_response(&mut writer, response).await
+        }
+    }
+}
+
+/// Send the state to a WebSocket client whenever it changes, and apply the JSON states that
+/// the client sends.
+async fn stream_state(
+    mut reader: impl AsyncRead + Unpin + Send + 'static,
+    mut writer: OwnedWriteHalf,
+    shared_state: Arc<RwLock<SharedState>>,
+) -> Result<()> {
+    // Frames are read in their own task, as reading a frame can not be cancelled halfway
+    let (tx_frames, mut rx_frames) = mpsc::channel(16);
+    let reader_task = tokio::spawn(async move {
+        while let Ok(frame) = read_frame(&mut reader).await {
+            if tx_frames.send(frame).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut ticks = interval(STREAM_INTERVAL);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut sent = String::new();
+
+    let result = loop {
+        tokio::select! {
+            frame = rx_frames.recv() => match frame.unwrap_or(Frame::Close) {
+                Frame::Text(text) => {
+                    let mut w_state = shared_state.write().await;
+                    if let Err(e) = serde_json::from_str(&text)
+                        .context("Invalid JSON")
+                        .and_then(|json| apply_json(&mut w_state, &json)) {
+                        warn!("Invalid state from WebSocket client: {e:#}");
+                    }
+                }
+                Frame::Ping(payload) => {
+                    if let Err(e) = writer.write_all(&pong_frame(&payload)).await {
+                        break Err(e.into());
+                    }
+                }
+                Frame::Close => break writer.write_all(&close_frame()).await.map_err(Into::into),
+                Frame::Other => {}
+            },
+            _ = ticks.tick() => {
+                let state = state_json(&*shared_state.read().await).to_string();
+                if state != sent {
+                    if let Err(e) = writer.write_all(&text_frame(&state)).await {
+                        break Err(e.into());
+                    }
+                    sent = state;
+                }
+            }
+        }
+    };
+    reader_task.abort();
+    result
+}
+
+/// Serves the control page, the REST API under `/api` and the WebSocket state stream on
+/// `/ws`.
+pub struct WebServer {
+    listener: TcpListener,
+    shared_state: Arc<RwLock<SharedState>>,
+}
+
+impl WebServer {
+    pub async fn bind(config: &WebConfig, shared_state: Arc<RwLock<SharedState>>) -> Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", config.port))
+            .await
+            .with_context(|| format!("Failed to listen for HTTP on port {}", config.port))?;
+        info!(
+            "Serving the control page on http://0.0.0.0:{}/",
+            config.port
+        );
+        Ok(WebServer {
+            listener,
+            shared_state,
+        })
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        loop {
+            let (stream, address) = self.listener.accept().await?;
+            let shared_state = self.shared_state.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle_connection(stream, shared_state).await {
+                    info!("Web connection from {address} closed: {e:#}");
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sequencers::euclidean::state::EuclideanSequencerState;
+
+    #[tokio::test]
+    async fn test_api() {
+        let shared_state = RwLock::new(SharedState::new(120.0));
+        let request = |method: &str, path: &str, body: &str| Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers: Vec::new(),
+            body: body.to_string(),
+        };
+
+        let response = route(
+            &request(
+                "PUT",
+                "/api/state",
+                r#"{"bpm": 100, "slots": [{}, {"pulses": 4}]}"#,
+            ),
+            &shared_state,
+        )
+        .await;
+        assert_eq!(response.status, "200 OK");
+        let json = serde_json::from_str::<Value>(&response.body).unwrap();
+        assert_eq!(json["bpm"], json!(100.0));
+        assert_eq!(json["slots"][1]["beats"], json!([0, 4, 8, 12]));
+
+        // The state read from the API can be written back unchanged
+        let response = route(&request("GET", "/api/state", ""), &shared_state).await;
+        let response = route(&request("PUT", "/api/state", &response.body), &shared_state).await;
+        assert_eq!(response.status, "200 OK");
+        assert_eq!(shared_state.read().await.right_state.pulses, 4);
+
+        let response = route(
+            &request("PUT", "/api/state", r#"{"tempo": 1}"#),
+            &shared_state,
+        )
+        .await;
+        assert_eq!(response.status, "400 Bad Request");
+
+        // Nothing is changed when a member is invalid
+        let response = route(
+            &request("PUT", "/api/state", r#"{"bpm": 140, "tempo": 1}"#),
+            &shared_state,
+        )
+        .await;
+        assert_eq!(response.status, "400 Bad Request");
+        assert_eq!(shared_state.read().await.bpm, 100.0);
+
+        // The changes of requests, grouped like those of a dragged slider, are undone together
+        let body = r#"{"bpm": 140, "slots": [{"steps": 8}]}"#;
+        route(&request("PUT", "/api/state", body), &shared_state).await;
+        let mut w_state = shared_state.write().await;
+        assert_eq!((w_state.bpm, w_state.left_state.steps), (140.0, 8));
+        w_state.undo();
+        assert_eq!((w_state.bpm, w_state.left_state.steps), (120.0, 16));
+        assert_eq!(
+            w_state.right_state.pulses,
+            EuclideanSequencerState::new().pulses
+        );
+        assert!(!w_state.history.can_undo());
+        w_state.redo();
+        drop(w_state);
+
+        let response = route(&request("POST", "/api/scenes/9/recall", ""), &shared_state).await;
+        assert_eq!(response.status, "404 Not Found");
+        let response = route(&request("GET", "/", ""), &shared_state).await;
+        assert!(response.body.contains("<html"));
+
+        // Deeply nested JSON is refused instead of overflowing the stack
+        let nested = "[".repeat(MAX_BODY_SIZE);
+        let response = route(&request("PUT", "/api/state", &nested), &shared_state).await;
+        assert_eq!(response.status, "400 Bad Request");
+
+        // Only files in the working directory can be loaded, and their contents are not shown
+        for path in ["/etc/passwd", "../scenes.toml", "..\\scenes.toml"] {
+            let body = json!({ "scenes": path }).to_string();
+            let response = route(&request("POST", "/api/project/load", &body), &shared_state).await;
+            assert_eq!(response.status, "400 Bad Request");
+            assert!(!response.body.contains(path));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_request() {
+        let head = "GET /api/state HTTP/1.1\r\nHost: pi\r\n\r\n";
+        let request = read_request(&mut head.as_bytes()).await.unwrap();
+        assert_eq!(request.path, "/api/state");
+        assert_eq!(request.header("host"), Some("pi"));
+
+        let long = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE_LENGTH));
+        assert!(read_request(&mut long.as_bytes()).await.is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use anyhow::{bail, Result};
+use sha1::{Digest, Sha1};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Appended to the key of the client to compute the accept key, see RFC 6455.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Largest frame accepted from a client.
+const MAX_FRAME_SIZE: u64 = 64 * 1024;
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// A message received from a client.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Frame {
+    Text(String),
+    Ping(Vec<u8>),
+    Close,
+    /// Binary messages, pongs and continuations, which are ignored.
+    Other,
+}
+
+/// Value of the `Sec-WebSocket-Accept` header for the `Sec-WebSocket-Key` of a client.
+pub fn accept_key(key: &str) -> String {
+    let digest = Sha1::digest(format!("{}{}", key.trim(), WEBSOCKET_GUID));
+    base64(&digest)
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Encode a message from the server, which is never masked.
+fn encode(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+pub fn text_frame(text: &str) -> Vec<u8> {
+    encode(OPCODE_TEXT, text.as_bytes())
+}
+
+pub fn pong_frame(payload: &[u8]) -> Vec<u8> {
+    encode(OPCODE_PONG, payload)
+}
+
+pub fn close_frame() -> Vec<u8> {
+    encode(OPCODE_CLOSE, &[])
+}
+
+/// Read the next frame from a client, unmasking its payload.
+pub async fn read_frame(reader: &mut (impl AsyncRead + Unpin)) -> Result<Frame> {
+    let mut head = [0; 2];
+    reader.read_exact(&mut head).await?;
+    let opcode = head[0] & 0x0F;
+    let masked = head[1] & 0x80 != 0;
+    let len = match head[1] & 0x7F {
+        126 => reader.read_u16().await? as u64,
+        127 => reader.read_u64().await?,
+        len => len as u64,
+    };
+    if len > MAX_FRAME_SIZE {
+        bail!("WebSocket frame of {len} bytes is too large");
+    }
+    let mut mask = [0; 4];
+    if masked {
+        reader.read_exact(&mut mask).await?;
+    }
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload).await?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+
+    Ok(match opcode {
+        OPCODE_TEXT => Frame::Text(String::from_utf8_lossy(&payload).into_owned()),
+        OPCODE_PING => Frame::Ping(payload),
+        OPCODE_CLOSE => Frame::Close,
+        _ => Frame::Other,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_handshake_and_frames() {
+        // Example from RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(base64(b"ab"), "YWI=");
+
+        // A masked text frame from a client
+        let mask = [1, 2, 3, 4];
+        let mut frame = vec![0x81, 0x80 | 5];
+        frame.extend_from_slice(&mask);
+        frame.extend(b"hello".iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        assert_eq!(
+            read_frame(&mut frame.as_slice()).await.unwrap(),
+            Frame::Text("hello".to_string())
+        );
+
+        let text = "x".repeat(300);
+        let frame = text_frame(&text);
+        assert_eq!(&frame[..4], &[0x81, 126, 1, 44]);
+        assert_eq!(
+            read_frame(&mut frame.as_slice()).await.unwrap(),
+            Frame::Text(text)
+        );
+        assert_eq!(
+            read_frame(&mut close_frame().as_slice()).await.unwrap(),
+            Frame::Close
+        );
+    }
+}